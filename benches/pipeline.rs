@@ -0,0 +1,135 @@
+//! 音频与识别流水线的基准测试
+//!
+//! 运行：`cargo bench --bench pipeline`。识别基准需要设置 `BENCH_MODEL`（建议
+//! ggml-tiny）和 `BENCH_WAV`（16kHz 单声道 16 位 WAV），未设置时跳过。
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use opus::{Application, Channels, Encoder};
+
+use heart_mirror_brain::audio::{calculate_rms, AudioOptions, AudioProcessor};
+use heart_mirror_brain::speech::{Recognizer, RecognizerOptions, SpeechRecognizer};
+
+const SAMPLE_RATE: usize = 16000;
+/// 20ms 帧
+const FRAME_SAMPLES: usize = 320;
+
+/// 1 秒 220Hz 正弦"语音"后接 300ms 静音，足以触发一次完整的 VAD 录音
+fn synthetic_utterance() -> Vec<i16> {
+    let speech = (0..SAMPLE_RATE).map(|i| {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        ((t * 220.0 * std::f32::consts::TAU).sin() * 8000.0) as i16
+    });
+    let silence = std::iter::repeat_n(0, SAMPLE_RATE * 3 / 10);
+    speech.chain(silence).collect()
+}
+
+fn audio_options(decode_float: bool) -> AudioOptions {
+    AudioOptions {
+        max_total_buffer_bytes: usize::MAX,
+        decode_float,
+        detect_channels: false,
+        limiter: None,
+        pre_emphasis: None,
+        cooldown_samples: 0,
+        window_samples: 0,
+        window_overlap_samples: 0,
+        sustain_threshold: 800.0,
+        max_unsustained_samples: 0,
+        energy_window_samples: 0,
+        vad_frame_samples: 0,
+    }
+}
+
+fn bench_rms(c: &mut Criterion) {
+    let frame: Vec<f32> = synthetic_utterance()[..FRAME_SAMPLES]
+        .iter()
+        .map(|&s| s as f32 / 32768.0)
+        .collect();
+
+    c.bench_function("calculate_rms/20ms", |b| {
+        b.iter(|| calculate_rms(black_box(&frame)))
+    });
+}
+
+fn bench_vad(c: &mut Criterion) {
+    let pcm = synthetic_utterance();
+
+    let mut group = c.benchmark_group("vad");
+    group.throughput(Throughput::Elements(pcm.len() as u64));
+    group.bench_function("utterance_1s", |b| {
+        b.iter_batched(
+            || AudioProcessor::new(audio_options(false)).unwrap(),
+            |mut processor| {
+                for frame in pcm.chunks(FRAME_SAMPLES) {
+                    black_box(processor.process_pcm(frame));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_opus_decode(c: &mut Criterion) {
+    let mut encoder = Encoder::new(SAMPLE_RATE as u32, Channels::Mono, Application::Voip).unwrap();
+    let packets: Vec<Vec<u8>> = synthetic_utterance()
+        .chunks_exact(FRAME_SAMPLES)
+        .map(|frame| encoder.encode_vec(frame, 4000).unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("opus_decode");
+    group.throughput(Throughput::Elements(packets.len() as u64));
+    for (name, decode_float) in [("i16", false), ("float", true)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || AudioProcessor::new(audio_options(decode_float)).unwrap(),
+                |mut processor| {
+                    for packet in &packets {
+                        black_box(processor.process_audio(packet));
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_recognize(c: &mut Criterion) {
+    let (Ok(model), Ok(wav)) = (std::env::var("BENCH_MODEL"), std::env::var("BENCH_WAV")) else {
+        eprintln!("未设置 BENCH_MODEL/BENCH_WAV，跳过识别基准");
+        return;
+    };
+
+    let options = RecognizerOptions {
+        normalize: true,
+        temperature: 0.0,
+        no_speech_thold: 0.6,
+        suppress_blank: true,
+        token_timestamps: false,
+        suppress_tokens: Vec::new(),
+        min_audio_samples: 0,
+    };
+    let recognizer = SpeechRecognizer::try_load(&model, "zh", options).expect("模型加载失败");
+    let audio: Vec<f32> = hound::WavReader::open(&wav)
+        .expect("无法读取 WAV")
+        .into_samples::<i16>()
+        .map(|s| s.expect("WAV 样本损坏") as f32 / 32768.0)
+        .collect();
+
+    let mut group = c.benchmark_group("recognize");
+    group.sample_size(10);
+    group.bench_function("wav", |b| {
+        b.iter(|| recognizer.recognize(black_box(&audio), None))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_rms,
+    bench_vad,
+    bench_opus_decode,
+    bench_recognize
+);
+criterion_main!(benches);
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+const SAMPLE_RATE: usize = 16000;
+/// 分析帧长（40ms）
+const FRAME_LEN: usize = 640;
+/// 音高搜索范围 60Hz–400Hz 对应的自相关延迟
+const MIN_PITCH_LAG: usize = SAMPLE_RATE / 400;
+const MAX_PITCH_LAG: usize = SAMPLE_RATE / 60;
+/// 只对 RMS 高于该值（i16 幅度）的帧估计音高
+const VOICED_RMS: f32 = 500.0;
+/// 归一化自相关高于该值才认为是有声帧
+const MIN_PITCH_CORRELATION: f32 = 0.3;
+/// 每隔几帧估计一次音高，限制长语句的计算量
+const PITCH_FRAME_STRIDE: usize = 4;
+
+/// 单句语音的粗略声学特征，用于客户端按说话人启发式分组
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AcousticFingerprint {
+    /// 整句 RMS 能量（i16 幅度）
+    pub rms: f32,
+    /// 最响 40ms 帧的 RMS 能量（i16 幅度）
+    pub max_energy: f32,
+    /// 过零率（每样本）
+    pub zcr: f32,
+    /// 有声帧音高的中位数，无法估计时为空
+    pub pitch_hz: Option<f32>,
+}
+
+/// 计算 16kHz 单声道浮点样本的声学特征
+pub fn fingerprint(samples: &[f32]) -> AcousticFingerprint {
+    let frames: Vec<&[f32]> = samples.chunks_exact(FRAME_LEN).collect();
+
+    let mut pitches: Vec<f32> = frames
+        .iter()
+        .step_by(PITCH_FRAME_STRIDE)
+        .filter(|frame| rms(frame) > VOICED_RMS)
+        .filter_map(|frame| estimate_pitch(frame))
+        .collect();
+    pitches.sort_by(f32::total_cmp);
+
+    AcousticFingerprint {
+        rms: rms(samples),
+        max_energy: frames.iter().map(|frame| rms(frame)).fold(0.0, f32::max),
+        zcr: zero_crossing_rate(samples),
+        pitch_hz: pitches.get(pitches.len() / 2).copied(),
+    }
+}
+
+/// RMS 能量，按 i16 幅度计算以便与 VAD 阈值对照
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = samples.iter().map(|&s| (s * 32768.0).powi(2)).sum();
+    (sum / samples.len() as f32).sqrt()
+}
+
+fn zero_crossing_rate(samples: &[f32]) -> f32 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    crossings as f32 / (samples.len() - 1) as f32
+}
+
+/// 用归一化自相关估计一帧的基频
+fn estimate_pitch(frame: &[f32]) -> Option<f32> {
+    let energy: f32 = frame.iter().map(|s| s * s).sum();
+    if energy == 0.0 {
+        return None;
+    }
+
+    let (best_lag, best_correlation) = (MIN_PITCH_LAG..=MAX_PITCH_LAG.min(frame.len() - 1))
+        .map(|lag| {
+            let correlation: f32 = frame[..frame.len() - lag]
+                .iter()
+                .zip(&frame[lag..])
+                .map(|(a, b)| a * b)
+                .sum();
+            (lag, correlation / energy)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    (best_correlation > MIN_PITCH_CORRELATION).then(|| SAMPLE_RATE as f32 / best_lag as f32)
+}
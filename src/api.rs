@@ -0,0 +1,264 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tracing::{error, warn};
+
+use crate::emotion::BreakerStatus;
+use crate::metrics::METRICS;
+use crate::monitor::{self, TokenError};
+use crate::reanalyze;
+use crate::reload::{self, ReloadError, ReloadReport};
+use crate::state::AppState;
+use crate::storage::HistoryRecord;
+
+/// 流式返回语音片段时每块的大小
+const AUDIO_CHUNK_BYTES: usize = 64 * 1024;
+
+/// 接口错误响应
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    error: String,
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ApiError>)>;
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> (StatusCode, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: message.into(),
+        }),
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    status: &'static str,
+    ollama: BreakerStatus,
+}
+
+/// 健康检查，附带 Ollama 熔断器状态
+pub async fn health(State(state): State<AppState>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        ollama: state.emotion_analyzer.breaker_status(),
+    })
+}
+
+/// Prometheus 格式的运行指标
+pub async fn metrics() -> String {
+    METRICS.render()
+}
+
+/// 重新读取配置并应用可热更新的设置，返回生效和需要重启的设置
+pub async fn reload_config(State(state): State<AppState>) -> ApiResult<ReloadReport> {
+    reload::reload(&state).map(Json).map_err(|e| {
+        error!("重新加载配置失败: {}", e);
+        let status = match e {
+            ReloadError::File(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ReloadError::Invalid => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        api_error(status, e.to_string())
+    })
+}
+
+/// 持久化关闭时历史相关接口统一返回的错误
+fn persistence_disabled() -> (StatusCode, Json<ApiError>) {
+    api_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "历史记录持久化已禁用 (PERSIST_HISTORY=false)",
+    )
+}
+
+/// 按 ID 查询单条历史记录
+pub async fn history_record(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> ApiResult<HistoryRecord> {
+    if !state.config().persist_history {
+        return Err(persistence_disabled());
+    }
+
+    find_record(&state, id).await.map(Json)
+}
+
+/// 查询历史记录，不存在或查询失败时转为接口错误
+async fn find_record(
+    state: &AppState,
+    id: i64,
+) -> Result<HistoryRecord, (StatusCode, Json<ApiError>)> {
+    match state.history.get_speech_result(id).await {
+        Ok(Some(record)) => Ok(record),
+        Ok(None) => Err(api_error(
+            StatusCode::NOT_FOUND,
+            format!("记录 {} 不存在", id),
+        )),
+        Err(e) => {
+            error!("查询历史记录失败: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "数据库查询失败",
+            ))
+        }
+    }
+}
+
+/// 返回单条历史记录的语音片段（WAV）
+pub async fn history_audio(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Response, (StatusCode, Json<ApiError>)> {
+    if !state.config().persist_history {
+        return Err(persistence_disabled());
+    }
+
+    let Some(path) = find_record(&state, id).await?.result.audio_path else {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            format!("记录 {} 没有保存语音片段", id),
+        ));
+    };
+
+    match tokio::fs::File::open(&path).await {
+        Ok(file) => {
+            let stream = futures::stream::unfold(file, |mut file| async move {
+                let mut chunk = vec![0u8; AUDIO_CHUNK_BYTES];
+                match file.read(&mut chunk).await {
+                    Ok(0) => None,
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        Some((Ok::<_, std::io::Error>(chunk), file))
+                    }
+                    Err(e) => Some((Err(e), file)),
+                }
+            });
+            Ok((
+                [(header::CONTENT_TYPE, "audio/wav")],
+                Body::from_stream(stream),
+            )
+                .into_response())
+        }
+        Err(e) => {
+            warn!("读取语音片段 {} 失败: {}", path, e);
+            Err(api_error(StatusCode::NOT_FOUND, "语音片段文件不存在"))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReanalyzeParams {
+    /// 只处理该时间（RFC 3339）之后的记录，缺省时处理全部记录
+    since: Option<String>,
+    /// `MONITOR_TOKEN`，也可通过 `Authorization: Bearer` 携带
+    token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReanalyzeResponse {
+    queued: usize,
+    model: String,
+}
+
+/// 用当前 Ollama 模型在后台重新分析历史记录的情绪，需携带 `MONITOR_TOKEN`
+pub async fn reanalyze(
+    State(state): State<AppState>,
+    Query(params): Query<ReanalyzeParams>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<ReanalyzeResponse>), (StatusCode, Json<ApiError>)> {
+    let config = state.config();
+    match monitor::verify_token(
+        config.monitor_token.as_deref(),
+        params.token.as_deref(),
+        &headers,
+    ) {
+        Ok(()) => {}
+        Err(TokenError::Disabled) => {
+            return Err(api_error(
+                StatusCode::NOT_FOUND,
+                "管理接口未启用 (MONITOR_TOKEN 未设置)",
+            ));
+        }
+        Err(TokenError::Invalid) => {
+            warn!("重新分析接口鉴权失败");
+            return Err(api_error(StatusCode::UNAUTHORIZED, "无效的管理令牌"));
+        }
+    }
+    if !config.persist_history {
+        return Err(persistence_disabled());
+    }
+
+    let since_ms = match params.since.as_deref() {
+        Some(raw) => match parse_since(raw) {
+            Ok(since_ms) => Some(since_ms),
+            Err(e) => {
+                return Err(api_error(
+                    StatusCode::BAD_REQUEST,
+                    format!("since 不是合法的 RFC 3339 时间: {}", e),
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let Some(guard) = state.reanalyze_job.try_start() else {
+        return Err(api_error(StatusCode::CONFLICT, "已有重新分析任务在运行"));
+    };
+
+    let queued = match state.history.count_speech_results_since(since_ms).await {
+        Ok(queued) => queued,
+        Err(e) => {
+            error!("查询历史记录失败: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "数据库查询失败",
+            ));
+        }
+    };
+
+    let response = ReanalyzeResponse {
+        queued,
+        model: state.emotion_analyzer.model_name(),
+    };
+    tokio::spawn(reanalyze::run(state, since_ms, guard));
+
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// 解析 `since` 参数为 Unix 毫秒
+///
+/// 未编码的 `+08:00` 在查询串中会被解码成空格，时区偏移前的空格按 `+` 处理。
+fn parse_since(raw: &str) -> Result<i64, chrono::ParseError> {
+    let time = DateTime::parse_from_rfc3339(raw).or_else(|e| match raw.rsplit_once(' ') {
+        Some((time, offset)) if offset.len() == 5 && offset.as_bytes()[2] == b':' => {
+            DateTime::parse_from_rfc3339(&format!("{}+{}", time, offset))
+        }
+        _ => Err(e),
+    })?;
+    Ok(time.timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_accepts_offset_with_decoded_plus() {
+        let expected = parse_since("2024-01-15T00:00:00+08:00").unwrap();
+
+        assert_eq!(parse_since("2024-01-15T00:00:00 08:00").unwrap(), expected);
+        assert_eq!(
+            parse_since("2024-01-15T00:00:00-08:00").unwrap(),
+            expected + 16 * 3600 * 1000
+        );
+        assert_eq!(parse_since("2024-01-14T16:00:00Z").unwrap(), expected);
+        assert!(parse_since("2024-01-15T00:00:00 0800").is_err());
+        assert!(parse_since("yesterday").is_err());
+    }
+}
@@ -1,62 +1,584 @@
 use opus::{Channels, Decoder};
-use tracing::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::logging;
+use crate::metrics::METRICS;
+use crate::vad::{
+    self, EndReason, EnergyVad, SileroModel, VadDecision, VoiceActivityDetector, VAD_THRESHOLD_END,
+};
+
+/// 音频处理的可配置项
+#[derive(Debug, Clone)]
+pub struct AudioOptions {
+    /// 所有连接合计的音频缓冲上限（字节）
+    pub max_total_buffer_bytes: usize,
+    /// 使用 `decode_float` 直接解码为浮点样本，省去 i16 中转
+    pub decode_float: bool,
+    /// 按包的 TOC 字节检测声道数，变化时重建解码器
+    pub detect_channels: bool,
+    /// 解码增益之后的软限幅，`None` 时不处理
+    pub limiter: Option<Limiter>,
+    /// 切分完成的语音片段做预加重的系数，`None` 时不处理
+    pub pre_emphasis: Option<f32>,
+    /// 一句语音结束后忽略的样本数，按样本计时以兼容非实时输入
+    pub cooldown_samples: usize,
+    /// 固定窗口模式的窗口样本数，`0` 时使用 VAD 切分
+    pub window_samples: usize,
+    /// 相邻窗口重叠的样本数
+    pub window_overlap_samples: usize,
+    /// 录音期间需周期性达到的能量阈值
+    pub sustain_threshold: f32,
+    /// 能量持续低于 `sustain_threshold` 的样本数上限，`0` 时不限制
+    pub max_unsustained_samples: usize,
+    /// 能量检测计算 RMS 的样本数，`0` 时按客户端帧计算
+    pub energy_window_samples: usize,
+    /// 解码后的样本攒满该数量再送入 VAD，`0` 时每个客户端帧直接送入
+    pub vad_frame_samples: usize,
+}
+
+impl AudioOptions {
+    pub fn from_config(config: &Config) -> Self {
+        let window_samples = config.chunk_window.map_or(0, duration_to_samples);
+        Self {
+            max_total_buffer_bytes: config.max_total_audio_bytes,
+            decode_float: config.decode_float,
+            detect_channels: config.opus_detect_channels,
+            limiter: (config.limiter_threshold > 0.0).then(|| Limiter {
+                threshold: config.limiter_threshold.min(1.0),
+                ratio: config.limiter_ratio.max(1.0),
+            }),
+            pre_emphasis: config
+                .pre_emphasis
+                .then_some(config.pre_emphasis_coeff.clamp(0.0, 1.0)),
+            cooldown_samples: duration_to_samples(config.vad_cooldown),
+            window_samples,
+            window_overlap_samples: duration_to_samples(config.chunk_overlap)
+                .min(window_samples / 2),
+            sustain_threshold: config.vad_sustain_threshold,
+            max_unsustained_samples: config.vad_max_unsustained.map_or(0, duration_to_samples),
+            energy_window_samples: duration_to_samples(config.vad_energy_window),
+            vad_frame_samples: duration_to_samples(config.vad_frame),
+        }
+    }
+}
+
+/// 软限幅：超过拐点的部分按比例压缩，结果不超过阈值
+///
+/// 解码增益放大音量偏低的输入时，瞬态峰值可能超出满幅而削波失真；
+/// 先压缩再截断比直接截断保留更多波形。
+#[derive(Debug, Clone, Copy)]
+pub struct Limiter {
+    /// 输出幅度上限，满幅为 1.0
+    pub threshold: f32,
+    /// 超出拐点部分的压缩比，`1.0` 时只截断到阈值
+    pub ratio: f32,
+}
+
+impl Limiter {
+    /// 开始压缩的幅度：超出部分按 `ratio` 压缩后，满幅输入恰好落在 `threshold`
+    fn knee(&self) -> f32 {
+        if self.ratio <= 1.0 {
+            return self.threshold;
+        }
+        ((self.threshold * self.ratio - 1.0) / (self.ratio - 1.0)).max(0.0)
+    }
+
+    /// 返回处理后的样本和被限幅的样本数，超出满幅的输入截断到阈值
+    pub fn apply(&self, samples: &[f32]) -> (Vec<f32>, usize) {
+        let knee = self.knee();
+        let mut limited = 0;
+        let output = samples
+            .iter()
+            .map(|&sample| {
+                let magnitude = sample.abs();
+                if magnitude <= knee {
+                    return sample;
+                }
+                limited += 1;
+                let compressed = knee + (magnitude - knee) / self.ratio;
+                compressed.min(self.threshold).copysign(sample)
+            })
+            .collect();
+        (output, limited)
+    }
+}
+
+/// 预加重：`y[n] = x[n] - coeff * x[n-1]`，提升高频以补偿麦克风的高频衰减
+fn pre_emphasize(samples: &mut [f32], coeff: f32) {
+    for i in (1..samples.len()).rev() {
+        samples[i] -= coeff * samples[i - 1];
+    }
+}
+
+/// VAD 切分出的一段完整语音
+#[derive(Debug, Clone)]
+pub struct Utterance {
+    /// 原始录音，保存的语音片段和声学特征使用这份样本
+    pub samples: Vec<f32>,
+    /// 识别前做预加重的系数，`None` 时不处理
+    pub pre_emphasis: Option<f32>,
+    pub vad: VadMetadata,
+    /// 切分完成的时间，用于判断排队等待识别的时长
+    pub finalized_at: Instant,
+    /// 片段开头相对输入开头的音频时长（毫秒），按样本数计算
+    pub offset_ms: u64,
+}
+
+impl Utterance {
+    /// 送入识别的样本：开启预加重时为处理后的副本，`samples` 保持不变
+    pub fn recognition_samples(&self) -> Vec<f32> {
+        let mut samples = self.samples.clone();
+        if let Some(coeff) = self.pre_emphasis {
+            pre_emphasize(&mut samples, coeff);
+        }
+        samples
+    }
+
+    /// 片段的音频时长（毫秒）
+    pub fn duration_ms(&self) -> u64 {
+        (self.samples.len() * 1000 / SAMPLE_RATE) as u64
+    }
+
+    /// 本片段结束与 `next` 开始之间的停顿（毫秒）
+    pub fn gap_to(&self, next: &Utterance) -> u64 {
+        next.offset_ms
+            .saturating_sub(self.offset_ms + self.duration_ms())
+    }
+
+    /// 把 `next` 接在本片段之后，合并后不超过全局缓冲长度时返回
+    ///
+    /// 两者之间的停顿不保留，端点检测信息和完成时间取 `next` 的。
+    pub fn merged_with(&self, next: &Utterance) -> Option<Utterance> {
+        if self.samples.len() + next.samples.len() > MAX_BUFFER_SIZE {
+            return None;
+        }
+        let mut samples = Vec::with_capacity(self.samples.len() + next.samples.len());
+        samples.extend_from_slice(&self.samples);
+        samples.extend_from_slice(&next.samples);
+        Some(Utterance {
+            samples,
+            pre_emphasis: next.pre_emphasis,
+            vad: next.vad,
+            finalized_at: next.finalized_at,
+            offset_ms: self.offset_ms,
+        })
+    }
+}
+
+/// 语音片段的端点检测信息，用于排查切分问题
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VadMetadata {
+    /// 能量不低于结束阈值的输入帧数
+    pub voiced_frames: u32,
+    /// 能量低于结束阈值的输入帧数
+    pub silent_frames: u32,
+    /// 片段内最大帧能量（i16 幅度）
+    pub max_energy: f32,
+    /// 片段结束的原因
+    pub end: EndReason,
+}
+
+/// 一段时间内输入音频的电平，单位为 i16 幅度，与 VAD 阈值一致
+#[derive(Debug, Clone, Copy)]
+pub struct AudioLevel {
+    /// 全部输入样本的 RMS
+    pub rms: f32,
+    /// 最安静的一个输入帧的 RMS，近似环境噪声
+    pub noise_floor: f32,
+}
+
+/// 上次读取电平以来的累计值
+#[derive(Debug, Default)]
+struct LevelStats {
+    sum_squares: f64,
+    samples: usize,
+    min_frame_rms: Option<f32>,
+}
 
 pub struct AudioProcessor {
+    options: AudioOptions,
     decoder: Decoder,
+    /// 解码器当前的声道数
+    channels: Channels,
+    /// 解码增益（Q8 dB），重建解码器时重新设置
+    gain_q8: i32,
     pcm_buffer: [i16; 5760],
+    float_buffer: [f32; 5760],
     audio_buffer: Vec<f32>,
+    /// 开启 VAD 分析帧时尚未攒满一帧的样本
+    pending: Vec<f32>,
+    vad: Box<dyn VoiceActivityDetector>,
     vad_state: VadState,
+    /// 客户端每帧的样本数，由首个解码结果确定
+    frame_samples: Option<usize>,
+    /// 在此时刻之前忽略输入
+    muted_until: Option<Instant>,
+    /// 已输入的样本总数，包括被静音和冷却丢弃的样本
+    samples_seen: usize,
+    /// 已开始的录音数，用于发现新语句开始
+    recordings_started: u64,
+    /// 输入电平统计，供定期上报
+    level: LevelStats,
 }
 
+/// 当前录音的状态和统计，端点判断由 `vad` 负责
 struct VadState {
-    silence_frames: usize,
     is_recording: bool,
     max_energy: f32,
+    voiced_frames: u32,
+    silent_frames: u32,
+    /// 冷却期剩余的样本数
+    cooldown_samples: usize,
+    /// 固定窗口模式下尚未包含在已发出窗口中的样本数
+    fresh_samples: usize,
 }
 
-const VAD_THRESHOLD_START: f32 = 800.0;
-const VAD_THRESHOLD_END: f32 = 500.0;
-const MAX_SILENCE_FRAMES: usize = 12;
-const MAX_BUFFER_SIZE: usize = 16000 * 30;
+const SAMPLE_RATE: usize = 16000;
+const MAX_BUFFER_SIZE: usize = SAMPLE_RATE * 30;
+/// 客户端可请求的解码增益上限（dB），过大的增益会放大噪声并误触发 VAD
+const MAX_DECODER_GAIN_DB: f32 = 24.0;
+/// PCM 输入每次送入 VAD 的帧长（20ms）
+const PCM_FRAME_SAMPLES: usize = 320;
+/// Opus 合法帧长（2.5ms 至 60ms）在 16kHz 下的样本数
+const OPUS_FRAME_SAMPLES: [usize; 6] = [40, 80, 160, 320, 640, 960];
 
 impl AudioProcessor {
-    /// 创建新的音频处理器
-    pub fn new() -> Result<Self, opus::Error> {
+    /// 创建使用默认能量检测器的音频处理器
+    pub fn new(options: AudioOptions) -> Result<Self, opus::Error> {
+        let vad = Box::new(EnergyVad::new(&options));
+        Self::with_vad(options, vad)
+    }
+
+    /// 按配置创建音频处理器，启动时加载了 Silero 模型时使用 Silero VAD
+    pub fn from_config(
+        config: &Config,
+        silero: Option<&Arc<SileroModel>>,
+    ) -> Result<Self, opus::Error> {
+        let options = AudioOptions::from_config(config);
+        let vad = vad::detector(silero, config, &options);
+        Self::with_vad(options, vad)
+    }
+
+    /// 创建使用指定语音活动检测器的音频处理器
+    pub fn with_vad(
+        options: AudioOptions,
+        vad: Box<dyn VoiceActivityDetector>,
+    ) -> Result<Self, opus::Error> {
         let decoder = Decoder::new(16000, Channels::Mono)?;
 
         Ok(Self {
             decoder,
+            channels: Channels::Mono,
+            gain_q8: 0,
             pcm_buffer: [0i16; 5760],
+            float_buffer: [0f32; 5760],
             audio_buffer: Vec::with_capacity(16000 * 10),
+            pending: Vec::with_capacity(options.vad_frame_samples),
+            vad,
             vad_state: VadState {
-                silence_frames: 0,
                 is_recording: false,
                 max_energy: 0.0,
+                voiced_frames: 0,
+                silent_frames: 0,
+                cooldown_samples: 0,
+                fresh_samples: 0,
             },
+            frame_samples: None,
+            muted_until: None,
+            samples_seen: 0,
+            recordings_started: 0,
+            level: LevelStats::default(),
+            options,
         })
     }
 
-    /// 处理音频数据，返回是否有完整语音片段
-    pub fn process_audio(&mut self, opus_data: &[u8]) -> Option<Vec<f32>> {
-        match self.decoder.decode(opus_data, &mut self.pcm_buffer, false) {
+    /// 设置 Opus 解码增益，超出 ±24dB 时截断，返回实际生效的增益
+    ///
+    /// Opus 的带宽、复杂度等参数由编码端决定，解码端只有增益可以安全调整。
+    pub fn set_decoder_gain(&mut self, gain_db: f32) -> Result<f32, opus::Error> {
+        let gain_db = gain_db.clamp(-MAX_DECODER_GAIN_DB, MAX_DECODER_GAIN_DB);
+        // 增益以 Q8 格式的 dB 表示
+        let gain_q8 = (gain_db * 256.0).round() as i32;
+        self.decoder.set_gain(gain_q8)?;
+        self.gain_q8 = gain_q8;
+        Ok(gain_db)
+    }
+
+    /// 在接下来的 `duration` 内忽略输入，时长为零时不生效
+    pub fn mute_for(&mut self, duration: Duration) {
+        if !duration.is_zero() {
+            self.muted_until = Some(Instant::now() + duration);
+        }
+    }
+
+    /// 处理音频数据，返回本包内结束的语音片段
+    pub fn process_audio(&mut self, opus_data: &[u8]) -> Vec<Utterance> {
+        if self.options.detect_channels {
+            self.match_packet_channels(opus_data);
+        }
+
+        let decoded = if self.options.decode_float {
+            self.decoder
+                .decode_float(opus_data, &mut self.float_buffer, false)
+        } else {
+            self.decoder.decode(opus_data, &mut self.pcm_buffer, false)
+        };
+
+        match decoded {
             Ok(samples_count) => {
-                let pcm_slice = &self.pcm_buffer[..samples_count];
-                let energy = calculate_rms(pcm_slice);
+                self.track_frame_size(samples_count);
 
-                let samples: Vec<i16> = pcm_slice.to_vec();
-                self.update_vad_state(&samples, energy)
+                let channels = channel_count(self.channels);
+                let interleaved = samples_count * channels;
+                let samples: Vec<f32> = if self.options.decode_float {
+                    self.float_buffer[..interleaved].to_vec()
+                } else {
+                    pcm_to_float(&self.pcm_buffer[..interleaved])
+                };
+                let samples = if channels > 1 {
+                    downmix(&samples, channels)
+                } else {
+                    samples
+                };
+                self.process_samples(&samples)
             }
             Err(e) => {
                 warn!("Opus解码错误: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// 包的声道数与解码器不同时重建解码器
+    ///
+    /// Opus 包首字节（TOC）的第 2 位为立体声标志，见 RFC 6716 3.1 节。
+    fn match_packet_channels(&mut self, opus_data: &[u8]) {
+        let Some(&toc) = opus_data.first() else {
+            return;
+        };
+        let channels = if toc & 0x04 != 0 {
+            Channels::Stereo
+        } else {
+            Channels::Mono
+        };
+        if channels == self.channels {
+            return;
+        }
+
+        let decoder = Decoder::new(16000, channels).and_then(|mut decoder| {
+            if self.gain_q8 != 0 {
+                decoder.set_gain(self.gain_q8)?;
+            }
+            Ok(decoder)
+        });
+        match decoder {
+            Ok(decoder) => {
+                info!(
+                    "Opus 声道数变化: {} -> {}，重建解码器",
+                    channel_count(self.channels),
+                    channel_count(channels)
+                );
+                self.decoder = decoder;
+                self.channels = channels;
+            }
+            Err(e) => warn!("重建 Opus 解码器失败: {}", e),
+        }
+    }
+
+    /// 处理已解码的 16kHz 单声道 PCM 帧，返回本帧内结束的语音片段
+    pub fn process_pcm(&mut self, samples: &[i16]) -> Vec<Utterance> {
+        self.process_samples(&pcm_to_float(samples))
+    }
+
+    /// 将 PCM16LE 字节按 20ms 帧送入 VAD，末尾的奇数字节被忽略
+    pub fn process_pcm_bytes(&mut self, bytes: &[u8]) -> Vec<Utterance> {
+        let samples: Vec<i16> = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+
+        samples
+            .chunks(PCM_FRAME_SAMPLES)
+            .flat_map(|frame| self.process_pcm(frame))
+            .collect()
+    }
+
+    /// 处理 [-1, 1] 范围的浮点样本
+    ///
+    /// 一次输入跨越多个分析帧时可能先后结束多句，按结束顺序全部返回。
+    fn process_samples(&mut self, samples: &[f32]) -> Vec<Utterance> {
+        self.samples_seen += samples.len();
+        let limited;
+        let samples = match self.options.limiter {
+            Some(limiter) => {
+                let (output, count) = limiter.apply(samples);
+                METRICS
+                    .limited_samples_total
+                    .fetch_add(count as u64, Ordering::Relaxed);
+                limited = output;
+                &limited
+            }
+            None => samples,
+        };
+        self.track_level(samples);
+        if self.is_muted() {
+            return Vec::new();
+        }
+
+        if self.options.window_samples > 0 {
+            return self.process_window(samples).into_iter().collect();
+        }
+
+        let frame_samples = self.options.vad_frame_samples;
+        if frame_samples == 0 {
+            return self.process_vad_frame(samples).into_iter().collect();
+        }
+
+        // 按固定分析帧送入 VAD，端点检测的粒度与客户端帧长无关
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(samples);
+        let mut frames = pending.chunks_exact(frame_samples);
+        let mut utterances = Vec::new();
+        for frame in frames.by_ref() {
+            utterances.extend(self.process_vad_frame(frame));
+        }
+        let consumed = pending.len() - frames.remainder().len();
+        pending.drain(..consumed);
+        self.pending = pending;
+        utterances
+    }
+
+    /// 一个分析帧经过冷却判断后送入 VAD
+    fn process_vad_frame(&mut self, samples: &[f32]) -> Option<Utterance> {
+        if self.in_cooldown(samples.len()) {
+            return None;
+        }
+
+        self.update_vad_state(samples)
+    }
+
+    /// 输入结束时结束未完成的录音
+    pub fn flush(&mut self) -> Option<Utterance> {
+        if self.options.window_samples > 0 {
+            return if self.vad_state.fresh_samples > 0 {
+                self.take_recording(EndReason::Flush)
+            } else {
                 None
+            };
+        }
+
+        // 不足一个分析帧的剩余样本不再判断，录音中时并入本句
+        let pending = std::mem::take(&mut self.pending);
+        if self.vad_state.is_recording {
+            self.add_samples_to_buffer(&pending);
+            self.finalize_recording(EndReason::Flush)
+        } else {
+            None
+        }
+    }
+
+    /// 是否处于静音窗口内，窗口结束后自动解除
+    fn is_muted(&mut self) -> bool {
+        match self.muted_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                self.muted_until = None;
+                info!(target: logging::SAMPLED, "静音窗口结束，开始处理音频");
+                false
             }
+            None => false,
+        }
+    }
+
+    /// 固定窗口模式：不做端点检测，缓冲满一个窗口即发出，保留重叠部分供下一窗口使用
+    fn process_window(&mut self, samples: &[f32]) -> Option<Utterance> {
+        self.add_samples_to_buffer(samples);
+        self.vad_state.fresh_samples += samples.len();
+        let energy = calculate_rms(samples);
+        self.count_frame(energy, energy >= VAD_THRESHOLD_END);
+
+        let window_samples = self.options.window_samples;
+        if self.audio_buffer.len() < window_samples {
+            return None;
+        }
+
+        let window = Utterance {
+            samples: self.audio_buffer[..window_samples].to_vec(),
+            pre_emphasis: self.options.pre_emphasis,
+            vad: self.vad_metadata(EndReason::Window),
+            finalized_at: Instant::now(),
+            offset_ms: self.buffer_offset_ms(),
+        };
+        self.drain_buffer(window_samples - self.options.window_overlap_samples);
+        self.vad_state.fresh_samples = self
+            .audio_buffer
+            .len()
+            .saturating_sub(self.options.window_overlap_samples);
+        self.reset_counters();
+        Some(window)
+    }
+
+    /// 丢弃缓冲开头的 `count` 个样本
+    fn drain_buffer(&mut self, count: usize) {
+        self.audio_buffer.drain(..count);
+        METRICS
+            .buffered_audio_bytes
+            .fetch_sub(count * std::mem::size_of::<f32>(), Ordering::Relaxed);
+    }
+
+    /// 是否处于一句语音结束后的冷却期，消耗本帧的样本数
+    fn in_cooldown(&mut self, samples: usize) -> bool {
+        if self.vad_state.cooldown_samples == 0 {
+            return false;
         }
+        self.vad_state.cooldown_samples = self.vad_state.cooldown_samples.saturating_sub(samples);
+        true
     }
 
-    /// 更新语音活动检测状态
-    fn update_vad_state(&mut self, samples: &[i16], energy: f32) -> Option<Vec<f32>> {
+    /// 记录客户端帧长，帧长不合法或中途变化时告警
+    fn track_frame_size(&mut self, samples_count: usize) {
+        if self.frame_samples == Some(samples_count) {
+            return;
+        }
+
+        let duration_ms = samples_count as f32 * 1000.0 / SAMPLE_RATE as f32;
+        if !OPUS_FRAME_SAMPLES.contains(&samples_count) {
+            warn!(
+                "非标准 Opus 帧长: {} 样本 ({:.1}ms)",
+                samples_count, duration_ms
+            );
+        }
+
+        match self.frame_samples {
+            None => info!("客户端帧长: {} 样本 ({:.1}ms)", samples_count, duration_ms),
+            Some(previous) => warn!(
+                "客户端帧长中途变化: {} -> {} 样本 ({:.1}ms)",
+                previous, samples_count, duration_ms
+            ),
+        }
+        self.frame_samples = Some(samples_count);
+    }
+
+    /// 按检测器的判断更新录音状态
+    fn update_vad_state(&mut self, samples: &[f32]) -> Option<Utterance> {
+        let decision = self.vad.process(samples);
+        let energy = calculate_rms(samples);
+
         if !self.vad_state.is_recording {
-            if energy > VAD_THRESHOLD_START {
+            if decision == VadDecision::Start {
+                if self.global_buffer_full() {
+                    METRICS
+                        .audio_backpressure_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    warn!("全局音频缓冲已满，拒绝开始新的录音");
+                    self.vad.reset();
+                    return None;
+                }
                 self.start_recording(samples, energy);
             }
             return None;
@@ -64,43 +586,138 @@ impl AudioProcessor {
 
         self.add_samples_to_buffer(samples);
 
-        if energy > self.vad_state.max_energy {
-            self.vad_state.max_energy = energy;
+        if self.global_buffer_full() {
+            METRICS
+                .audio_backpressure_total
+                .fetch_add(1, Ordering::Relaxed);
+            warn!("全局音频缓冲已满，提前结束当前录音");
+            return self.finalize_recording(EndReason::BufferFull);
         }
 
-        if energy < VAD_THRESHOLD_END {
-            self.vad_state.silence_frames += 1;
-        } else {
-            self.vad_state.silence_frames = 0;
+        match decision {
+            VadDecision::End(end) => {
+                self.count_frame(energy, false);
+                if end == EndReason::Unsustained {
+                    info!(target: logging::SAMPLED, "能量长时间未达到持续语音阈值，结束录音");
+                }
+                self.finalize_recording(end)
+            }
+            VadDecision::Continue { voiced } => {
+                self.count_frame(energy, voiced);
+                self.check_buffer_overflow()
+            }
+            // 录音中不应出现，按语音帧处理
+            VadDecision::Start | VadDecision::Idle => {
+                self.count_frame(energy, true);
+                self.check_buffer_overflow()
+            }
         }
+    }
 
-        if self.vad_state.silence_frames >= MAX_SILENCE_FRAMES {
-            return self.finalize_recording();
+    /// 上次读取以来的输入电平并重新开始统计，期间没有输入时返回 `None`
+    ///
+    /// 统计包括静音窗口和冷却期内的输入，不论是否有人说话。
+    pub fn take_level(&mut self) -> Option<AudioLevel> {
+        let level = std::mem::take(&mut self.level);
+        let noise_floor = level.min_frame_rms?;
+        Some(AudioLevel {
+            rms: (level.sum_squares / level.samples as f64).sqrt() as f32,
+            noise_floor,
+        })
+    }
+
+    /// 按客户端帧累计电平
+    fn track_level(&mut self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
         }
+        let rms = calculate_rms(samples);
+        self.level.sum_squares += f64::from(rms).powi(2) * samples.len() as f64;
+        self.level.samples += samples.len();
+        self.level.min_frame_rms = Some(self.level.min_frame_rms.map_or(rms, |min| min.min(rms)));
+    }
 
-        self.check_buffer_overflow();
-        None
+    /// 已开始的录音数，两次读取之间增加说明有新语句开始
+    pub fn recordings_started(&self) -> u64 {
+        self.recordings_started
     }
 
     /// 开始录音
-    fn start_recording(&mut self, samples: &[i16], energy: f32) {
+    fn start_recording(&mut self, samples: &[f32], energy: f32) {
+        self.recordings_started += 1;
         self.vad_state.is_recording = true;
-        self.vad_state.silence_frames = 0;
-        self.vad_state.max_energy = energy;
+        self.count_frame(energy, true);
         self.add_samples_to_buffer(samples);
     }
 
-    /// 添加样本到缓冲区
-    fn add_samples_to_buffer(&mut self, samples: &[i16]) {
-        for &sample in samples {
-            self.audio_buffer.push(sample as f32 / 32768.0);
+    /// 统计一帧的能量和语音判断
+    fn count_frame(&mut self, energy: f32, voiced: bool) {
+        self.vad_state.max_energy = self.vad_state.max_energy.max(energy);
+        if voiced {
+            self.vad_state.voiced_frames += 1;
+        } else {
+            self.vad_state.silent_frames += 1;
+        }
+    }
+
+    fn vad_metadata(&self, end: EndReason) -> VadMetadata {
+        VadMetadata {
+            voiced_frames: self.vad_state.voiced_frames,
+            silent_frames: self.vad_state.silent_frames,
+            max_energy: self.vad_state.max_energy,
+            end,
         }
     }
 
-    /// 完成录音并返回音频数据
-    fn finalize_recording(&mut self) -> Option<Vec<f32>> {
+    fn reset_counters(&mut self) {
+        self.vad_state.max_energy = 0.0;
+        self.vad_state.voiced_frames = 0;
+        self.vad_state.silent_frames = 0;
+    }
+
+    /// 添加样本到缓冲区
+    fn add_samples_to_buffer(&mut self, samples: &[f32]) {
+        self.audio_buffer.extend_from_slice(samples);
+        METRICS
+            .buffered_audio_bytes
+            .fetch_add(std::mem::size_of_val(samples), Ordering::Relaxed);
+    }
+
+    /// 所有连接的音频缓冲是否超过上限
+    fn global_buffer_full(&self) -> bool {
+        METRICS.buffered_audio_bytes.load(Ordering::Relaxed) >= self.options.max_total_buffer_bytes
+    }
+
+    /// 缓冲开头相对输入开头的毫秒数，缓冲之后只有尚未攒满分析帧的样本
+    fn buffer_offset_ms(&self) -> u64 {
+        let start = self
+            .samples_seen
+            .saturating_sub(self.pending.len() + self.audio_buffer.len());
+        (start * 1000 / SAMPLE_RATE) as u64
+    }
+
+    /// 当前缓冲占用的字节数
+    fn buffered_bytes(&self) -> usize {
+        std::mem::size_of_val(self.audio_buffer.as_slice())
+    }
+
+    /// 完成录音并返回音频数据，随后进入冷却期
+    fn finalize_recording(&mut self, end: EndReason) -> Option<Utterance> {
+        let result = self.take_recording(end);
+        self.vad_state.cooldown_samples = self.options.cooldown_samples;
+        result
+    }
+
+    /// 取出缓冲的音频，过短的片段丢弃
+    fn take_recording(&mut self, end: EndReason) -> Option<Utterance> {
         if self.audio_buffer.len() > 8000 {
-            let result = self.audio_buffer.clone();
+            let result = Utterance {
+                samples: self.audio_buffer.clone(),
+                pre_emphasis: self.options.pre_emphasis,
+                vad: self.vad_metadata(end),
+                finalized_at: Instant::now(),
+                offset_ms: self.buffer_offset_ms(),
+            };
             self.reset_state();
             Some(result)
         } else {
@@ -111,26 +728,185 @@ impl AudioProcessor {
 
     /// 重置录音状态
     fn reset_state(&mut self) {
+        METRICS
+            .buffered_audio_bytes
+            .fetch_sub(self.buffered_bytes(), Ordering::Relaxed);
         self.audio_buffer.clear();
-        self.vad_state.silence_frames = 0;
+        self.vad.reset();
         self.vad_state.is_recording = false;
-        self.vad_state.max_energy = 0.0;
+        self.vad_state.fresh_samples = 0;
+        self.reset_counters();
     }
 
-    /// 检查缓冲区溢出
-    fn check_buffer_overflow(&mut self) {
+    /// 单句录音超过最大时长时结束本句，已缓冲的音频照常发出
+    fn check_buffer_overflow(&mut self) -> Option<Utterance> {
         if self.audio_buffer.len() > MAX_BUFFER_SIZE {
-            warn!("缓冲区过大，重置");
-            self.reset_state();
+            warn!("单句录音超过最大时长，提前结束");
+            return self.finalize_recording(EndReason::Overflow);
         }
+        None
+    }
+}
+
+impl Drop for AudioProcessor {
+    fn drop(&mut self) {
+        METRICS
+            .buffered_audio_bytes
+            .fetch_sub(self.buffered_bytes(), Ordering::Relaxed);
     }
 }
 
-/// 计算音频样本的RMS能量
-fn calculate_rms(samples: &[i16]) -> f32 {
+fn duration_to_samples(duration: Duration) -> usize {
+    SAMPLE_RATE * duration.as_millis() as usize / 1000
+}
+
+fn channel_count(channels: Channels) -> usize {
+    match channels {
+        Channels::Mono => 1,
+        Channels::Stereo => 2,
+    }
+}
+
+/// 交错的多声道样本取各声道平均，下混为单声道
+fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+    samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// i16 PCM 转为 [-1, 1] 范围的浮点样本
+fn pcm_to_float(samples: &[i16]) -> Vec<f32> {
+    samples.iter().map(|&s| s as f32 / 32768.0).collect()
+}
+
+/// 计算音频样本的RMS能量，按 i16 幅度计算以沿用 VAD 阈值
+pub fn calculate_rms(samples: &[f32]) -> f32 {
     if samples.is_empty() {
         return 0.0;
     }
-    let sum: f32 = samples.iter().map(|&s| (s as f32).powi(2)).sum();
+    let sum: f32 = samples.iter().map(|&s| (s * 32768.0).powi(2)).sum();
     (sum / samples.len() as f32).sqrt()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 满幅正弦波，叠加超出满幅的削波样本
+    fn full_scale_and_clipping() -> Vec<f32> {
+        let mut samples: Vec<f32> = (0..1600)
+            .map(|i| (i as f32 / 16000.0 * 440.0 * std::f32::consts::TAU).sin())
+            .collect();
+        samples.extend([1.0, -1.0, 1.5, -1.5, 4.0, -4.0, f32::MAX, f32::MIN]);
+        samples
+    }
+
+    #[test]
+    fn limiter_output_never_exceeds_threshold() {
+        let input = full_scale_and_clipping();
+        for threshold in [0.1, 0.5, 0.8, 1.0] {
+            for ratio in [1.0, 2.0, 4.0, 20.0] {
+                let limiter = Limiter { threshold, ratio };
+                let (output, limited) = limiter.apply(&input);
+
+                assert_eq!(output.len(), input.len());
+                assert!(limited > 0);
+                for (sample, original) in output.iter().zip(&input) {
+                    assert!(
+                        sample.abs() <= threshold,
+                        "阈值 {} 压缩比 {}: {} -> {}",
+                        threshold,
+                        ratio,
+                        original,
+                        sample
+                    );
+                    assert_eq!(sample.signum(), original.signum());
+                }
+            }
+        }
+    }
+
+    /// 1 秒 220Hz 正弦后接 300ms 静音
+    fn speech_then_silence() -> Vec<i16> {
+        let speech = (0..16000).map(|i| {
+            let t = i as f32 / 16000.0;
+            ((t * 220.0 * std::f32::consts::TAU).sin() * 8000.0) as i16
+        });
+        speech.chain(std::iter::repeat_n(0, 4800)).collect()
+    }
+
+    #[test]
+    fn utterances_ending_in_one_input_are_all_returned() {
+        let options = AudioOptions {
+            max_total_buffer_bytes: usize::MAX,
+            decode_float: false,
+            detect_channels: false,
+            limiter: None,
+            pre_emphasis: None,
+            cooldown_samples: 0,
+            window_samples: 0,
+            window_overlap_samples: 0,
+            sustain_threshold: 800.0,
+            max_unsustained_samples: 0,
+            energy_window_samples: 0,
+            vad_frame_samples: PCM_FRAME_SAMPLES,
+        };
+        let mut processor = AudioProcessor::new(options).unwrap();
+        let mut input = speech_then_silence();
+        input.extend(speech_then_silence());
+
+        let utterances = processor.process_pcm(&input);
+
+        assert_eq!(utterances.len(), 2);
+        assert!(utterances
+            .iter()
+            .all(|utterance| utterance.samples.len() >= 16000));
+        assert!(processor.flush().is_none());
+    }
+
+    #[test]
+    fn pre_emphasis_leaves_utterance_samples_untouched() {
+        let options = AudioOptions {
+            max_total_buffer_bytes: usize::MAX,
+            decode_float: false,
+            detect_channels: false,
+            limiter: None,
+            pre_emphasis: Some(0.97),
+            cooldown_samples: 0,
+            window_samples: 0,
+            window_overlap_samples: 0,
+            sustain_threshold: 800.0,
+            max_unsustained_samples: 0,
+            energy_window_samples: 0,
+            vad_frame_samples: PCM_FRAME_SAMPLES,
+        };
+        let mut processor = AudioProcessor::new(options).unwrap();
+        let input = speech_then_silence();
+
+        let utterance = processor.process_pcm(&input).remove(0);
+        let raw: Vec<f32> = input[..utterance.samples.len()]
+            .iter()
+            .map(|&sample| sample as f32 / 32768.0)
+            .collect();
+
+        assert_eq!(utterance.samples, raw);
+        let filtered = utterance.recognition_samples();
+        assert_eq!(filtered.len(), raw.len());
+        assert!((filtered[1] - (raw[1] - 0.97 * raw[0])).abs() < 1e-6);
+    }
+
+    #[test]
+    fn limiter_keeps_samples_below_knee() {
+        let limiter = Limiter {
+            threshold: 0.8,
+            ratio: 4.0,
+        };
+        let (output, limited) = limiter.apply(&[0.0, 0.5, -0.7, 0.9, 1.0]);
+
+        assert_eq!(&output[..3], &[0.0, 0.5, -0.7]);
+        assert_eq!(limited, 2);
+        assert!(output[3] > 0.7 && output[3] < 0.8);
+        assert!((output[4] - 0.8).abs() < 1e-6);
+    }
+}
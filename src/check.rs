@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use crate::config::Config;
+use crate::emotion::EmotionAnalyzer;
+use crate::history::HistoryStore;
+use crate::speech::{RecognizerOptions, SpeechRecognizer};
+use crate::vad::SileroModel;
+
+/// 启动前依赖检查：模型、Ollama、数据库、监听地址，全部通过返回 true
+pub async fn run(config: &Config, history: &dyn HistoryStore) -> bool {
+    let mut passed = true;
+
+    println!("🔍 心镜依赖检查");
+
+    let options = RecognizerOptions::from_config(config);
+    for (language, model_path) in &config.whisper_models {
+        let result = if Path::new(model_path).exists() {
+            SpeechRecognizer::try_load(model_path, language, options.clone())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        } else {
+            Err("文件不存在".to_string())
+        };
+        passed &= report(
+            &format!("Whisper 模型 [{}] {}", language, model_path),
+            result,
+        );
+    }
+
+    if let Some(model_path) = &config.fallback_model {
+        let result = if Path::new(model_path).exists() {
+            SpeechRecognizer::try_load(model_path, &config.default_language, options.clone())
+                .map(drop)
+                .map_err(|e| e.to_string())
+        } else {
+            Err("文件不存在".to_string())
+        };
+        passed &= report(&format!("备用 Whisper 模型 {}", model_path), result);
+    }
+
+    if let Some(model_path) = &config.shadow_model {
+        let result = if Path::new(model_path).exists() {
+            SpeechRecognizer::try_load(model_path, &config.default_language, options.clone())
+                .map(drop)
+                .map_err(|e| e.to_string())
+        } else {
+            Err("文件不存在".to_string())
+        };
+        passed &= report(&format!("影子 Whisper 模型 {}", model_path), result);
+    }
+
+    if let Some(model_path) = &config.silero_vad_model {
+        let result = if Path::new(model_path).exists() {
+            SileroModel::load(model_path)
+                .map(drop)
+                .map_err(|e| e.to_string())
+        } else {
+            Err("文件不存在".to_string())
+        };
+        passed &= report(&format!("Silero VAD 模型 {}", model_path), result);
+    }
+
+    let analyzer = EmotionAnalyzer::from_config(config);
+    let result = analyzer
+        .send_test_request()
+        .await
+        .map_err(|e| e.to_string());
+    passed &= report(
+        &format!("Ollama {} @ {}", analyzer.model_name(), config.ollama_host),
+        result,
+    );
+
+    if config.persist_history {
+        let result = history.check_writable().await.map_err(|e| e.to_string());
+        passed &= report(&format!("历史库 ({}) 可写", history.name()), result);
+    } else {
+        println!("  ⏭️  历史记录持久化已禁用，跳过");
+    }
+
+    let result = tokio::net::TcpListener::bind(config.listen_addr)
+        .await
+        .map(drop)
+        .map_err(|e| e.to_string());
+    passed &= report(&format!("监听地址 {}", config.listen_addr), result);
+
+    if passed {
+        println!("✅ 全部检查通过");
+    } else {
+        println!("❌ 存在未通过的检查");
+    }
+    passed
+}
+
+/// 输出单项检查结果
+fn report(name: &str, result: Result<(), String>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("  ✅ {}", name);
+            true
+        }
+        Err(e) => {
+            println!("  ❌ {}: {}", name, e);
+            false
+        }
+    }
+}
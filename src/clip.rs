@@ -0,0 +1,120 @@
+use std::io;
+use std::path::Path;
+
+const SAMPLE_RATE: u32 = 16000;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// 将语音片段保存为 WAV 文件，返回写入的路径
+///
+/// 文件名由会话 ID 和毫秒时间戳组成，同一会话内不会重名。
+pub async fn save(dir: &str, session_id: &str, samples: &[f32]) -> io::Result<String> {
+    tokio::fs::create_dir_all(dir).await?;
+    let file_name = format!(
+        "{}-{}.wav",
+        session_id,
+        chrono::Utc::now().timestamp_millis()
+    );
+    let path = Path::new(dir).join(file_name);
+    tokio::fs::write(&path, encode_wav(samples)).await?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// 编码为 16kHz 单声道 16 位 PCM WAV
+fn encode_wav(samples: &[f32]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let block_align = BITS_PER_SAMPLE / 8;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // 单声道
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for &sample in samples {
+        let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&pcm.to_le_bytes());
+    }
+    wav
+}
+
+/// 输入数据开头的 WAV 头部解析结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavHeader {
+    /// 不是 WAV 文件，按裸 PCM 处理
+    NotWav,
+    /// 头部尚未读完整
+    Incomplete,
+    /// 音频数据从 `offset` 开始；缺少 `fmt ` 块时格式字段为 0
+    Data {
+        offset: usize,
+        sample_rate: u32,
+        channels: u16,
+        bits_per_sample: u16,
+    },
+}
+
+impl WavHeader {
+    /// 是否为服务要求的 16kHz 单声道 16 位 PCM
+    pub fn is_supported_format(&self) -> bool {
+        match *self {
+            WavHeader::Data {
+                sample_rate,
+                channels,
+                bits_per_sample,
+                ..
+            } => sample_rate == SAMPLE_RATE && channels == 1 && bits_per_sample == BITS_PER_SAMPLE,
+            _ => true,
+        }
+    }
+}
+
+/// 解析 RIFF/WAVE 头部，跳过 `data` 之前的其他块
+pub fn parse_wav_header(bytes: &[u8]) -> WavHeader {
+    if bytes.len() < 12 {
+        return if b"RIFF".starts_with(&bytes[..bytes.len().min(4)]) {
+            WavHeader::Incomplete
+        } else {
+            WavHeader::NotWav
+        };
+    }
+    if &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return WavHeader::NotWav;
+    }
+
+    let (mut sample_rate, mut channels, mut bits_per_sample) = (0, 0, 0);
+    let mut offset = 12;
+    while let Some(chunk) = bytes.get(offset..offset + 8) {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        let body = offset + 8;
+        match &chunk[..4] {
+            b"data" => {
+                return WavHeader::Data {
+                    offset: body,
+                    sample_rate,
+                    channels,
+                    bits_per_sample,
+                }
+            }
+            b"fmt " => {
+                let Some(fmt) = bytes.get(body..body + 16) else {
+                    return WavHeader::Incomplete;
+                };
+                channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+                sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+            }
+            _ => {}
+        }
+        // 块大小为奇数时有一个填充字节
+        offset = body + size + size % 2;
+    }
+    WavHeader::Incomplete
+}
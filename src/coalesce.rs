@@ -0,0 +1,62 @@
+use std::sync::atomic::Ordering;
+
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::metrics::METRICS;
+
+/// 可被合并的推送消息
+pub trait Interim {
+    /// 中间结果的种类，同类中间结果只保留最新一条；最终结果返回 None，总是送达
+    fn interim_kind(&self) -> Option<&'static str>;
+}
+
+/// 推送队列的接收端，客户端消费跟不上时合并排队的中间结果
+///
+/// 每次取出消息时，若排在后面的是同类中间结果，则丢弃较旧的一条，
+/// 遇到其他种类的消息即停止，保证最终结果和消息之间的先后顺序不变。
+pub struct Coalescer<T> {
+    rx: UnboundedReceiver<T>,
+    /// 合并时多取出的一条非同类消息，下次优先返回
+    pending: Option<T>,
+    enabled: bool,
+}
+
+impl<T: Interim> Coalescer<T> {
+    pub fn new(rx: UnboundedReceiver<T>, enabled: bool) -> Self {
+        Self {
+            rx,
+            pending: None,
+            enabled,
+        }
+    }
+
+    /// 取出下一条要发送的消息，发送端全部关闭且队列为空时返回 None
+    ///
+    /// 只在等待第一条消息时挂起，可安全用于 `tokio::select!`。
+    pub async fn recv(&mut self) -> Option<T> {
+        let mut current = match self.pending.take() {
+            Some(message) => message,
+            None => self.rx.recv().await?,
+        };
+        if !self.enabled {
+            return Some(current);
+        }
+
+        while let Some(kind) = current.interim_kind() {
+            match self.rx.try_recv() {
+                Ok(next) if next.interim_kind() == Some(kind) => {
+                    METRICS
+                        .interim_coalesced_total
+                        .fetch_add(1, Ordering::Relaxed);
+                    current = next;
+                }
+                Ok(next) => {
+                    self.pending = Some(next);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        Some(current)
+    }
+}
@@ -0,0 +1,549 @@
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::env::VarError;
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{PoisonError, RwLock};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::emotion::{EmotionActions, EmotionLabels, EmotionSelection, OllamaApi};
+use crate::model_select;
+use crate::storage;
+use crate::subtitle::SubtitleFormat;
+use crate::text::TranscriptLogging;
+use crate::vad::VAD_THRESHOLD_END;
+
+/// `CONFIG_FILE` 中的设置，优先于同名环境变量
+static FILE_VALUES: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// 服务器配置，从环境变量和 `CONFIG_FILE` 读取
+///
+/// 新增字段时需同时加入 `reload.rs` 的可热更新或需重启列表。
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 服务监听地址
+    pub listen_addr: SocketAddr,
+    /// 监听套接字的连接等待队列长度
+    pub listen_backlog: i32,
+    /// tokio 工作线程数，`None` 使用 CPU 核数
+    pub worker_threads: Option<usize>,
+    /// 收到退出信号后等待连接关闭的最长时间
+    pub shutdown_grace: Duration,
+    /// 连接空闲多久后开始发送 keepalive 探测
+    pub tcp_keepalive_time: Duration,
+    /// keepalive 探测间隔
+    pub tcp_keepalive_interval: Duration,
+    /// keepalive 探测失败多少次后断开
+    pub tcp_keepalive_retries: u32,
+    /// 已发送数据未被确认多久后断开（`TCP_USER_TIMEOUT`），`None` 使用系统默认值
+    pub tcp_user_timeout: Option<Duration>,
+    /// 每个来源 IP 每秒允许的新连接数，`0` 不限制
+    pub connection_rate: f64,
+    /// 每个来源 IP 可连续建立的连接数上限
+    pub connection_burst: u32,
+    /// 语言 -> Whisper 模型路径
+    pub whisper_models: Vec<(String, String)>,
+    /// 没有连接使用多久后卸载 Whisper 模型，下次连接时重新加载；`None` 时常驻内存
+    pub model_idle_unload: Option<Duration>,
+    /// 负载过高时切换到的较小 Whisper 模型，仅用于默认语言
+    pub fallback_model: Option<String>,
+    /// 主模型识别延迟的滑动平均超过该值时切换到备用模型
+    pub fallback_latency: Duration,
+    /// 同时进行的识别数超过该值时切换到备用模型
+    pub fallback_queue_depth: usize,
+    /// 切换到备用模型后至少保持的时长
+    pub fallback_hold: Duration,
+    /// 在后台重新识别每句语音以对比结果的影子 Whisper 模型路径
+    pub shadow_model: Option<String>,
+    /// 客户端未声明语言时使用的默认语言
+    pub default_language: String,
+    /// 初始连接时按时间段生成问候语和情绪
+    pub time_greeting: bool,
+    /// 包含 `ping` 的任意文本都视为心跳，兼容旧客户端
+    pub legacy_ping_match: bool,
+    /// 存储时间戳和生成问候语使用的时区
+    pub timezone: Tz,
+    /// 是否将识别结果写入 SQLite
+    pub persist_history: bool,
+    /// SQLite 数据库文件路径，`:memory:` 时使用进程内的内存数据库
+    pub db_path: String,
+    /// 历史库地址，如 `sqlite:/data/history.db`，设置后取代 `DB_PATH`
+    pub database_url: Option<String>,
+    /// 同时保存每条识别结果的语音片段，供回放
+    pub store_audio: bool,
+    /// 语音片段保存目录
+    pub audio_dir: String,
+    /// 识别结果繁体转简体、全角转半角
+    pub normalize_transcript: bool,
+    /// Whisper 解码初始温度
+    pub whisper_temperature: f32,
+    /// Whisper 无语音概率阈值
+    pub whisper_no_speech_thold: f32,
+    /// Whisper 抑制开头空白输出
+    pub whisper_suppress_blank: bool,
+    /// 解码时禁止输出的 token，可写词表 ID 或文本，见 `speech::resolve_suppress_tokens`
+    pub whisper_suppress_tokens: Vec<String>,
+    /// 短于该时长的音频在识别前用静音补足，零表示不补
+    pub whisper_min_audio: Duration,
+    /// 识别文本的最大字符数，超出时截断并以省略号结尾
+    pub max_transcript_chars: usize,
+    /// 日志中记录识别文本和客户端文本的方式
+    pub log_transcripts: TranscriptLogging,
+    /// 为每句语音计算粗略声学特征（能量、过零率、音高）
+    pub acoustic_fingerprint: bool,
+    /// 为每句语音计算语速和停顿统计
+    pub prosody_metrics: bool,
+    /// 在结果中附带端点检测信息（帧数、峰值能量、结束原因）
+    pub vad_metadata: bool,
+    /// 在结果中附带逐 token 的时间戳，供口型同步和卡拉 OK 高亮
+    pub token_timings: bool,
+    /// 识别出每个分段后立即分析其情绪并推送，整句结果仍单独返回
+    pub segment_emotion: bool,
+    /// 情绪到客户端动作的映射，设置 `EMOTION_ACTIONS_FILE` 时存在
+    pub emotion_actions: Option<EmotionActions>,
+    /// 发给客户端时替换的情绪标签
+    pub emotion_labels: EmotionLabels,
+    /// 客户端消费过慢时只保留最新的中间结果（进度、分段情绪），最终结果总是送达
+    pub coalesce_interim: bool,
+    /// 丢弃 `hello` 握手之前收到的音频
+    pub strict_handshake: bool,
+    /// 用户再次开口时中止同一连接上尚未完成的识别
+    pub barge_in_cancel: bool,
+    /// 在结果和历史记录中标注识别和情绪分析使用的模型
+    pub include_model_ids: bool,
+    /// 在结果和历史记录中同时保留规范化前的识别文本
+    pub include_raw_text: bool,
+    /// 处理完 `hello` 和 `event` 后回复 `ack` 消息
+    pub control_acks: bool,
+    /// 默认模型不可用时拒绝新的 WebSocket 连接
+    pub require_model_ready: bool,
+    /// 未通过有效性检查的识别结果也返回给客户端，并标记为低质量
+    pub emit_low_quality: bool,
+    /// 识别文本至少包含的字母、数字或中日韩文字数，不足时视为无效
+    pub min_content_chars: usize,
+    /// 由一次 LLM 请求同时判断识别文本是否有意义并分析情绪
+    pub combined_analysis: bool,
+    /// 低质量结果也写入输出端
+    pub store_low_quality: bool,
+    /// 定期整理历史库（`VACUUM`）的间隔，零表示禁用
+    pub vacuum_interval: Duration,
+    /// 距最近一次写入至少多久才执行整理
+    pub vacuum_idle: Duration,
+    /// 识别结果输出端列表，如 `sqlite,stdout`
+    pub result_sinks: Vec<String>,
+    /// `redis` 输出端的连接地址，如 `redis://127.0.0.1:6379`
+    pub mq_url: Option<String>,
+    /// `redis` 输出端发布的频道
+    pub mq_topic: String,
+    /// `subtitle` 输出端写入字幕文件的目录
+    pub subtitle_dir: String,
+    /// `subtitle` 输出端的字幕格式
+    pub subtitle_format: SubtitleFormat,
+    /// 所有连接合计的音频缓冲上限（字节）
+    pub max_total_audio_bytes: usize,
+    /// Opus 直接解码为浮点样本
+    pub decode_float: bool,
+    /// 按 Opus 包 TOC 字节检测声道数，立体声自动下混为单声道
+    pub opus_detect_channels: bool,
+    /// 软限幅开始压缩的幅度（满幅为 1.0），`0` 时不限幅
+    pub limiter_threshold: f32,
+    /// 软限幅超出阈值部分的压缩比
+    pub limiter_ratio: f32,
+    /// 识别前对语音片段做预加重
+    pub pre_emphasis: bool,
+    /// 预加重系数，越接近 1 高频提升越明显
+    pub pre_emphasis_coeff: f32,
+    /// 发送初始响应后忽略麦克风输入的时长，避免把设备播放的问候语识别为语音
+    pub startup_mute: Duration,
+    /// 一句语音结束后忽略输入的时长，避免混响尾音立即触发下一句
+    pub vad_cooldown: Duration,
+    /// 录音期间需周期性达到的"持续语音"能量阈值（i16 幅度）
+    pub vad_sustain_threshold: f32,
+    /// 能量持续低于持续语音阈值多久后结束录音，`None` 时不限制
+    pub vad_max_unsustained: Option<Duration>,
+    /// 能量检测计算 RMS 的时间窗口，`0` 时按客户端帧计算
+    pub vad_energy_window: Duration,
+    /// 解码后的音频攒满该时长再送入 VAD，零表示按客户端帧
+    pub vad_frame: Duration,
+    /// Silero VAD ONNX 模型路径，设置后替代能量检测，加载失败时回退
+    pub silero_vad_model: Option<String>,
+    /// Silero VAD 判定为语音的概率阈值
+    pub silero_vad_threshold: f32,
+    /// 唤醒词，设置后只有说出唤醒词后的语句才会完整识别
+    pub wake_phrase: Option<String>,
+    /// 唤醒后保持唤醒的时长，每句有效语音续期
+    pub wake_window: Duration,
+    /// 固定窗口流式识别的窗口长度，`None` 时按 VAD 切分语句
+    pub chunk_window: Option<Duration>,
+    /// 相邻窗口的重叠时长，最多为窗口长度的一半
+    pub chunk_overlap: Duration,
+    /// 同一连接内与上一句完全相同的识别结果在该时长内不再发送和存储，`None` 时不去重
+    pub dedup_window: Option<Duration>,
+    /// 实时输入的语句切分后等待识别超过该时长时丢弃，`None` 时不丢弃
+    pub max_utterance_staleness: Option<Duration>,
+    /// 短语句与上一句的停顿不超过该时长时合并重新识别，`None` 时不合并
+    pub utterance_coalesce_window: Option<Duration>,
+    /// 参与合并的语句的最大时长
+    pub utterance_coalesce_max: Duration,
+    /// Ollama 服务地址
+    pub ollama_host: String,
+    /// 情绪分析使用的 Ollama 模型
+    pub ollama_model: String,
+    /// 情绪分析使用的 Ollama 接口
+    pub ollama_api: OllamaApi,
+    /// 后台探测 Ollama 可达性的间隔，`None` 时不探测
+    pub ollama_probe_interval: Option<Duration>,
+    /// 单次情绪分析失败后的重试次数
+    pub ollama_retries: u32,
+    /// 单次情绪分析请求的超时
+    pub ollama_timeout: Duration,
+    /// 连续失败多少次后熔断
+    pub breaker_threshold: u32,
+    /// 熔断后等待多久再探测 Ollama
+    pub breaker_cooldown: Duration,
+    /// 情绪分析时附带的此前语句数，`0` 表示只分析当前语句
+    pub emotion_context_turns: usize,
+    /// 每个连接在内存中保留的最近识别结果数，供 `get_recent` 查询
+    pub recent_transcripts: usize,
+    /// 每个连接上报输入电平（`audio_level`）的间隔，`None` 时不上报
+    pub audio_level_interval: Option<Duration>,
+    /// 协商压缩的连接中，JSON 消息不小于该字节数时压缩发送，`0` 表示不压缩
+    pub compress_min_bytes: usize,
+    /// `/monitor` 接口的访问令牌，未设置时禁用该接口
+    pub monitor_token: Option<String>,
+    /// 新情绪需连续出现多少句才替换上报的情绪，`1` 表示不平滑
+    pub emotion_smoothing_window: usize,
+    /// LLM 输出包含多个情绪标签时的选取规则
+    pub emotion_selection: EmotionSelection,
+    /// 合并并发的相同情绪分析请求
+    pub emotion_single_flight: bool,
+    /// 送入情绪分析提示词的文本最大字符数，`0` 表示不限制
+    pub emotion_max_chars: usize,
+    /// 词数少于该值的语句不做情绪分析，直接使用 `neutral`，`0` 表示总是分析
+    pub emotion_min_words: usize,
+    /// 重新分析历史记录时两次请求的间隔
+    pub reanalyze_interval: Duration,
+}
+
+impl Config {
+    /// 从环境变量构建配置
+    ///
+    /// `WHISPER_MODELS` 形如 `zh=ggml-base.bin,en=ggml-base.en.bin`；
+    /// 未设置时退回到 `MODEL_PATH` 单模型模式。开启 `WHISPER_AUTO_MODEL` 且未显式设置
+    /// `MODEL_PATH` 时，按硬件从 `WHISPER_MODEL_DIR` 中自动选择模型。
+    pub fn from_env() -> Self {
+        let default_language = env_or("DEFAULT_LANGUAGE", "zh");
+
+        let whisper_models = match env_var("WHISPER_MODELS") {
+            Ok(spec) => parse_model_map(&spec),
+            Err(_) => vec![(
+                default_language.clone(),
+                single_model_path(&default_language),
+            )],
+        };
+
+        Self {
+            listen_addr: env_parse("LISTEN_ADDR", SocketAddr::from(([0, 0, 0, 0], 4321))),
+            listen_backlog: env_parse("LISTEN_BACKLOG", 1024),
+            worker_threads: match env_parse("WORKER_THREADS", 0) {
+                0 => None,
+                threads => Some(threads),
+            },
+            shutdown_grace: Duration::from_secs(env_parse("SHUTDOWN_GRACE_SECS", 10)),
+            tcp_keepalive_time: Duration::from_secs(env_parse("TCP_KEEPALIVE_SECS", 60)),
+            tcp_keepalive_interval: Duration::from_secs(env_parse(
+                "TCP_KEEPALIVE_INTERVAL_SECS",
+                10,
+            )),
+            tcp_keepalive_retries: env_parse("TCP_KEEPALIVE_RETRIES", 5),
+            tcp_user_timeout: match env_parse("TCP_USER_TIMEOUT_SECS", 120) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            connection_rate: env_parse("CONNECTION_RATE", 0.0),
+            connection_burst: env_parse("CONNECTION_BURST", 5),
+            whisper_models,
+            fallback_model: env_var("FALLBACK_MODEL_PATH")
+                .ok()
+                .filter(|path| !path.is_empty()),
+            fallback_latency: Duration::from_millis(env_parse("FALLBACK_LATENCY_MS", 3000)),
+            fallback_queue_depth: env_parse("FALLBACK_QUEUE_DEPTH", 4),
+            fallback_hold: Duration::from_secs(env_parse("FALLBACK_HOLD_SECS", 30)),
+            shadow_model: env_var("SHADOW_MODEL_PATH")
+                .ok()
+                .filter(|path| !path.is_empty()),
+            model_idle_unload: match env_parse("MODEL_IDLE_UNLOAD_SECS", 0) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            default_language,
+            time_greeting: env_bool("TIME_GREETING", false),
+            legacy_ping_match: env_bool("LEGACY_PING_MATCH", false),
+            timezone: parse_timezone(),
+            persist_history: env_bool("PERSIST_HISTORY", true),
+            db_path: env_or("DB_PATH", storage::DEFAULT_DB_PATH),
+            database_url: env_var("DATABASE_URL").ok().filter(|url| !url.is_empty()),
+            store_audio: env_bool("STORE_AUDIO", false),
+            audio_dir: env_or("AUDIO_DIR", "audio_clips"),
+            normalize_transcript: env_bool("NORMALIZE_TRANSCRIPT", false),
+            whisper_temperature: env_parse("WHISPER_TEMPERATURE", 0.0),
+            whisper_no_speech_thold: env_parse("WHISPER_NO_SPEECH_THOLD", 0.6),
+            whisper_suppress_blank: env_bool("WHISPER_SUPPRESS_BLANK", true),
+            whisper_suppress_tokens: parse_suppress_tokens(),
+            whisper_min_audio: Duration::from_millis(env_parse("WHISPER_MIN_AUDIO_MS", 0)),
+            max_transcript_chars: env_parse("MAX_TRANSCRIPT_CHARS", 2000),
+            log_transcripts: env_parse("LOG_TRANSCRIPTS", TranscriptLogging::Full),
+            acoustic_fingerprint: env_bool("ACOUSTIC_FINGERPRINT", false),
+            prosody_metrics: env_bool("PROSODY_METRICS", false),
+            vad_metadata: env_bool("VAD_METADATA", false),
+            token_timings: env_bool("TOKEN_TIMINGS", false),
+            segment_emotion: env_bool("SEGMENT_EMOTION", false),
+            emotion_actions: load_emotion_actions(),
+            emotion_labels: EmotionLabels::parse(&env_or("EMOTION_LABEL_MAP", "")),
+            coalesce_interim: env_bool("COALESCE_INTERIM", false),
+            strict_handshake: env_bool("STRICT_HANDSHAKE", false),
+            barge_in_cancel: env_bool("BARGE_IN_CANCEL", false),
+            include_model_ids: env_bool("INCLUDE_MODEL_IDS", false),
+            include_raw_text: env_bool("INCLUDE_RAW_TEXT", false),
+            control_acks: env_bool("CONTROL_ACKS", false),
+            require_model_ready: env_bool("REQUIRE_MODEL_READY", false),
+            emit_low_quality: env_bool("EMIT_LOW_QUALITY", false),
+            min_content_chars: env_parse("MIN_CONTENT_CHARS", 1),
+            combined_analysis: env_bool("COMBINED_ANALYSIS", false),
+            store_low_quality: env_bool("STORE_LOW_QUALITY", false),
+            vacuum_interval: Duration::from_secs(env_parse("DB_VACUUM_INTERVAL_SECS", 86400)),
+            vacuum_idle: Duration::from_secs(env_parse("DB_VACUUM_IDLE_SECS", 60)),
+            result_sinks: env_list("RESULT_SINKS", "sqlite"),
+            mq_url: env_var("MQ_URL").ok().filter(|url| !url.is_empty()),
+            mq_topic: env_or("MQ_TOPIC", "xinjing.results"),
+            subtitle_dir: env_or("SUBTITLE_DIR", "subtitles"),
+            subtitle_format: env_parse("SUBTITLE_FORMAT", SubtitleFormat::Srt),
+            max_total_audio_bytes: env_parse("MAX_TOTAL_AUDIO_MB", 256usize) * 1024 * 1024,
+            decode_float: env_bool("OPUS_DECODE_FLOAT", false),
+            opus_detect_channels: env_bool("OPUS_DETECT_CHANNELS", false),
+            limiter_threshold: env_parse("LIMITER_THRESHOLD", 0.0),
+            limiter_ratio: env_parse("LIMITER_RATIO", 4.0),
+            pre_emphasis: env_bool("PRE_EMPHASIS", false),
+            pre_emphasis_coeff: env_parse("PRE_EMPHASIS_COEFF", 0.97),
+            startup_mute: Duration::from_millis(env_parse("STARTUP_MUTE_MS", 0)),
+            vad_cooldown: Duration::from_millis(env_parse("VAD_COOLDOWN_MS", 0)),
+            vad_sustain_threshold: sustain_threshold(),
+            vad_max_unsustained: match env_parse("VAD_MAX_UNSUSTAINED_MS", 0) {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            vad_energy_window: Duration::from_millis(env_parse("VAD_ENERGY_WINDOW_MS", 20)),
+            vad_frame: Duration::from_millis(env_parse("VAD_FRAME_MS", 0)),
+            silero_vad_model: env_var("SILERO_VAD_MODEL")
+                .ok()
+                .filter(|path| !path.is_empty()),
+            silero_vad_threshold: env_parse("SILERO_VAD_THRESHOLD", 0.5),
+            wake_phrase: env_var("WAKE_PHRASE")
+                .ok()
+                .filter(|phrase| !phrase.trim().is_empty()),
+            wake_window: Duration::from_secs(env_parse("WAKE_WINDOW_SECS", 10)),
+            chunk_window: match env_parse("CHUNK_WINDOW_MS", 0) {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            chunk_overlap: Duration::from_millis(env_parse("CHUNK_OVERLAP_MS", 1000)),
+            dedup_window: match env_parse("DEDUP_WINDOW_MS", 0) {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            max_utterance_staleness: match env_parse("MAX_UTTERANCE_STALENESS_MS", 0) {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            utterance_coalesce_window: match env_parse("UTTERANCE_COALESCE_MS", 0) {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+            utterance_coalesce_max: Duration::from_millis(env_parse(
+                "UTTERANCE_COALESCE_MAX_MS",
+                1500,
+            )),
+            ollama_host: env_or("OLLAMA_HOST", "http://ollama:11434"),
+            ollama_model: env_or("OLLAMA_MODEL", "qwen2.5:1.5b"),
+            ollama_api: env_parse("OLLAMA_API", OllamaApi::Generate),
+            ollama_probe_interval: match env_parse("OLLAMA_PROBE_INTERVAL_SECS", 0) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            ollama_retries: env_parse("OLLAMA_RETRIES", 1),
+            ollama_timeout: Duration::from_millis(env_parse("OLLAMA_TIMEOUT_MS", 5000)),
+            breaker_threshold: env_parse("OLLAMA_BREAKER_THRESHOLD", 3),
+            breaker_cooldown: Duration::from_secs(env_parse("OLLAMA_BREAKER_COOLDOWN_SECS", 30)),
+            emotion_context_turns: env_parse("EMOTION_CONTEXT_TURNS", 0),
+            recent_transcripts: env_parse("RECENT_TRANSCRIPTS", 20),
+            audio_level_interval: match env_parse("AUDIO_LEVEL_INTERVAL_SECS", 0) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            compress_min_bytes: env_parse("COMPRESS_MIN_BYTES", 1024),
+            monitor_token: env_var("MONITOR_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            emotion_smoothing_window: env_parse("EMOTION_SMOOTHING_WINDOW", 1),
+            emotion_selection: env_parse("EMOTION_SELECTION", EmotionSelection::First),
+            emotion_single_flight: env_bool("EMOTION_SINGLE_FLIGHT", true),
+            emotion_max_chars: env_parse("EMOTION_MAX_CHARS", 500),
+            emotion_min_words: env_parse("EMOTION_MIN_WORDS", 0),
+            reanalyze_interval: Duration::from_millis(env_parse("REANALYZE_INTERVAL_MS", 500)),
+        }
+    }
+}
+
+/// 读取 `CONFIG_FILE` 指定的 `KEY=VALUE` 文件，之后构建的配置以其中的设置为准
+///
+/// 空行和 `#` 开头的行被忽略，值两侧的引号会被去掉。未设置 `CONFIG_FILE` 时只使用环境变量。
+/// 读取失败时保留上一次读取的内容。
+pub fn load_config_file() -> io::Result<()> {
+    let values = match std::env::var("CONFIG_FILE") {
+        Ok(path) if !path.is_empty() => Some(parse_config_file(&std::fs::read_to_string(path)?)),
+        _ => None,
+    };
+    *FILE_VALUES.write().unwrap_or_else(PoisonError::into_inner) = values;
+    Ok(())
+}
+
+/// 解析 `KEY=VALUE` 格式的配置文件，无法解析的行忽略
+fn parse_config_file(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// 读取设置，`CONFIG_FILE` 中的值优先于环境变量
+fn env_var(key: &str) -> Result<String, VarError> {
+    let file_value = FILE_VALUES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .and_then(|values| values.get(key).cloned());
+    match file_value {
+        Some(value) => Ok(value),
+        None => std::env::var(key),
+    }
+}
+
+/// 读取环境变量，不存在时使用默认值
+fn env_or(key: &str, default: &str) -> String {
+    env_var(key).unwrap_or_else(|_| default.to_string())
+}
+
+/// 读取布尔型环境变量，接受 `1/true/yes/on`
+fn env_bool(key: &str, default: bool) -> bool {
+    match env_var(key) {
+        Ok(value) => matches!(
+            value.trim().to_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        ),
+        Err(_) => default,
+    }
+}
+
+/// 读取逗号分隔的列表型环境变量
+fn env_list(key: &str, default: &str) -> Vec<String> {
+    env_or(key, default)
+        .split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// 读取 `WHISPER_SUPPRESS_TOKENS`，逗号分隔；保留大小写和词首空格之外的内容
+fn parse_suppress_tokens() -> Vec<String> {
+    env_var("WHISPER_SUPPRESS_TOKENS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// 读取可解析的环境变量，缺失或解析失败时使用默认值
+fn env_parse<T: FromStr>(key: &str, default: T) -> T {
+    env_var(key)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// 读取持续语音阈值，不高于 VAD 结束阈值时没有意义，提高到结束阈值
+fn sustain_threshold() -> f32 {
+    let threshold = env_parse("VAD_SUSTAIN_THRESHOLD", 800.0);
+    if threshold <= VAD_THRESHOLD_END {
+        warn!(
+            "VAD_SUSTAIN_THRESHOLD={} 不高于结束阈值 {}，按结束阈值处理",
+            threshold, VAD_THRESHOLD_END
+        );
+        return VAD_THRESHOLD_END;
+    }
+    threshold
+}
+
+/// 读取 `EMOTION_ACTIONS_FILE` 指定的动作映射
+///
+/// 映射缺项时客户端会收到没有动作的结果，因此加载或校验失败时直接终止启动。
+fn load_emotion_actions() -> Option<EmotionActions> {
+    let path = env_var("EMOTION_ACTIONS_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())?;
+    match EmotionActions::load(&path) {
+        Ok(actions) => Some(actions),
+        Err(e) => panic!("EMOTION_ACTIONS_FILE={} 无效: {}", path, e),
+    }
+}
+
+/// 读取 `TZ_NAME` 时区，默认 `Asia/Shanghai`
+///
+/// 时区名写错会导致时间戳悄悄偏移，因此解析失败时直接终止启动。
+fn parse_timezone() -> Tz {
+    let name = env_or("TZ_NAME", "Asia/Shanghai");
+    name.trim()
+        .parse()
+        .unwrap_or_else(|e| panic!("无效的时区 TZ_NAME={}: {}", name, e))
+}
+
+/// 单模型模式的模型路径：显式的 `MODEL_PATH` 优先，其次自动选择，最后使用默认模型
+fn single_model_path(language: &str) -> String {
+    if let Ok(path) = env_var("MODEL_PATH") {
+        return path;
+    }
+    if env_bool("WHISPER_AUTO_MODEL", false) {
+        let dir = env_or("WHISPER_MODEL_DIR", "models");
+        if let Some(path) = model_select::select(&dir, language) {
+            return path;
+        }
+        warn!("自动选择模型失败，使用默认模型 ggml-base.bin");
+    }
+    "ggml-base.bin".to_string()
+}
+
+/// 解析 `语言=路径` 列表
+fn parse_model_map(spec: &str) -> Vec<(String, String)> {
+    spec.split(',')
+        .filter_map(|entry| {
+            let (language, path) = entry.split_once('=')?;
+            let (language, path) = (language.trim(), path.trim());
+            if language.is_empty() || path.is_empty() {
+                return None;
+            }
+            Some((language.to_lowercase(), path.to_string()))
+        })
+        .collect()
+}
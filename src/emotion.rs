@@ -1,8 +1,29 @@
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::watch;
 use tracing::{error, info, warn};
 
+use crate::config::Config;
+use crate::logging;
+use crate::metrics::METRICS;
+use crate::text;
+
+/// 对话上下文的总字符数上限，超出时丢弃最早的语句
+const MAX_CONTEXT_CHARS: usize = 500;
+
+/// 情绪分析可能返回的全部标签
+pub const VALID_EMOTIONS: &[&str] = &[
+    "joy", "anger", "sadness", "fear", "calm", "neutral", "sleep",
+];
+
 #[derive(Debug, Serialize)]
 struct OllamaRequest {
     model: String,
@@ -15,38 +36,484 @@ struct OllamaResponse {
     response: String,
 }
 
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: ChatMessage,
+}
+
+/// 情绪分析使用的 Ollama 接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OllamaApi {
+    /// `/api/generate`，指令和文本拼成一个提示词
+    Generate,
+    /// `/api/chat`，指令作为 system 消息，文本作为 user 消息
+    Chat,
+}
+
+impl OllamaApi {
+    fn path(self) -> &'static str {
+        match self {
+            Self::Generate => "/api/generate",
+            Self::Chat => "/api/chat",
+        }
+    }
+}
+
+impl FromStr for OllamaApi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "generate" => Ok(Self::Generate),
+            "chat" => Ok(Self::Chat),
+            other => Err(format!("未知的 Ollama 接口: {}", other)),
+        }
+    }
+}
+
+/// 合并模式下 LLM 对一句识别文本的判断
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombinedVerdict {
+    /// 是有意义的语音，而不是噪声幻觉、重复填充词或无意义片段
+    pub meaningful: bool,
+    pub emotion: String,
+}
+
+/// 合并模式要求 LLM 输出的 JSON
+#[derive(Debug, Deserialize)]
+struct CombinedResponse {
+    meaningful: bool,
+    emotion: String,
+}
+
+/// 一次情绪分析的提示词，generate 接口只使用 `user`
+#[derive(Debug)]
+struct EmotionPrompt {
+    system: Option<String>,
+    user: String,
+}
+
+impl EmotionPrompt {
+    /// 合并相同请求时使用的键
+    fn key(&self) -> String {
+        match &self.system {
+            Some(system) => format!("{}\n{}", system, self.user),
+            None => self.user.clone(),
+        }
+    }
+}
+
+/// 情绪分析请求错误
+#[derive(Debug, Error)]
+pub enum EmotionError {
+    /// 无法建立连接或传输中断
+    #[error("无法连接 Ollama: {0}")]
+    Connect(#[source] reqwest::Error),
+    /// 请求超时
+    #[error("Ollama 请求超时: {0}")]
+    Timeout(#[source] reqwest::Error),
+    /// Ollama 返回非成功状态码
+    #[error("Ollama 返回错误状态: {0}")]
+    Status(StatusCode),
+    /// 响应体无法解析
+    #[error("无法解析 Ollama 响应: {0}")]
+    Parse(#[source] reqwest::Error),
+    /// 熔断中，未发送请求
+    #[error("Ollama 熔断中")]
+    CircuitOpen,
+    /// 共享的相同请求失败，只保留错误描述
+    #[error("共享请求失败: {0}")]
+    Shared(String),
+}
+
+impl EmotionError {
+    /// 是否值得重试：连接失败、超时和服务端错误可重试，解析错误和客户端错误不可
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Connect(_) | Self::Timeout(_) => true,
+            Self::Status(status) => status.is_server_error(),
+            Self::Parse(_) | Self::CircuitOpen | Self::Shared(_) => false,
+        }
+    }
+
+    /// 是否说明后端不可用而应计入熔断：与可重试的错误相同
+    ///
+    /// 解析错误和 4xx 说明 Ollama 仍在响应，问题出在请求或模型输出上，不计入。
+    pub fn is_backend_failure(&self) -> bool {
+        self.is_retryable()
+    }
+}
+
+impl From<reqwest::Error> for EmotionError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e)
+        } else if e.is_decode() {
+            Self::Parse(e)
+        } else {
+            Self::Connect(e)
+        }
+    }
+}
+
+/// 熔断器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// 正常请求
+    Closed,
+    /// 熔断中，直接使用默认情绪
+    Open,
+    /// 冷却结束，放行一次探测请求
+    HalfOpen,
+}
+
+/// 熔断器对外暴露的快照
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+}
+
+/// Ollama 后端熔断器：连续失败达到阈值后在冷却期内短路请求
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerInner>,
+    /// 熔断打开时为 true，恢复时为 false，供连接订阅
+    degraded: watch::Sender<bool>,
+}
+
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+            }),
+            degraded: watch::Sender::new(false),
+        }
+    }
+
+    /// 判断是否允许发起请求，允许时返回放行凭证，请求结束后由凭证记录结果
+    fn try_acquire(&self) -> Option<BreakerPermit<'_>> {
+        let mut inner = self.inner.lock().unwrap();
+        let probe = match inner.state {
+            BreakerState::Closed => false,
+            BreakerState::HalfOpen => return None,
+            BreakerState::Open => {
+                let cooled_down = inner
+                    .opened_at
+                    .is_some_and(|opened_at| opened_at.elapsed() >= self.cooldown);
+                if !cooled_down {
+                    return None;
+                }
+                info!("Ollama 熔断冷却结束，发送探测请求");
+                inner.state = BreakerState::HalfOpen;
+                true
+            }
+        };
+        Some(BreakerPermit {
+            breaker: self,
+            probe,
+            finished: false,
+        })
+    }
+
+    /// 记录一次成功请求
+    fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state != BreakerState::Closed {
+            info!("✅ Ollama 已恢复，关闭熔断");
+        }
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        self.set_degraded(false);
+    }
+
+    /// 记录一次失败请求
+    fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+
+        let should_open =
+            inner.state == BreakerState::HalfOpen || inner.consecutive_failures >= self.threshold;
+        if should_open && inner.state != BreakerState::Open {
+            warn!(
+                "Ollama 连续失败 {} 次，熔断 {:?}",
+                inner.consecutive_failures, self.cooldown
+            );
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+            self.set_degraded(true);
+        }
+    }
+
+    /// 仅在降级状态变化时通知订阅者
+    fn set_degraded(&self, degraded: bool) {
+        self.degraded.send_if_modified(|current| {
+            let changed = *current != degraded;
+            *current = degraded;
+            changed
+        });
+    }
+
+    fn status(&self) -> BreakerStatus {
+        let inner = self.inner.lock().unwrap();
+        BreakerStatus {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+        }
+    }
+}
+
+/// 熔断器放行的一次请求
+///
+/// 半开状态下只放行一个探测请求，其结果决定熔断器关闭还是重新打开。探测请求在得出结果前
+/// 被丢弃（客户端断开、被新语句打断）时按失败处理，熔断器重新打开并在冷却后再次探测，
+/// 不会一直停留在半开状态。
+struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    /// 半开状态下的探测请求
+    probe: bool,
+    finished: bool,
+}
+
+impl BreakerPermit<'_> {
+    /// 按请求结果更新熔断器：只有后端不可用的错误计入失败
+    fn finish<T>(mut self, result: &Result<T, EmotionError>) {
+        self.finished = true;
+        match result {
+            Ok(_) => self.breaker.record_success(),
+            Err(e) if e.is_backend_failure() => self.breaker.record_failure(),
+            // Ollama 有响应，探测说明后端已恢复
+            Err(EmotionError::Parse(_) | EmotionError::Status(_)) if self.probe => {
+                self.breaker.record_success()
+            }
+            // 共享的请求失败，无法判断原因，探测按失败处理
+            Err(_) if self.probe => self.breaker.record_failure(),
+            Err(_) => {}
+        }
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.finished {
+            warn!("Ollama 探测请求被取消，按失败处理");
+            self.breaker.record_failure();
+        }
+    }
+}
+
+/// 单个在途请求的结果，错误只保留描述以便共享
+type FlightOutcome = Option<Result<String, String>>;
+
+/// 合并并发的相同请求：同一提示词只发送一次，后到的调用者等待首个请求的结果
+#[derive(Default)]
+struct SingleFlight {
+    in_flight: Mutex<HashMap<String, watch::Receiver<FlightOutcome>>>,
+}
+
+impl SingleFlight {
+    async fn run<F, Fut>(&self, key: &str, request: F) -> Result<String, EmotionError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, EmotionError>>,
+    {
+        // 查找和登记在同一次加锁内完成，同一提示词只会有一个首个请求
+        let (tx, rx) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.entry(key.to_string()) {
+                Entry::Occupied(entry) => (None, entry.get().clone()),
+                Entry::Vacant(entry) => {
+                    let (tx, rx) = watch::channel(None);
+                    entry.insert(rx.clone());
+                    (Some(tx), rx)
+                }
+            }
+        };
+        let Some(tx) = tx else {
+            return Self::wait(rx, request).await;
+        };
+        let _guard = FlightGuard {
+            flights: self,
+            key,
+            rx,
+        };
+
+        let result = request().await;
+        let _ = tx.send(Some(
+            result
+                .as_ref()
+                .map(Clone::clone)
+                .map_err(ToString::to_string),
+        ));
+        result
+    }
+
+    /// 等待首个请求的结果，首个请求被取消时改为自行请求
+    async fn wait<F, Fut>(
+        mut rx: watch::Receiver<FlightOutcome>,
+        request: F,
+    ) -> Result<String, EmotionError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, EmotionError>>,
+    {
+        let outcome = rx
+            .wait_for(Option::is_some)
+            .await
+            .map(|outcome| outcome.clone());
+        match outcome {
+            Ok(Some(result)) => {
+                METRICS
+                    .emotion_single_flight_hits_total
+                    .fetch_add(1, Ordering::Relaxed);
+                result.map_err(EmotionError::Shared)
+            }
+            _ => request().await,
+        }
+    }
+}
+
+/// 首个请求结束或被取消时移除自己登记的在途记录
+struct FlightGuard<'a> {
+    flights: &'a SingleFlight,
+    key: &'a str,
+    rx: watch::Receiver<FlightOutcome>,
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.flights.in_flight.lock().unwrap();
+        if in_flight
+            .get(self.key)
+            .is_some_and(|rx| rx.same_channel(&self.rx))
+        {
+            in_flight.remove(self.key);
+        }
+    }
+}
+
+/// 情绪分析请求的目标
+#[derive(Debug)]
+struct OllamaEndpoint {
+    model: String,
+    api: OllamaApi,
+    /// 完整的接口地址
+    url: String,
+}
+
+impl OllamaEndpoint {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            model: config.ollama_model.clone(),
+            api: config.ollama_api,
+            url: format!("{}{}", config.ollama_host, config.ollama_api.path()),
+        }
+    }
+}
+
 pub struct EmotionAnalyzer {
     client: Client,
-    model_name: String,
+    /// 重新加载配置时整体替换
+    endpoint: RwLock<Arc<OllamaEndpoint>>,
     valid_emotions: &'static [&'static str],
-    api_base_url: String,
+    selection: EmotionSelection,
+    retries: u32,
+    /// 单次请求的超时
+    timeout: Duration,
+    breaker: CircuitBreaker,
+    /// `None` 时不合并相同请求
+    single_flight: Option<SingleFlight>,
+    /// 送入提示词的文本最大字符数，`0` 表示不限制
+    max_text_chars: usize,
 }
 
 impl EmotionAnalyzer {
     /// 创建新的情绪分析器
-    pub async fn new() -> Self {
-        let ollama_host =
-            std::env::var("OLLAMA_HOST").unwrap_or_else(|_| "http://ollama:11434".to_string());
+    pub async fn new(config: &Config) -> Self {
+        let analyzer = Self::from_config(config);
+        analyzer.test_connection().await;
+        analyzer
+    }
 
-        let analyzer = Self {
+    /// 根据配置构建分析器，不测试连接
+    pub fn from_config(config: &Config) -> Self {
+        Self {
             client: Client::new(),
-            model_name: "qwen2.5:1.5b".to_string(),
-            valid_emotions: &[
-                "joy", "anger", "sadness", "fear", "calm", "neutral", "sleep",
-            ],
-            api_base_url: format!("{}/api/generate", ollama_host),
-        };
+            endpoint: RwLock::new(Arc::new(OllamaEndpoint::from_config(config))),
+            valid_emotions: VALID_EMOTIONS,
+            selection: config.emotion_selection,
+            retries: config.ollama_retries,
+            timeout: config.ollama_timeout,
+            breaker: CircuitBreaker::new(config.breaker_threshold, config.breaker_cooldown),
+            single_flight: config.emotion_single_flight.then(SingleFlight::default),
+            max_text_chars: config.emotion_max_chars,
+        }
+    }
 
-        analyzer.test_connection().await;
-        analyzer
+    /// 使用的 Ollama 模型名
+    pub fn model_name(&self) -> String {
+        self.endpoint().model.clone()
     }
 
-    /// 分析文本情绪
-    pub async fn analyze(&self, text: &str) -> String {
-        let prompt = self.build_emotion_prompt(text);
+    /// 当前的 Ollama 地址、接口和模型
+    fn endpoint(&self) -> Arc<OllamaEndpoint> {
+        self.endpoint
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 
-        match self.send_ollama_request(&prompt).await {
-            Ok(response) => self.validate_emotion_response(&response),
+    /// 按新配置切换 Ollama 地址、接口和模型，之后发出的请求生效
+    pub fn set_endpoint(&self, config: &Config) {
+        *self
+            .endpoint
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Arc::new(OllamaEndpoint::from_config(config));
+    }
+
+    /// 分析文本情绪，`language` 为文本语言提示，用于选择提示词语言
+    pub async fn analyze(&self, text: &str, language: Option<&str>) -> String {
+        self.analyze_with_context(text, language, &[]).await
+    }
+
+    /// 结合此前的对话分析文本情绪，`context` 按时间顺序排列，只作为参考不参与分类
+    pub async fn analyze_with_context(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        context: &[String],
+    ) -> String {
+        match self.try_analyze(text, language, context).await {
+            Ok(emotion) => emotion,
+            Err(EmotionError::CircuitOpen) => "neutral".to_string(),
             Err(e) => {
                 warn!("情绪分析失败: {}, 使用默认情绪", e);
                 "neutral".to_string()
@@ -54,82 +521,739 @@ impl EmotionAnalyzer {
         }
     }
 
+    /// 分析文本情绪，失败时返回错误而不是默认情绪
+    pub async fn try_analyze(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        context: &[String],
+    ) -> Result<String, EmotionError> {
+        let prompt = self.build_emotion_prompt(text, language, context, false);
+        let response = self.request(&prompt).await?;
+        Ok(self.validate_emotion_response(&response))
+    }
+
+    /// 一次请求同时判断文本是否有意义并分析情绪
+    ///
+    /// 请求失败时不丢弃语音：视为有意义，情绪为 `neutral`。
+    pub async fn analyze_combined(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        context: &[String],
+    ) -> CombinedVerdict {
+        match self.try_analyze_combined(text, language, context).await {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                if !matches!(e, EmotionError::CircuitOpen) {
+                    warn!("合并分析失败: {}, 使用默认情绪", e);
+                }
+                CombinedVerdict {
+                    meaningful: true,
+                    emotion: "neutral".to_string(),
+                }
+            }
+        }
+    }
+
+    /// 合并分析，失败时返回错误
+    pub async fn try_analyze_combined(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        context: &[String],
+    ) -> Result<CombinedVerdict, EmotionError> {
+        let prompt = self.build_emotion_prompt(text, language, context, true);
+        let response = self.request(&prompt).await?;
+        Ok(self.validate_combined_response(&response))
+    }
+
+    /// 经熔断器放行后发送请求（开启时合并相同请求），返回 LLM 的原始输出
+    ///
+    /// 请求结束后按结果更新熔断器；请求中途被丢弃时由放行凭证处理。
+    async fn request(&self, prompt: &EmotionPrompt) -> Result<String, EmotionError> {
+        let permit = self
+            .breaker
+            .try_acquire()
+            .ok_or(EmotionError::CircuitOpen)?;
+        let result = match &self.single_flight {
+            Some(flights) => {
+                flights
+                    .run(&prompt.key(), || self.send_with_retry(prompt))
+                    .await
+            }
+            None => self.send_with_retry(prompt).await,
+        };
+        permit.finish(&result);
+        result
+    }
+
+    /// 熔断器当前状态
+    pub fn breaker_status(&self) -> BreakerStatus {
+        self.breaker.status()
+    }
+
+    /// 订阅情绪分析降级状态变化
+    pub fn subscribe_degraded(&self) -> watch::Receiver<bool> {
+        self.breaker.degraded.subscribe()
+    }
+
+    /// 带有限次重试的请求
+    async fn send_with_retry(&self, prompt: &EmotionPrompt) -> Result<String, EmotionError> {
+        let mut attempt = 0;
+        loop {
+            match self.send_ollama_request(prompt).await {
+                Ok(response) => return Ok(response),
+                Err(e) if attempt >= self.retries || !e.is_retryable() => return Err(e),
+                Err(e) => warn!("情绪分析请求失败: {}, 第 {} 次重试", e, attempt + 1),
+            }
+            attempt += 1;
+            tokio::time::sleep(Duration::from_millis(200 * attempt as u64)).await;
+        }
+    }
+
+    /// 启动定期探测 Ollama 的后台任务，结果计入熔断器
+    ///
+    /// 启动时 Ollama 尚未就绪、或重启后恢复时，不必等待真实请求即可关闭熔断，
+    /// `/health` 也能反映当前状态。可达性变化时记录日志。
+    pub fn spawn_health_probe(self: &Arc<Self>, interval: Duration) {
+        let analyzer = self.clone();
+        tokio::spawn(async move {
+            let mut reachable = None;
+            loop {
+                tokio::time::sleep(interval).await;
+                let result = analyzer.send_test_request().await;
+                match &result {
+                    Ok(()) => analyzer.breaker.record_success(),
+                    Err(e) if e.is_backend_failure() => analyzer.breaker.record_failure(),
+                    Err(_) => {}
+                }
+                match (reachable, &result) {
+                    (Some(false) | None, Ok(())) => info!("健康探测: Ollama 可达"),
+                    (Some(true) | None, Err(e)) => warn!("健康探测: Ollama 不可达: {}", e),
+                    _ => {}
+                }
+                reachable = Some(result.is_ok());
+            }
+        });
+    }
+
     /// 测试与Ollama的连接
     async fn test_connection(&self) {
         match self.send_test_request().await {
-            Ok(_) => info!("✅ Ollama {} 模型连接成功", self.model_name),
+            Ok(_) => info!("✅ Ollama {} 模型连接成功", self.model_name()),
             Err(e) => {
                 error!("❌ Ollama 连接失败: {}", e);
-                error!("💡 提示: 运行 'ollama run {}' 来安装模型", self.model_name);
+                error!(
+                    "💡 提示: 运行 'ollama run {}' 来安装模型",
+                    self.model_name()
+                );
             }
         }
     }
 
     /// 发送测试请求
-    async fn send_test_request(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let request = OllamaRequest {
-            model: self.model_name.clone(),
-            prompt: "测试".to_string(),
-            stream: false,
+    pub async fn send_test_request(&self) -> Result<(), EmotionError> {
+        let prompt = EmotionPrompt {
+            system: None,
+            user: "测试".to_string(),
         };
-
         let response = self
-            .client
-            .post(&self.api_base_url)
-            .json(&request)
-            .timeout(Duration::from_secs(10))
-            .send()
+            .post(&self.endpoint(), &prompt, Duration::from_secs(10))
             .await?;
 
         if response.status().is_success() {
             Ok(())
         } else {
-            Err(format!("Ollama 返回错误状态: {}", response.status()).into())
+            Err(EmotionError::Status(response.status()))
         }
     }
 
-    /// 构建情绪分析提示词
-    fn build_emotion_prompt(&self, text: &str) -> String {
-        format!(
-            "Analyze the sentiment of the following text. ONLY output ONE word, strictly from this list: {:?}. Do NOT output anything else.\n\nText: {}\n\nSentiment:",
-            self.valid_emotions, text
-        )
+    /// 构建情绪分析提示词，chat 接口下指令单独作为 system 消息
+    ///
+    /// `combined` 为 true 时要求 LLM 同时判断文本是否有意义，以 JSON 输出。
+    fn build_emotion_prompt(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        context: &[String],
+        combined: bool,
+    ) -> EmotionPrompt {
+        let is_chinese = language.is_some_and(|language| language.to_lowercase().starts_with("zh"));
+        let text = self.truncate_text(text);
+
+        let preamble = match (context.is_empty(), is_chinese) {
+            (true, _) => String::new(),
+            (false, true) => format!(
+                "以下是此前的对话，仅供理解语境，不要对其分类：\n<context>\n{}\n</context>\n\n",
+                context.join("\n")
+            ),
+            (false, false) => format!(
+                "Earlier conversation, for context only. Do NOT classify it:\n<context>\n{}\n</context>\n\n",
+                context.join("\n")
+            ),
+        };
+
+        let (instruction, text_label, answer_label) = match (combined, is_chinese) {
+            (true, true) => (
+                format!(
+                    "判断下面文本是否是有意义的语音转写（而不是噪声产生的幻觉、重复的填充词或无意义的片段），并分析其情绪。只输出一行 JSON：{{\"meaningful\": true 或 false, \"emotion\": \"情绪\"}}，情绪必须严格从以下列表中选择：{:?}。不要输出任何其他内容。",
+                    self.valid_emotions
+                ),
+                "文本：",
+                "JSON：",
+            ),
+            (true, false) => (
+                format!(
+                    "Decide whether the following text is a meaningful speech transcript (not a hallucination from noise, repeated filler words or a meaningless fragment) and analyze its sentiment. ONLY output one line of JSON: {{\"meaningful\": true or false, \"emotion\": \"<label>\"}}, where the label is strictly from this list: {:?}. Do NOT output anything else.",
+                    self.valid_emotions
+                ),
+                "Text: ",
+                "JSON:",
+            ),
+            (false, true) => (
+                format!(
+                    "分析下面文本的情绪。只输出一个英文单词，必须严格从以下列表中选择：{:?}。不要输出任何其他内容。",
+                    self.valid_emotions
+                ),
+                "文本：",
+                "情绪：",
+            ),
+            (false, false) => (
+                format!(
+                    "Analyze the sentiment of the following text. ONLY output ONE word, strictly from this list: {:?}. Do NOT output anything else.",
+                    self.valid_emotions
+                ),
+                "Text: ",
+                "Sentiment:",
+            ),
+        };
+
+        match self.endpoint().api {
+            OllamaApi::Generate => EmotionPrompt {
+                system: None,
+                user: format!(
+                    "{}{}\n\n{}{}\n\n{}",
+                    preamble, instruction, text_label, text, answer_label
+                ),
+            },
+            OllamaApi::Chat => EmotionPrompt {
+                system: Some(instruction),
+                user: format!("{}{}{}", preamble, text_label, text),
+            },
+        }
     }
 
-    /// 发送Ollama请求
-    async fn send_ollama_request(
+    /// 过长的文本只取开头部分分析，情绪通常从一部分就能判断，识别结果本身不受影响
+    fn truncate_text<'a>(&self, text: &'a str) -> &'a str {
+        if self.max_text_chars == 0 {
+            return text;
+        }
+        let (kept, truncated) = text::truncate_at_sentence(text, self.max_text_chars);
+        if truncated {
+            info!(
+                target: logging::SAMPLED,
+                "情绪分析文本过长 ({} 字)，截断为 {} 字",
+                text.chars().count(),
+                kept.chars().count()
+            );
+        }
+        kept
+    }
+
+    /// 按配置的接口格式发送请求
+    async fn post(
         &self,
-        prompt: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let request = OllamaRequest {
-            model: self.model_name.clone(),
-            prompt: prompt.to_string(),
-            stream: false,
+        endpoint: &OllamaEndpoint,
+        prompt: &EmotionPrompt,
+        timeout: Duration,
+    ) -> Result<reqwest::Response, EmotionError> {
+        let request = self.client.post(&endpoint.url).timeout(timeout);
+        let request = match endpoint.api {
+            OllamaApi::Generate => request.json(&OllamaRequest {
+                model: endpoint.model.clone(),
+                prompt: prompt.user.clone(),
+                stream: false,
+            }),
+            OllamaApi::Chat => {
+                let system = prompt.system.iter().map(|content| ChatMessage {
+                    role: "system".to_string(),
+                    content: content.clone(),
+                });
+                let user = ChatMessage {
+                    role: "user".to_string(),
+                    content: prompt.user.clone(),
+                };
+                request.json(&OllamaChatRequest {
+                    model: endpoint.model.clone(),
+                    messages: system.chain([user]).collect(),
+                    stream: false,
+                })
+            }
         };
+        Ok(request.send().await?)
+    }
 
-        let response = self
-            .client
-            .post(&self.api_base_url)
-            .json(&request)
-            .timeout(Duration::from_secs(5))
-            .send()
-            .await?;
+    /// 发送Ollama请求
+    async fn send_ollama_request(&self, prompt: &EmotionPrompt) -> Result<String, EmotionError> {
+        let endpoint = self.endpoint();
+        let response = self.post(&endpoint, prompt, self.timeout).await?;
+
+        if !response.status().is_success() {
+            return Err(EmotionError::Status(response.status()));
+        }
 
-        let ollama_resp: OllamaResponse = response.json().await?;
-        Ok(ollama_resp.response)
+        match endpoint.api {
+            OllamaApi::Generate => {
+                let ollama_resp: OllamaResponse = response.json().await?;
+                Ok(ollama_resp.response)
+            }
+            OllamaApi::Chat => {
+                let ollama_resp: OllamaChatResponse = response.json().await?;
+                Ok(ollama_resp.message.content)
+            }
+        }
     }
 
     /// 验证并清理情绪响应
     fn validate_emotion_response(&self, response: &str) -> String {
-        let emotion = response.trim().to_lowercase();
+        match parse_emotion(response, self.valid_emotions, self.selection) {
+            Some(emotion) => emotion.to_string(),
+            None => {
+                info!(
+                    target: logging::SAMPLED,
+                    "LLM 返回了非预期的情绪: {}, 使用 neutral",
+                    response.trim()
+                );
+                "neutral".to_string()
+            }
+        }
+    }
+
+    /// 解析合并分析的 JSON；无法解析时视为有意义，情绪按普通输出提取
+    fn validate_combined_response(&self, response: &str) -> CombinedVerdict {
+        if let Some(verdict) = parse_combined(response, self.valid_emotions, self.selection) {
+            return verdict;
+        }
+        info!(
+            target: logging::SAMPLED,
+            "LLM 返回了无法解析的合并分析结果: {}",
+            response.trim()
+        );
+        CombinedVerdict {
+            meaningful: true,
+            emotion: self.validate_emotion_response(response),
+        }
+    }
+}
+
+/// 从合并分析的 LLM 输出中提取 JSON 判断，允许 JSON 前后有多余文字
+///
+/// JSON 无法解析时返回 `None`；情绪不在列表中时为 `neutral`。
+pub fn parse_combined(
+    response: &str,
+    valid_emotions: &[&'static str],
+    selection: EmotionSelection,
+) -> Option<CombinedVerdict> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    let parsed: CombinedResponse = serde_json::from_str(response.get(start..=end)?).ok()?;
+    Some(CombinedVerdict {
+        meaningful: parsed.meaningful,
+        emotion: parse_emotion(&parsed.emotion, valid_emotions, selection)
+            .unwrap_or("neutral")
+            .to_string(),
+    })
+}
+
+/// 情绪到客户端动作（动画 ID 等）的映射加载失败的原因
+#[derive(Debug, Error)]
+pub enum ActionMapError {
+    #[error("无法读取动作映射文件: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("动作映射文件格式错误: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("以下情绪没有对应的动作: {}", .0.join(", "))]
+    Missing(Vec<&'static str>),
+}
+
+/// 情绪到客户端动作的映射，由服务端统一决定，保证多个客户端表现一致
+#[derive(Debug, Clone)]
+pub struct EmotionActions {
+    actions: HashMap<String, String>,
+}
 
-        for &valid_emotion in self.valid_emotions {
-            if emotion.contains(valid_emotion) {
-                return valid_emotion.to_string();
+impl EmotionActions {
+    /// 从 JSON 对象文件（如 `{"joy": "wave", ...}`）加载，每个有效情绪都必须有映射
+    pub fn load(path: &str) -> Result<Self, ActionMapError> {
+        let content = std::fs::read_to_string(path)?;
+        let actions: HashMap<String, String> = serde_json::from_str(&content)?;
+
+        let missing: Vec<&'static str> = VALID_EMOTIONS
+            .iter()
+            .copied()
+            .filter(|emotion| !actions.contains_key(*emotion))
+            .collect();
+        if !missing.is_empty() {
+            return Err(ActionMapError::Missing(missing));
+        }
+        for emotion in actions.keys() {
+            if !VALID_EMOTIONS.contains(&emotion.as_str()) {
+                warn!("动作映射中的情绪 {} 不是有效情绪，将被忽略", emotion);
             }
         }
 
-        info!("LLM 返回了非预期的情绪: {}, 使用 neutral", emotion);
-        "neutral".to_string()
+        Ok(Self { actions })
+    }
+
+    /// 情绪对应的动作
+    pub fn action_for(&self, emotion: &str) -> Option<&str> {
+        self.actions.get(emotion).map(String::as_str)
+    }
+}
+
+/// 内部情绪标签到客户端标签的映射，未映射的标签原样输出
+///
+/// 提示词和内部处理（平滑、动作映射、存储）始终使用内部标签，只在发给客户端时替换。
+#[derive(Debug, Clone, Default)]
+pub struct EmotionLabels {
+    labels: HashMap<String, String>,
+}
+
+impl EmotionLabels {
+    /// 解析 `joy=happy,sadness=sad` 形式的映射，内部标签不区分大小写，客户端标签保留原样
+    pub fn parse(spec: &str) -> Self {
+        let labels: HashMap<String, String> = spec
+            .split(',')
+            .filter_map(|entry| {
+                let (internal, client) = entry.split_once('=')?;
+                let (internal, client) = (internal.trim().to_lowercase(), client.trim());
+                if internal.is_empty() || client.is_empty() {
+                    return None;
+                }
+                Some((internal, client.to_string()))
+            })
+            .collect();
+        for internal in labels.keys() {
+            if !VALID_EMOTIONS.contains(&internal.as_str()) {
+                warn!("标签映射中的情绪 {} 不是有效情绪，将被忽略", internal);
+            }
+        }
+        Self { labels }
+    }
+
+    /// 把内部标签替换为客户端标签
+    pub fn apply(&self, emotion: &mut String) {
+        if let Some(label) = self.labels.get(emotion.as_str()) {
+            emotion.clone_from(label);
+        }
+    }
+}
+
+/// LLM 输出包含多个情绪标签时的选取规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmotionSelection {
+    /// 文本中最先出现的标签
+    First,
+    /// 文本中最后出现的标签
+    Last,
+    /// 最长的标签，等长时取最先出现的
+    Longest,
+}
+
+impl FromStr for EmotionSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "first" => Ok(Self::First),
+            "last" => Ok(Self::Last),
+            "longest" => Ok(Self::Longest),
+            other => Err(format!("未知的情绪选取规则: {}", other)),
+        }
+    }
+}
+
+/// 从 LLM 输出中提取情绪标签：忽略大小写和首尾空白，多个标签按 `selection` 选取
+///
+/// 结果只取决于标签在文本中的位置和长度，与 `valid_emotions` 的顺序无关。
+/// 不依赖分析器状态和网络，便于单独验证解析规则。
+pub fn parse_emotion(
+    response: &str,
+    valid_emotions: &[&'static str],
+    selection: EmotionSelection,
+) -> Option<&'static str> {
+    let response = response.trim().to_lowercase();
+    // 每个标签的所有出现位置
+    let matches = valid_emotions.iter().flat_map(|&emotion| {
+        response
+            .match_indices(emotion)
+            .map(move |(position, _)| (position, emotion))
+    });
+
+    let selected =
+        match selection {
+            EmotionSelection::First => matches
+                .min_by_key(|&(position, emotion)| (position, std::cmp::Reverse(emotion.len()))),
+            EmotionSelection::Last => {
+                matches.max_by_key(|&(position, emotion)| (position, emotion.len()))
+            }
+            EmotionSelection::Longest => matches
+                .min_by_key(|&(position, emotion)| (std::cmp::Reverse(emotion.len()), position)),
+        };
+    selected.map(|(_, emotion)| emotion)
+}
+
+/// 跨语句平滑情绪标签，避免界面动画频繁跳变
+///
+/// 只有新情绪连续出现 `window` 次才替换当前上报的情绪；`window` 不大于 1 时不做平滑。
+#[derive(Debug)]
+pub struct EmotionSmoother {
+    window: usize,
+    reported: Option<String>,
+    /// 尚未生效的候选情绪及其连续出现次数
+    candidate: Option<(String, usize)>,
+}
+
+impl EmotionSmoother {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            reported: None,
+            candidate: None,
+        }
+    }
+
+    /// 输入本句情绪，返回应上报的情绪
+    pub fn update(&mut self, emotion: String) -> String {
+        if self.window <= 1 {
+            return emotion;
+        }
+
+        let Some(reported) = &self.reported else {
+            self.reported = Some(emotion.clone());
+            return emotion;
+        };
+
+        if *reported == emotion {
+            self.candidate = None;
+            return emotion;
+        }
+
+        let count = match &mut self.candidate {
+            Some((label, count)) if *label == emotion => {
+                *count += 1;
+                *count
+            }
+            _ => {
+                self.candidate = Some((emotion.clone(), 1));
+                1
+            }
+        };
+
+        if count >= self.window {
+            self.candidate = None;
+            self.reported = Some(emotion.clone());
+            return emotion;
+        }
+
+        reported.clone()
+    }
+}
+
+/// 会话中最近的若干句识别文本，作为情绪分析的语境
+#[derive(Debug)]
+pub struct ConversationContext {
+    max_turns: usize,
+    turns: VecDeque<String>,
+}
+
+impl ConversationContext {
+    /// `max_turns` 为 0 时不保留上下文
+    pub fn new(max_turns: usize) -> Self {
+        Self {
+            max_turns,
+            turns: VecDeque::with_capacity(max_turns),
+        }
+    }
+
+    /// 当前上下文，按时间顺序排列
+    pub fn turns(&self) -> &[String] {
+        self.turns.as_slices().0
+    }
+
+    /// 追加一句文本，超出句数或字符数上限时丢弃最早的语句
+    pub fn push(&mut self, text: &str) {
+        if self.max_turns == 0 {
+            return;
+        }
+
+        self.turns
+            .push_back(text::truncate_chars(text, MAX_CONTEXT_CHARS).to_string());
+        while self.turns.len() > self.max_turns || self.total_chars() > MAX_CONTEXT_CHARS {
+            self.turns.pop_front();
+        }
+        self.turns.make_contiguous();
+    }
+
+    /// 撤回最近追加的一句，该句被合并后重新识别的结果取代时调用
+    pub fn pop_last(&mut self) {
+        self.turns.pop_back();
+    }
+
+    fn total_chars(&self) -> usize {
+        self.turns.iter().map(|turn| turn.chars().count()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn concurrent_identical_requests_share_one_call() {
+        let flights = SingleFlight::default();
+        let calls = AtomicUsize::new(0);
+        let request = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok("高兴".to_string())
+        };
+
+        let (first, second, third) = tokio::join!(
+            flights.run("prompt", request),
+            flights.run("prompt", request),
+            flights.run("prompt", request),
+        );
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        for result in [first, second, third] {
+            assert_eq!(result.unwrap(), "高兴");
+        }
+        assert!(flights.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn dropped_probe_reopens_breaker() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure();
+        assert_eq!(breaker.status().state, BreakerState::Open);
+
+        let probe = breaker.try_acquire().expect("冷却结束后应放行探测");
+        assert_eq!(breaker.status().state, BreakerState::HalfOpen);
+        assert!(breaker.try_acquire().is_none());
+        drop(probe);
+
+        assert_eq!(breaker.status().state, BreakerState::Open);
+        assert!(breaker.try_acquire().is_some());
+    }
+
+    #[test]
+    fn client_errors_do_not_trip_breaker() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        let permit = breaker.try_acquire().unwrap();
+        permit.finish::<()>(&Err(EmotionError::Status(StatusCode::BAD_REQUEST)));
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+
+        let permit = breaker.try_acquire().unwrap();
+        permit.finish::<()>(&Err(EmotionError::Status(StatusCode::BAD_GATEWAY)));
+        assert_eq!(breaker.status().state, BreakerState::Open);
+    }
+
+    /// 按三种规则解析，结果依次为 First、Last、Longest
+    fn parse_all(response: &str) -> [Option<&'static str>; 3] {
+        [
+            EmotionSelection::First,
+            EmotionSelection::Last,
+            EmotionSelection::Longest,
+        ]
+        .map(|selection| parse_emotion(response, VALID_EMOTIONS, selection))
+    }
+
+    #[test]
+    fn parses_single_label_ignoring_case_and_whitespace() {
+        assert_eq!(parse_all("  JOY\n"), [Some("joy"); 3]);
+        assert_eq!(parse_all("Sentiment: calm."), [Some("calm"); 3]);
+    }
+
+    #[test]
+    fn selects_among_comma_separated_labels() {
+        assert_eq!(
+            parse_all("joy, anger"),
+            [Some("joy"), Some("anger"), Some("anger")]
+        );
+    }
+
+    #[test]
+    fn selects_among_space_separated_labels() {
+        assert_eq!(
+            parse_all("sadness anger"),
+            [Some("sadness"), Some("anger"), Some("sadness")]
+        );
+    }
+
+    #[test]
+    fn duplicated_labels_use_their_positions() {
+        assert_eq!(
+            parse_all("joy joy fear joy"),
+            [Some("joy"), Some("joy"), Some("fear")]
+        );
+        // 等长时 Longest 取最先出现的
+        assert_eq!(
+            parse_all("calm fear calm"),
+            [Some("calm"), Some("calm"), Some("calm")]
+        );
+    }
+
+    #[test]
+    fn unknown_and_empty_outputs_have_no_label() {
+        for response in ["happy", "excited!", "", "   \n", "，"] {
+            assert_eq!(parse_all(response), [None; 3], "{:?}", response);
+        }
+    }
+
+    #[test]
+    fn result_does_not_depend_on_label_order() {
+        let reversed: Vec<&'static str> = VALID_EMOTIONS.iter().rev().copied().collect();
+        for selection in [
+            EmotionSelection::First,
+            EmotionSelection::Last,
+            EmotionSelection::Longest,
+        ] {
+            assert_eq!(
+                parse_emotion("fear, sleep, joy", &reversed, selection),
+                parse_emotion("fear, sleep, joy", VALID_EMOTIONS, selection)
+            );
+        }
+    }
+
+    #[test]
+    fn parses_selection_rule() {
+        assert_eq!("LAST".parse(), Ok(EmotionSelection::Last));
+        assert_eq!("longest".parse(), Ok(EmotionSelection::Longest));
+        assert!("middle".parse::<EmotionSelection>().is_err());
+    }
+
+    #[test]
+    fn combined_output_applies_selection_to_emotion() {
+        let response = r#"好的：{"meaningful": false, "emotion": "joy, anger"}"#;
+        let verdict = parse_combined(response, VALID_EMOTIONS, EmotionSelection::Last).unwrap();
+        assert_eq!(
+            verdict,
+            CombinedVerdict {
+                meaningful: false,
+                emotion: "anger".to_string(),
+            }
+        );
+
+        let verdict = parse_combined(
+            r#"{"meaningful": true, "emotion": "happy"}"#,
+            VALID_EMOTIONS,
+            EmotionSelection::First,
+        );
+        assert_eq!(verdict.unwrap().emotion, "neutral");
+        assert!(parse_combined("joy", VALID_EMOTIONS, EmotionSelection::First).is_none());
     }
 }
@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::speech::{Recognizer, RecognizerOptions, SpeechRecognizer};
+
+/// 识别延迟指数滑动平均的平滑系数
+const LATENCY_EWMA_ALPHA: f64 = 0.3;
+
+/// 负载过高时把默认语言的识别切换到预加载的较小模型
+///
+/// 主模型识别延迟的滑动平均超过阈值，或同时进行的识别数超过上限时降级；
+/// 降级持续 `hold` 后，若排队已回落则恢复主模型并重新测量延迟，仍然过载会再次降级。
+pub struct ModelFallback {
+    model: Arc<SpeechRecognizer>,
+    latency_threshold: Duration,
+    max_in_flight: usize,
+    hold: Duration,
+    in_flight: AtomicUsize,
+    state: Mutex<FallbackState>,
+}
+
+#[derive(Default)]
+struct FallbackState {
+    /// 主模型识别延迟的滑动平均（毫秒），恢复主模型时清零
+    latency_ewma_ms: f64,
+    degraded_since: Option<Instant>,
+}
+
+/// 一次进行中的识别，释放时计数减一
+pub struct InFlight<'a> {
+    in_flight: &'a AtomicUsize,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ModelFallback {
+    /// 按配置加载备用模型，未配置或加载失败时返回 None
+    pub fn from_config(config: &Config, options: &RecognizerOptions) -> Option<Self> {
+        let model_path = config.fallback_model.as_deref()?;
+        info!("加载备用 Whisper 模型: {}", model_path);
+        let model =
+            match SpeechRecognizer::try_load(model_path, &config.default_language, options.clone())
+            {
+                Ok(model) => model,
+                Err(e) => {
+                    warn!("备用 Whisper 模型加载失败，不启用降级: {}", e);
+                    return None;
+                }
+            };
+
+        Some(Self {
+            model: Arc::new(model),
+            latency_threshold: config.fallback_latency,
+            max_in_flight: config.fallback_queue_depth,
+            hold: config.fallback_hold,
+            in_flight: AtomicUsize::new(0),
+            state: Mutex::default(),
+        })
+    }
+
+    /// 登记一次识别，返回的守卫存活期间计入排队深度
+    pub fn begin(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight {
+            in_flight: &self.in_flight,
+        }
+    }
+
+    /// 选择本次识别使用的模型：降级期间同语言的识别改用备用模型
+    pub fn select(&self, primary: &Arc<dyn Recognizer>) -> Arc<dyn Recognizer> {
+        if primary.language() != self.model.language() {
+            return primary.clone();
+        }
+
+        let in_flight = self.in_flight.load(Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match state.degraded_since {
+            Some(since) if since.elapsed() >= self.hold && in_flight <= self.max_in_flight => {
+                info!("识别负载已回落（进行中 {}），恢复主模型", in_flight);
+                *state = FallbackState::default();
+                primary.clone()
+            }
+            Some(_) => self.model.clone(),
+            None if in_flight > self.max_in_flight => {
+                warn!(
+                    "同时进行的识别数 {} 超过 {}，切换到备用模型",
+                    in_flight, self.max_in_flight
+                );
+                state.degraded_since = Some(Instant::now());
+                self.model.clone()
+            }
+            None => primary.clone(),
+        }
+    }
+
+    /// 记录一次主模型识别的耗时，滑动平均超过阈值时降级
+    pub fn record(&self, recognizer: &dyn Recognizer, latency: Duration) {
+        if std::ptr::addr_eq(recognizer, Arc::as_ptr(&self.model))
+            || recognizer.language() != self.model.language()
+        {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.degraded_since.is_some() {
+            return;
+        }
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        state.latency_ewma_ms = if state.latency_ewma_ms == 0.0 {
+            latency_ms
+        } else {
+            LATENCY_EWMA_ALPHA * latency_ms + (1.0 - LATENCY_EWMA_ALPHA) * state.latency_ewma_ms
+        };
+
+        if state.latency_ewma_ms > self.latency_threshold.as_secs_f64() * 1000.0 {
+            warn!(
+                "识别延迟 {:.0}ms 超过 {:?}，切换到备用模型",
+                state.latency_ewma_ms, self.latency_threshold
+            );
+            state.degraded_since = Some(Instant::now());
+        }
+    }
+}
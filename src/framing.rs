@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// 帧头长度：1 字节操作码 + 2 字节大端长度
+const HEADER_LEN: usize = 3;
+
+/// 连接使用的消息编码，在 `Hello` 中协商
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// JSON 文本帧 + 裸 Opus 二进制帧
+    #[default]
+    Json,
+    /// 长度前缀的二进制帧
+    Binary,
+}
+
+/// 服务器→客户端 JSON 消息的压缩方式，在 `Hello` 中协商
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// 不压缩
+    #[default]
+    None,
+    /// 超过阈值的消息压缩为 zstd 二进制消息
+    Zstd,
+}
+
+/// zstd 压缩级别，较低级别压缩快，对短小的 JSON 压缩率已足够
+const ZSTD_LEVEL: i32 = 3;
+
+/// 压缩一条 JSON 消息
+pub fn compress(json: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::bulk::compress(json, ZSTD_LEVEL)
+}
+
+/// 二进制帧操作码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    /// 服务器→客户端：握手确认，负载为 1 字节协议版本
+    Hello = 0x01,
+    /// 客户端→服务器：一帧 Opus 音频
+    Audio = 0x02,
+    /// 服务器→客户端：UTF-8 识别文本
+    Transcript = 0x03,
+    /// 服务器→客户端：UTF-8 情绪标签
+    Emotion = 0x04,
+    /// 客户端→服务器：心跳，负载为空
+    Ping = 0x05,
+    /// 服务器→客户端：心跳响应，负载为空
+    Pong = 0x06,
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Hello),
+            0x02 => Ok(Self::Audio),
+            0x03 => Ok(Self::Transcript),
+            0x04 => Ok(Self::Emotion),
+            0x05 => Ok(Self::Ping),
+            0x06 => Ok(Self::Pong),
+            other => Err(FrameError::UnknownOpcode(other)),
+        }
+    }
+}
+
+/// 音频负载的编码，开启 `codec_header` 时由每条音频消息的首字节指定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AudioCodec {
+    /// 一帧 Opus 音频
+    Opus = 0x00,
+    /// 16kHz 单声道 PCM16LE
+    Pcm16 = 0x01,
+}
+
+impl TryFrom<u8> for AudioCodec {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Self::Opus),
+            0x01 => Ok(Self::Pcm16),
+            other => Err(FrameError::UnknownCodec(other)),
+        }
+    }
+}
+
+/// 拆出音频消息首字节的编码标识，返回编码和其后的音频数据
+pub fn split_codec(data: &[u8]) -> Result<(AudioCodec, &[u8]), FrameError> {
+    let (&codec, audio) = data.split_first().ok_or(FrameError::Truncated {
+        expected: 1,
+        actual: 0,
+    })?;
+    Ok((AudioCodec::try_from(codec)?, audio))
+}
+
+/// 二进制帧解析错误
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("未知的操作码: 0x{0:02x}")]
+    UnknownOpcode(u8),
+    #[error("未知的音频编码: 0x{0:02x}")]
+    UnknownCodec(u8),
+    #[error("帧不完整: 需要 {expected} 字节，剩余 {actual} 字节")]
+    Truncated { expected: usize, actual: usize },
+    #[error("负载过长: {0} 字节")]
+    PayloadTooLarge(usize),
+}
+
+/// 一个已解析的帧，负载借用自原始消息
+#[derive(Debug)]
+pub struct Frame<'a> {
+    pub opcode: Opcode,
+    pub payload: &'a [u8],
+}
+
+/// 解析一条 WebSocket 二进制消息，其中可以连续包含多个帧
+pub fn decode(mut data: &[u8]) -> Result<Vec<Frame<'_>>, FrameError> {
+    let mut frames = Vec::new();
+    while !data.is_empty() {
+        let (frame, rest) = decode_one(data)?;
+        frames.push(frame);
+        data = rest;
+    }
+    Ok(frames)
+}
+
+/// 解析字节流开头的全部完整帧，返回帧和已消费的字节数，末尾不完整的帧留待后续数据补齐
+pub fn decode_prefix(data: &[u8]) -> Result<(Vec<Frame<'_>>, usize), FrameError> {
+    let mut frames = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        match decode_one(rest) {
+            Ok((frame, remaining)) => {
+                frames.push(frame);
+                rest = remaining;
+            }
+            Err(FrameError::Truncated { .. }) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((frames, data.len() - rest.len()))
+}
+
+/// 解析开头的一个帧，返回帧和剩余字节
+fn decode_one(data: &[u8]) -> Result<(Frame<'_>, &[u8]), FrameError> {
+    if data.len() < HEADER_LEN {
+        return Err(FrameError::Truncated {
+            expected: HEADER_LEN,
+            actual: data.len(),
+        });
+    }
+
+    let opcode = Opcode::try_from(data[0])?;
+    let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+    let rest = &data[HEADER_LEN..];
+    if rest.len() < len {
+        return Err(FrameError::Truncated {
+            expected: len,
+            actual: rest.len(),
+        });
+    }
+
+    let frame = Frame {
+        opcode,
+        payload: &rest[..len],
+    };
+    Ok((frame, &rest[len..]))
+}
+
+/// 编码单个帧
+pub fn encode(opcode: Opcode, payload: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let len =
+        u16::try_from(payload.len()).map_err(|_| FrameError::PayloadTooLarge(payload.len()))?;
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.push(opcode as u8);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(opcode: Opcode, payload: &[u8]) -> Vec<u8> {
+        encode(opcode, payload).unwrap()
+    }
+
+    #[test]
+    fn encode_writes_big_endian_length_header() {
+        assert_eq!(
+            frame(Opcode::Transcript, "你好".as_bytes()),
+            [&[0x03, 0x00, 0x06][..], "你好".as_bytes()].concat()
+        );
+        assert_eq!(frame(Opcode::Ping, &[]), [0x05, 0x00, 0x00]);
+        assert_eq!(frame(Opcode::Audio, &[0; 300])[..3], [0x02, 0x01, 0x2c]);
+        assert!(matches!(
+            encode(Opcode::Audio, &vec![0; 65536]),
+            Err(FrameError::PayloadTooLarge(65536))
+        ));
+    }
+
+    #[test]
+    fn decode_reads_every_frame_in_a_message() {
+        let data = [
+            frame(Opcode::Audio, &[1, 2, 3]),
+            frame(Opcode::Ping, &[]),
+            frame(Opcode::Audio, &[4]),
+        ]
+        .concat();
+
+        let frames = decode(&data).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].opcode, Opcode::Audio);
+        assert_eq!(frames[0].payload, [1, 2, 3]);
+        assert_eq!(frames[1].opcode, Opcode::Ping);
+        assert!(frames[1].payload.is_empty());
+        assert_eq!(frames[2].payload, [4]);
+        assert!(decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decode_rejects_truncated_frames() {
+        assert!(matches!(
+            decode(&[0x02, 0x00]),
+            Err(FrameError::Truncated {
+                expected: 3,
+                actual: 2
+            })
+        ));
+        assert!(matches!(
+            decode(&[0x02, 0x00, 0x04, 1, 2]),
+            Err(FrameError::Truncated {
+                expected: 4,
+                actual: 2
+            })
+        ));
+        let mut data = frame(Opcode::Ping, &[]);
+        data.push(0x02);
+        assert!(matches!(decode(&data), Err(FrameError::Truncated { .. })));
+    }
+
+    #[test]
+    fn decode_rejects_unknown_opcodes() {
+        assert!(matches!(
+            decode(&[0x7f, 0x00, 0x00]),
+            Err(FrameError::UnknownOpcode(0x7f))
+        ));
+        let data = [frame(Opcode::Ping, &[]), vec![0x00, 0x00, 0x00]].concat();
+        assert!(matches!(
+            decode(&data),
+            Err(FrameError::UnknownOpcode(0x00))
+        ));
+    }
+
+    #[test]
+    fn decode_prefix_leaves_partial_tail() {
+        let complete = [frame(Opcode::Audio, &[1, 2]), frame(Opcode::Ping, &[])].concat();
+        let partial = frame(Opcode::Audio, &[3, 4, 5]);
+
+        for cut in 0..partial.len() {
+            let data = [&complete[..], &partial[..cut]].concat();
+            let (frames, consumed) = decode_prefix(&data).unwrap();
+            assert_eq!(frames.len(), 2);
+            assert_eq!(consumed, complete.len());
+        }
+
+        let data = [&complete[..], &partial[..]].concat();
+        let (frames, consumed) = decode_prefix(&data).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].payload, [3, 4, 5]);
+        assert_eq!(consumed, data.len());
+    }
+
+    #[test]
+    fn decode_prefix_rejects_unknown_opcodes() {
+        let data = [frame(Opcode::Ping, &[]), vec![0x09, 0x00]].concat();
+        assert_eq!(decode_prefix(&data).unwrap().1, 3);
+
+        let data = [frame(Opcode::Ping, &[]), vec![0x09, 0x00, 0x00]].concat();
+        assert!(matches!(
+            decode_prefix(&data),
+            Err(FrameError::UnknownOpcode(0x09))
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::info;
+
+use crate::config::Config;
+use crate::postgres::PostgresStore;
+use crate::result::SpeechResult;
+use crate::shadow::ShadowComparison;
+use crate::storage::{self, Database, HistoryRecord};
+
+/// 历史记录存储错误
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 识别结果历史记录的存储后端
+///
+/// 写入和查询接口经由该 trait 访问历史库，多实例部署时可换用共享的数据库。
+#[async_trait]
+pub trait HistoryStore: Send + Sync {
+    /// 后端名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 写入一条识别结果
+    async fn insert_speech_result(&self, result: &SpeechResult) -> Result<(), StoreError>;
+
+    /// 写入一条影子模型对比记录
+    async fn insert_shadow_comparison(
+        &self,
+        comparison: &ShadowComparison,
+    ) -> Result<(), StoreError>;
+
+    /// 按 id 查询一条记录，不存在时返回 `None`
+    async fn get_speech_result(&self, id: i64) -> Result<Option<HistoryRecord>, StoreError>;
+
+    /// 统计创建时间不早于 `since_ms`（Unix 毫秒）的记录数，`None` 表示全部
+    async fn count_speech_results_since(&self, since_ms: Option<i64>) -> Result<usize, StoreError>;
+
+    /// 按 ID 升序分页查询创建时间不早于 `since_ms` 的记录，只返回 ID 大于 `after_id` 的至多 `limit` 条
+    async fn list_speech_results_page(
+        &self,
+        since_ms: Option<i64>,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryRecord>, StoreError>;
+
+    /// 更新记录的情绪和情绪模型并写入审计记录，修改时间按 `timezone` 记录
+    async fn update_emotion(
+        &self,
+        id: i64,
+        old_emotion: &str,
+        new_emotion: &str,
+        model: &str,
+        timezone: Tz,
+    ) -> Result<(), StoreError>;
+
+    /// 检查存储是否可写，用于启动自检
+    async fn check_writable(&self) -> Result<(), StoreError>;
+
+    /// 整理存储、回收已删除记录占用的空间
+    async fn vacuum(&self) -> Result<(), StoreError>;
+
+    /// 距离本进程最近一次写入的时间
+    fn idle_duration(&self) -> Duration;
+}
+
+/// 本地 SQLite 文件，单实例部署的默认后端
+///
+/// 首次访问时打开数据库，打开失败时下次访问重试；rusqlite 的调用都在阻塞线程池中执行。
+pub struct SqliteStore {
+    path: String,
+    database: OnceCell<Arc<Database>>,
+}
+
+impl SqliteStore {
+    /// 使用 `path` 处的数据库，`:memory:` 为本实例独有的内存数据库
+    pub fn open(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            database: OnceCell::new(),
+        }
+    }
+
+    /// 在阻塞线程池中对数据库执行 `operation`
+    async fn run<T, E, F>(&self, operation: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        E: Into<StoreError> + Send + 'static,
+        F: FnOnce(&Database) -> Result<T, E> + Send + 'static,
+    {
+        let database = self
+            .database
+            .get_or_try_init(|| async {
+                let path = self.path.clone();
+                let database = tokio::task::spawn_blocking(move || Database::open(&path)).await?;
+                Ok::<_, StoreError>(Arc::new(database?))
+            })
+            .await?
+            .clone();
+        tokio::task::spawn_blocking(move || operation(&database))
+            .await?
+            .map_err(Into::into)
+    }
+}
+
+#[async_trait]
+impl HistoryStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert_speech_result(&self, result: &SpeechResult) -> Result<(), StoreError> {
+        let result = result.clone();
+        self.run(move |database| {
+            database
+                .insert_speech_result(&result)
+                .map_err(|e| format!("{} ({})", e, storage::failure_kind(&e)))
+        })
+        .await
+    }
+
+    async fn insert_shadow_comparison(
+        &self,
+        comparison: &ShadowComparison,
+    ) -> Result<(), StoreError> {
+        let comparison = comparison.clone();
+        self.run(move |database| database.insert_shadow_comparison(&comparison))
+            .await
+    }
+
+    async fn get_speech_result(&self, id: i64) -> Result<Option<HistoryRecord>, StoreError> {
+        self.run(move |database| database.get_speech_result(id))
+            .await
+    }
+
+    async fn count_speech_results_since(&self, since_ms: Option<i64>) -> Result<usize, StoreError> {
+        self.run(move |database| database.count_speech_results_since(since_ms))
+            .await
+    }
+
+    async fn list_speech_results_page(
+        &self,
+        since_ms: Option<i64>,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryRecord>, StoreError> {
+        self.run(move |database| database.list_speech_results_page(since_ms, after_id, limit))
+            .await
+    }
+
+    async fn update_emotion(
+        &self,
+        id: i64,
+        old_emotion: &str,
+        new_emotion: &str,
+        model: &str,
+        timezone: Tz,
+    ) -> Result<(), StoreError> {
+        let (old_emotion, new_emotion, model) = (
+            old_emotion.to_string(),
+            new_emotion.to_string(),
+            model.to_string(),
+        );
+        self.run(move |database| {
+            database.update_emotion(id, &old_emotion, &new_emotion, &model, timezone)
+        })
+        .await
+    }
+
+    async fn check_writable(&self) -> Result<(), StoreError> {
+        self.run(Database::check_writable).await
+    }
+
+    async fn vacuum(&self) -> Result<(), StoreError> {
+        self.run(Database::vacuum).await
+    }
+
+    fn idle_duration(&self) -> Duration {
+        // 尚未打开时没有写入过
+        self.database
+            .get()
+            .map_or(Duration::MAX, |database| database.idle_duration())
+    }
+}
+
+/// 按 `DATABASE_URL` 的协议选择存储后端，未设置时使用 `DB_PATH` 处的 SQLite
+///
+/// 支持 `sqlite:<路径>`、`sqlite://<路径>` 和 `postgres://`（或 `postgresql://`）连接串。
+pub fn open(config: &Config) -> Result<Arc<dyn HistoryStore>, StoreError> {
+    let store: Arc<dyn HistoryStore> = match config.database_url.as_deref() {
+        None => Arc::new(SqliteStore::open(&config.db_path)),
+        Some(url) => match url.split_once(':') {
+            Some(("sqlite", rest)) => {
+                let path = rest.strip_prefix("//").unwrap_or(rest);
+                if path.is_empty() {
+                    return Err("DATABASE_URL 缺少 SQLite 数据库路径".into());
+                }
+                Arc::new(SqliteStore::open(path))
+            }
+            Some(("postgres" | "postgresql", _)) => Arc::new(PostgresStore::open(url)?),
+            _ => return Err(format!("DATABASE_URL 协议不受支持: {}", url).into()),
+        },
+    };
+    info!("历史记录存储: {}", store.name());
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::Timings;
+
+    fn speech_result(text: &str) -> SpeechResult {
+        SpeechResult {
+            session_id: "session".to_string(),
+            text: text.to_string(),
+            text_raw: None,
+            emotion: "平静".to_string(),
+            confidence: None,
+            timings: Timings::default(),
+            fingerprint: None,
+            vad: None,
+            prosody: None,
+            tokens: None,
+            asr_model: None,
+            emotion_model: None,
+            detected_language: None,
+            created_at: "2024-05-01T08:00:00+08:00".to_string(),
+            rejection: None,
+            audio_path: None,
+            persist_failed: false,
+            offset_ms: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_stores_keep_their_own_database() {
+        let first = SqliteStore::open(storage::MEMORY_DB_PATH);
+        let second = SqliteStore::open(storage::MEMORY_DB_PATH);
+        first
+            .insert_speech_result(&speech_result("有点难过"))
+            .await
+            .unwrap();
+        first
+            .update_emotion(1, "平静", "悲伤", "llama", Tz::Asia__Shanghai)
+            .await
+            .unwrap();
+
+        let record = first.get_speech_result(1).await.unwrap().unwrap();
+        assert_eq!(record.result.emotion, "悲伤");
+        assert_eq!(first.count_speech_results_since(None).await.unwrap(), 1);
+        assert_eq!(second.count_speech_results_since(None).await.unwrap(), 0);
+        assert!(first.idle_duration() < Duration::from_secs(5));
+        assert!(second.idle_duration() > Duration::from_secs(60));
+    }
+
+    #[test]
+    fn database_url_selects_backend() {
+        let mut config = Config::from_env();
+        config.database_url = Some("postgres://user@localhost/history".to_string());
+        assert_eq!(open(&config).unwrap().name(), "postgres");
+
+        config.database_url = Some("sqlite::memory:".to_string());
+        assert_eq!(open(&config).unwrap().name(), "sqlite");
+
+        config.database_url = Some("mysql://localhost/history".to_string());
+        assert!(open(&config).is_err());
+    }
+}
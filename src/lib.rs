@@ -0,0 +1,41 @@
+//! 心镜 (Heart Mirror) 大脑：语音识别与情绪分析服务
+//!
+//! 服务入口在 `main.rs`，这里导出各模块供基准测试使用。
+
+pub mod acoustic;
+pub mod api;
+pub mod audio;
+pub mod check;
+pub mod clip;
+pub mod coalesce;
+pub mod config;
+pub mod emotion;
+pub mod fallback;
+pub mod framing;
+pub mod history;
+pub mod logging;
+pub mod maintenance;
+pub mod metrics;
+pub mod model_select;
+pub mod monitor;
+pub mod net;
+pub mod pipeline;
+pub mod postgres;
+pub mod prosody;
+pub mod protocol;
+pub mod ratelimit;
+pub mod reanalyze;
+pub mod recent;
+pub mod reload;
+pub mod result;
+pub mod shadow;
+pub mod sink;
+pub mod speech;
+pub mod state;
+pub mod storage;
+pub mod stream;
+pub mod subtitle;
+pub mod text;
+pub mod vad;
+pub mod wake;
+pub mod websocket;
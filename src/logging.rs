@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// 按语句或按帧产生的高频日志使用的 target，开启采样时只输出其中一部分
+pub const SAMPLED: &str = "heart_mirror_brain::sampled";
+
+/// 初始化日志系统
+///
+/// 日志在读取配置之前初始化，因此采样率直接读取 `LOG_SAMPLE_RATE`：
+/// 为 N 时 `SAMPLED` target 下 INFO 及以下的日志每 N 条输出 1 条，警告和错误总是输出。
+/// `to_stderr` 为 true 时日志写到标准错误，标准输出留给结果。
+pub fn init(to_stderr: bool) {
+    let sample_rate = std::env::var("LOG_SAMPLE_RATE")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(1);
+
+    let builder = tracing_subscriber::fmt().with_max_level(Level::INFO);
+    let sampler = LogSampler::new(sample_rate);
+    if to_stderr {
+        builder
+            .with_writer(std::io::stderr)
+            .finish()
+            .with(sampler)
+            .init();
+    } else {
+        builder.finish().with(sampler).init();
+    }
+}
+
+/// 对高频日志按固定间隔采样的层
+pub struct LogSampler {
+    every: u64,
+    seen: AtomicU64,
+}
+
+impl LogSampler {
+    /// 每 `every` 条输出 1 条，0 和 1 表示不采样
+    pub fn new(every: u64) -> Self {
+        Self {
+            every: every.max(1),
+            seen: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogSampler {
+    fn event_enabled(&self, event: &Event<'_>, _: Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        if self.every == 1 || metadata.target() != SAMPLED || *metadata.level() <= Level::WARN {
+            return true;
+        }
+        self.seen
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.every)
+    }
+}
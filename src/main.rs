@@ -1,57 +1,210 @@
-use axum::{extract::ws::WebSocketUpgrade, response::IntoResponse, routing::get, Router};
+use axum::{
+    extract::{ws::WebSocketUpgrade, ConnectInfo, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use std::future::IntoFuture;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tracing::info;
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
 
-mod audio;
-mod emotion;
-mod protocol;
-mod speech;
-mod websocket;
+use heart_mirror_brain::config::{self, Config};
+use heart_mirror_brain::emotion::EmotionAnalyzer;
+use heart_mirror_brain::history::{self, HistoryStore};
+use heart_mirror_brain::metrics::METRICS;
+use heart_mirror_brain::ratelimit::ConnectionLimiter;
+use heart_mirror_brain::speech::RecognizerRegistry;
+use heart_mirror_brain::state::AppState;
+use heart_mirror_brain::vad::SileroModel;
+use heart_mirror_brain::{
+    api, check, logging, maintenance, monitor, net, reload, sink, stream, websocket,
+};
 
-use emotion::EmotionAnalyzer;
-use speech::SpeechRecognizer;
+fn main() -> ExitCode {
+    logging::init(is_stdin_mode());
 
-#[tokio::main]
-async fn main() {
-    init_logging();
+    if let Err(e) = config::load_config_file() {
+        warn!("读取 CONFIG_FILE 失败，只使用环境变量: {}", e);
+    }
+    // 模型路径从环境变量读取：WHISPER_MODELS 多语言映射，或 MODEL_PATH 单模型
+    let config = Config::from_env();
+    let history = match history::open(&config) {
+        Ok(store) => store,
+        Err(e) => {
+            error!("打开历史记录存储失败: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    // 修改：优先从环境变量读取模型路径，默认值为 "ggml-base.bin"
-    let model_path = std::env::var("MODEL_PATH").unwrap_or_else(|_| "ggml-base.bin".to_string());
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(worker_threads) = config.worker_threads {
+        builder.worker_threads(worker_threads);
+    }
+    let runtime = builder.enable_all().build().unwrap();
+
+    runtime.block_on(run(config, history))
+}
+
+/// 在 tokio 运行时中启动服务
+async fn run(config: Config, history: Arc<dyn HistoryStore>) -> ExitCode {
+    if is_check_only() {
+        return if check::run(&config, history.as_ref()).await {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
 
     info!("正在初始化系统...");
-    info!("加载 Whisper 模型路径: {}", model_path);
 
-    // 传入动态获取的路径
-    let speech_recognizer = Arc::new(SpeechRecognizer::new(&model_path).await);
-    let emotion_analyzer = Arc::new(EmotionAnalyzer::new().await);
+    let recognizers = Arc::new(RecognizerRegistry::load(&config).await);
+    if let Some(idle) = config.model_idle_unload {
+        recognizers.spawn_idle_unload(idle);
+    }
+    let emotion_analyzer = Arc::new(EmotionAnalyzer::new(&config).await);
+    if let Some(interval) = config.ollama_probe_interval {
+        emotion_analyzer.spawn_health_probe(interval);
+    }
+
+    let sinks = Arc::new(sink::build_sinks(&config, &history));
+
+    if config.persist_history {
+        maintenance::spawn_vacuum_task(&config, history.clone());
+    }
+
+    let (shutdown_tx, shutdown) = watch::channel(false);
+    let mut stopping = shutdown.clone();
+    let state = AppState {
+        config: Arc::new(RwLock::new(Arc::new(config.clone()))),
+        recognizers,
+        silero_vad: SileroModel::from_config(&config),
+        emotion_analyzer,
+        sinks,
+        history,
+        stream_sessions: Arc::default(),
+        reanalyze_job: Arc::default(),
+        monitor: monitor::channel(),
+        connection_limiter: (config.connection_rate > 0.0).then(|| {
+            Arc::new(ConnectionLimiter::new(
+                config.connection_rate,
+                config.connection_burst,
+            ))
+        }),
+        shutdown,
+    };
+    if is_stdin_mode() {
+        return match stream::run_stdin(state).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                error!("读取标准输入失败: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    reload::spawn_sighup_listener(state.clone());
 
-    let app = Router::new().route(
-        "/ws",
-        get(move |ws| ws_handler(ws, speech_recognizer.clone(), emotion_analyzer.clone())),
+    let app = Router::new()
+        .route("/ws", get(ws_handler))
+        .route("/monitor", get(monitor::handler))
+        .route("/health", get(api::health))
+        .route("/metrics", get(api::metrics))
+        .route("/history/:id", get(api::history_record))
+        .route("/history/:id/audio", get(api::history_audio))
+        .route("/reanalyze", post(api::reanalyze))
+        .route("/reload-config", post(api::reload_config))
+        .route("/stream", post(stream::start))
+        .route("/stream/:id", get(stream::events))
+        .route("/stream-audio", post(stream::stream_audio))
+        .with_state(state);
+
+    info!(
+        "🚀 心镜 (Heart Mirror) 大脑已启动，监听: {}",
+        config.listen_addr
     );
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 4321));
-    info!("🚀 心镜 (Heart Mirror) 大脑已启动，监听: {}", addr);
+    let listener = net::bind_listener(&config).unwrap();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        info!("收到退出信号，关闭连接");
+        let _ = shutdown_tx.send(true);
+    });
+    // 宽限期内仍未结束的连接（如未读完的 SSE）不再等待
+    let grace = async {
+        if stopping.wait_for(|stopping| *stopping).await.is_err() {
+            return std::future::pending().await;
+        }
+        tokio::time::sleep(config.shutdown_grace).await;
+    };
+    tokio::select! {
+        result = server.into_future() => result.unwrap(),
+        () = grace => warn!("宽限期内仍有连接未关闭，直接退出"),
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// 等待 Ctrl-C 或 SIGTERM，无法监听的信号忽略
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                warn!("无法监听 SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// 是否只运行依赖检查：`--check` 参数或 `CHECK_ONLY=1`
+fn is_check_only() -> bool {
+    std::env::args().any(|arg| arg == "--check")
+        || std::env::var("CHECK_ONLY").is_ok_and(|value| value == "1" || value == "true")
 }
 
-/// 初始化日志系统
-fn init_logging() {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+/// 是否从标准输入读取音频调试识别流程：`--stdin` 参数
+fn is_stdin_mode() -> bool {
+    std::env::args().any(|arg| arg == "--stdin")
 }
 
 /// WebSocket 升级处理器
 async fn ws_handler(
     ws: WebSocketUpgrade,
-    speech_recognizer: Arc<SpeechRecognizer>,
-    emotion_analyzer: Arc<EmotionAnalyzer>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| {
-        websocket::handle_connection(socket, speech_recognizer, emotion_analyzer)
-    })
+    if let Some(limiter) = &state.connection_limiter {
+        if !limiter.try_acquire(peer.ip()) {
+            warn!("来自 {} 的连接过于频繁，拒绝新连接", peer.ip());
+            METRICS
+                .connections_rate_limited_total
+                .fetch_add(1, Ordering::Relaxed);
+            return ws.on_upgrade(websocket::reject_rate_limited);
+        }
+    }
+    websocket::upgrade(ws, state)
 }
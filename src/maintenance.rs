@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::history::HistoryStore;
+
+/// 启动定期整理历史库的后台任务，间隔为零时不启动
+pub fn spawn_vacuum_task(config: &Config, store: Arc<dyn HistoryStore>) {
+    let interval = config.vacuum_interval;
+    let idle = config.vacuum_idle;
+    if interval.is_zero() {
+        info!("历史库定期整理已禁用");
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            wait_until_idle(store.as_ref(), idle).await;
+
+            let started = std::time::Instant::now();
+            match store.vacuum().await {
+                Ok(()) => info!(
+                    "历史库 ({}) 整理完成，耗时 {:?}",
+                    store.name(),
+                    started.elapsed()
+                ),
+                Err(e) => warn!("历史库 ({}) 整理失败: {}", store.name(), e),
+            }
+        }
+    });
+}
+
+/// 等待数据库在 `idle` 时长内没有写入
+async fn wait_until_idle(store: &dyn HistoryStore, idle: Duration) {
+    loop {
+        let idle_for = store.idle_duration();
+        if idle_for >= idle {
+            return;
+        }
+        tokio::time::sleep(idle - idle_for).await;
+    }
+}
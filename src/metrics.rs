@@ -0,0 +1,161 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// 全局运行指标
+pub struct Metrics {
+    /// 所有连接当前缓冲的音频字节数
+    pub buffered_audio_bytes: AtomicUsize,
+    /// 因超过全局音频缓冲上限而触发背压的次数
+    pub audio_backpressure_total: AtomicU64,
+    /// 复用并发相同情绪分析请求结果的次数
+    pub emotion_single_flight_hits_total: AtomicU64,
+    /// 识别结果写入输出端失败的次数
+    pub result_sink_failures_total: AtomicU64,
+    /// 客户端消费过慢时被较新消息取代而丢弃的中间结果数
+    pub interim_coalesced_total: AtomicU64,
+    /// 排队等待识别过久而被丢弃的语句数
+    pub stale_utterances_dropped_total: AtomicU64,
+    /// 用户再次开口而中止的识别数
+    pub recognitions_cancelled_total: AtomicU64,
+    /// 被软限幅压缩的样本数
+    pub limited_samples_total: AtomicU64,
+    /// 与上一句合并后重新识别的语句数
+    pub utterances_coalesced_total: AtomicU64,
+    pub connections_rate_limited_total: AtomicU64,
+    pub shadow_recognitions_total: AtomicU64,
+    pub shadow_mismatches_total: AtomicU64,
+    /// 上一次影子识别尚未完成而跳过的语句数
+    pub shadow_skipped_total: AtomicU64,
+    /// 语句过短而跳过情绪分析的次数
+    pub emotion_skipped_short_total: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    buffered_audio_bytes: AtomicUsize::new(0),
+    audio_backpressure_total: AtomicU64::new(0),
+    emotion_single_flight_hits_total: AtomicU64::new(0),
+    result_sink_failures_total: AtomicU64::new(0),
+    interim_coalesced_total: AtomicU64::new(0),
+    stale_utterances_dropped_total: AtomicU64::new(0),
+    recognitions_cancelled_total: AtomicU64::new(0),
+    limited_samples_total: AtomicU64::new(0),
+    utterances_coalesced_total: AtomicU64::new(0),
+    connections_rate_limited_total: AtomicU64::new(0),
+    shadow_recognitions_total: AtomicU64::new(0),
+    shadow_mismatches_total: AtomicU64::new(0),
+    shadow_skipped_total: AtomicU64::new(0),
+    emotion_skipped_short_total: AtomicU64::new(0),
+};
+
+impl Metrics {
+    /// 以 Prometheus 文本格式输出
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "heart_mirror_buffered_audio_bytes",
+            "gauge",
+            "Audio bytes currently buffered across all connections",
+            self.buffered_audio_bytes.load(Ordering::Relaxed) as u64,
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_audio_backpressure_total",
+            "counter",
+            "Times the global audio buffer cap forced backpressure",
+            self.audio_backpressure_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_emotion_single_flight_hits_total",
+            "counter",
+            "Emotion requests served by an identical in-flight request",
+            self.emotion_single_flight_hits_total
+                .load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_result_sink_failures_total",
+            "counter",
+            "Result writes that failed and were lost, across all sinks",
+            self.result_sink_failures_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_interim_coalesced_total",
+            "counter",
+            "Interim messages dropped in favour of a newer one for slow clients",
+            self.interim_coalesced_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_stale_utterances_dropped_total",
+            "counter",
+            "Utterances dropped because they waited too long before recognition",
+            self.stale_utterances_dropped_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_recognitions_cancelled_total",
+            "counter",
+            "Recognitions aborted because the speaker started a new utterance",
+            self.recognitions_cancelled_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_limited_samples_total",
+            "counter",
+            "Input samples compressed by the soft limiter",
+            self.limited_samples_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_utterances_coalesced_total",
+            "counter",
+            "Short utterances merged with the previous one and recognized again",
+            self.utterances_coalesced_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_connections_rate_limited_total",
+            "counter",
+            "WebSocket connections rejected by the per-IP connection rate limit",
+            self.connections_rate_limited_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_shadow_recognitions_total",
+            "counter",
+            "Utterances transcribed again by the shadow model",
+            self.shadow_recognitions_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_shadow_mismatches_total",
+            "counter",
+            "Shadow model transcripts that differ from the primary model",
+            self.shadow_mismatches_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_shadow_skipped_total",
+            "counter",
+            "Utterances not sent to the shadow model because it was still busy",
+            self.shadow_skipped_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "heart_mirror_emotion_skipped_short_total",
+            "counter",
+            "Emotion analyses skipped because the utterance was too short",
+            self.emotion_skipped_short_total.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::Path;
+
+use tracing::{info, warn};
+
+/// 各档模型运行所需的内存（MB）和建议的最少 CPU 核数，由大到小排列
+///
+/// 内存按 whisper.cpp 实际占用加约 50% 余量估计，为同机的 Ollama 留出空间。
+const MODEL_TIERS: [(&str, u64, usize); 5] = [
+    ("large", 6000, 8),
+    ("medium", 3200, 6),
+    ("small", 1300, 4),
+    ("base", 600, 2),
+    ("tiny", 400, 1),
+];
+
+/// 无法读取内存信息时最多选到的档位
+const UNKNOWN_MEMORY_TIER: &str = "base";
+
+/// 按可用内存和 CPU 核数从 `dir` 中选出最大的可运行模型，没有合适的模型时返回 None
+///
+/// 模型文件按 whisper.cpp 的命名 `ggml-<档位>*.bin` 匹配，`.en` 英文专用模型只在
+/// `language` 为 `en` 时参与选择，同档有多个文件时取文件名排序最后的（如 `large-v3`）。
+pub fn select(dir: &str, language: &str) -> Option<String> {
+    let files = match list_models(dir) {
+        Ok(files) => files,
+        Err(e) => {
+            warn!("无法读取模型目录 {}: {}", dir, e);
+            return None;
+        }
+    };
+
+    let memory_mb = available_memory_mb();
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let fits = |(tier, need_mb, need_cpus): &(&str, u64, usize)| match memory_mb {
+        Some(memory_mb) => memory_mb >= *need_mb && cpus >= *need_cpus,
+        None => tier_rank(tier) >= tier_rank(UNKNOWN_MEMORY_TIER) && cpus >= *need_cpus,
+    };
+
+    for tier in MODEL_TIERS.iter().filter(|tier| fits(tier)) {
+        let prefix = format!("ggml-{}", tier.0);
+        let chosen = files
+            .iter()
+            .filter(|name| name.starts_with(&prefix))
+            .filter(|name| language == "en" || !name.contains(".en"))
+            .max();
+        if let Some(name) = chosen {
+            let path = Path::new(dir).join(name).to_string_lossy().into_owned();
+            info!(
+                "自动选择 Whisper 模型 {}（可用内存 {}，CPU {} 核）",
+                path,
+                memory_mb.map_or("未知".to_string(), |mb| format!("{} MB", mb)),
+                cpus
+            );
+            return Some(path);
+        }
+    }
+
+    warn!("模型目录 {} 中没有适合当前硬件的模型", dir);
+    None
+}
+
+/// 档位在 `MODEL_TIERS` 中的位置，越大模型越小
+fn tier_rank(tier: &str) -> usize {
+    MODEL_TIERS
+        .iter()
+        .position(|(name, _, _)| *name == tier)
+        .unwrap_or(0)
+}
+
+/// 目录中的 `.bin` 文件名
+fn list_models(dir: &str) -> std::io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".bin") {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// 从 `/proc/meminfo` 读取可用内存（MB），非 Linux 系统返回 None
+fn available_memory_mb() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
@@ -0,0 +1,128 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::protocol::ServerResponse;
+use crate::state::AppState;
+use crate::websocket::{self, CloseReason};
+
+/// 广播通道容量，监控端读取过慢时丢弃最旧的消息
+const MONITOR_CAPACITY: usize = 256;
+
+/// 创建向监控端广播识别结果的通道
+pub fn channel() -> broadcast::Sender<ServerResponse> {
+    broadcast::channel(MONITOR_CAPACITY).0
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MonitorParams {
+    token: Option<String>,
+}
+
+/// 管理令牌校验失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    /// 未设置 `MONITOR_TOKEN`，管理接口不启用
+    Disabled,
+    /// 没有携带令牌或令牌不匹配
+    Invalid,
+}
+
+/// 校验请求携带的 `MONITOR_TOKEN`（`?token=` 或 `Authorization: Bearer`）
+pub fn verify_token(
+    expected: Option<&str>,
+    query_token: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), TokenError> {
+    let Some(expected) = expected else {
+        return Err(TokenError::Disabled);
+    };
+
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let provided = query_token.or(bearer);
+
+    if provided != Some(expected) {
+        return Err(TokenError::Invalid);
+    }
+    Ok(())
+}
+
+/// 监控端 WebSocket 升级，需携带 `MONITOR_TOKEN`（`?token=` 或 `Authorization: Bearer`）
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<MonitorParams>,
+    headers: HeaderMap,
+) -> Response {
+    let config = state.config();
+    match verify_token(
+        config.monitor_token.as_deref(),
+        params.token.as_deref(),
+        &headers,
+    ) {
+        Ok(()) => {}
+        Err(TokenError::Disabled) => {
+            return (
+                StatusCode::NOT_FOUND,
+                "监控接口未启用 (MONITOR_TOKEN 未设置)",
+            )
+                .into_response();
+        }
+        Err(TokenError::Invalid) => {
+            warn!("监控端鉴权失败");
+            return (StatusCode::UNAUTHORIZED, "无效的监控令牌").into_response();
+        }
+    }
+
+    let receiver = state.monitor.subscribe();
+    let shutdown = state.shutdown.clone();
+    ws.on_upgrade(move |socket| run(socket, receiver, shutdown))
+}
+
+/// 把广播的识别结果转发给监控端，忽略监控端发来的消息
+async fn run(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<ServerResponse>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!("监控端已连接");
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(response) => {
+                    let Ok(json) = serde_json::to_string(&response) else { continue };
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("监控端读取过慢，丢弃 {} 条结果", skipped),
+                Err(RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                _ => {}
+            },
+            Ok(()) = shutdown.changed() => {
+                if *shutdown.borrow_and_update() {
+                    websocket::close(&mut socket, CloseReason::ShuttingDown).await;
+                    break;
+                }
+            }
+        }
+    }
+
+    info!("监控端断开");
+}
@@ -0,0 +1,30 @@
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use std::io;
+use tokio::net::TcpListener;
+
+use crate::config::Config;
+
+/// 创建开启 TCP keepalive 的监听套接字
+///
+/// Linux 上 accept 得到的连接会继承监听套接字的 keepalive 和 `TCP_USER_TIMEOUT` 设置，
+/// 对端掉线后内核会在超时后主动关闭连接。
+pub fn bind_listener(config: &Config) -> io::Result<TcpListener> {
+    let addr = config.listen_addr;
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+
+    let keepalive = TcpKeepalive::new()
+        .with_time(config.tcp_keepalive_time)
+        .with_interval(config.tcp_keepalive_interval)
+        .with_retries(config.tcp_keepalive_retries);
+    socket.set_tcp_keepalive(&keepalive)?;
+
+    #[cfg(target_os = "linux")]
+    socket.set_tcp_user_timeout(config.tcp_user_timeout)?;
+
+    socket.bind(&addr.into())?;
+    socket.listen(config.listen_backlog)?;
+    socket.set_nonblocking(true)?;
+
+    TcpListener::from_std(socket.into())
+}
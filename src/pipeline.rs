@@ -0,0 +1,529 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::acoustic;
+use crate::audio::Utterance;
+use crate::clip;
+use crate::config::Config;
+use crate::emotion::ConversationContext;
+use crate::fallback::ModelFallback;
+use crate::logging;
+use crate::metrics::METRICS;
+use crate::prosody;
+use crate::result::{self, SpeechResult, Timings};
+use crate::shadow::{self, ShadowJob};
+use crate::sink;
+use crate::speech::{RecognizeCallbacks, Recognizer, Transcript};
+use crate::state::AppState;
+use crate::text;
+use crate::wake::{self, WakeGate};
+
+/// 仅情绪模式下代替原文存储的占位文本
+pub const REDACTED_TEXT: &str = "[redacted]";
+
+/// 会话级别的处理设置，在握手时协商
+#[derive(Debug, Clone)]
+pub struct SessionSettings {
+    /// 会话 ID，写入每条识别结果
+    pub session_id: String,
+    /// 覆盖默认的 Whisper 提示词
+    pub initial_prompt: Option<String>,
+    /// 只返回情绪，不传输或存储识别原文
+    pub emotion_only: bool,
+}
+
+impl SessionSettings {
+    /// 默认设置的新会话
+    pub fn new(session_id: String) -> Self {
+        Self {
+            session_id,
+            initial_prompt: None,
+            emotion_only: false,
+        }
+    }
+}
+
+/// 会话内跨语句保留的处理状态
+#[derive(Debug)]
+pub struct ConversationState {
+    /// 最近的识别文本，作为情绪分析的语境
+    pub context: ConversationContext,
+    /// 识别文本的去重状态
+    pub dedup: TranscriptDedup,
+    pub wake: WakeGate,
+}
+
+impl ConversationState {
+    /// 按配置创建新会话的状态
+    pub fn new(config: &Config) -> Self {
+        Self {
+            context: ConversationContext::new(config.emotion_context_turns),
+            dedup: TranscriptDedup::default(),
+            wake: WakeGate::from_config(config),
+        }
+    }
+}
+
+/// 会话内跨语句的识别文本去重状态
+#[derive(Debug, Default)]
+pub struct TranscriptDedup {
+    /// 固定窗口模式下上一个窗口的完整识别文本，用于去除重叠部分
+    previous_window: Option<String>,
+    /// 上一句识别文本及其时间，用于丢弃回声造成的重复结果
+    last_transcript: Option<(String, Instant)>,
+}
+
+impl TranscriptDedup {
+    /// 去掉与上一窗口重复的开头，并记录本窗口的完整识别文本供下一个窗口去重
+    fn strip_window_overlap<'a>(&mut self, recognized: &'a str) -> &'a str {
+        let clean = match &self.previous_window {
+            Some(previous) => text::strip_overlap(previous, recognized),
+            None => recognized,
+        };
+        self.previous_window = Some(recognized.to_string());
+        clean
+    }
+
+    /// 与上一句完全相同且间隔不超过 `window` 时返回 true；否则记录本句，供下一句比较
+    ///
+    /// 重复的语句不更新时间，持续回声不会无限延长去重窗口。
+    fn is_repeat(&mut self, text: &str, window: Duration) -> bool {
+        let now = Instant::now();
+        if let Some((last, at)) = &self.last_transcript {
+            if last == text && now.duration_since(*at) <= window {
+                return true;
+            }
+        }
+        self.last_transcript = Some((text.to_string(), now));
+        false
+    }
+}
+
+/// 一句语音中单个识别分段的情绪，分段识别出来后立即分析，不等整句结束
+#[derive(Debug, Clone)]
+pub struct SegmentEmotion {
+    /// 所属语句在会话中的序号
+    pub utterance: u64,
+    /// 分段在语句中的序号
+    pub segment: usize,
+    /// 分段文本，仅情绪模式下为 None
+    pub text: Option<String>,
+    pub emotion: String,
+}
+
+/// 处理一句语音时的可选回调
+#[derive(Default)]
+pub struct UtteranceHooks {
+    /// 识别进度百分比，在推理线程上调用
+    pub on_progress: Option<Box<dyn FnMut(i32) + Send>>,
+    /// 开启 `SEGMENT_EMOTION` 时，分段情绪分析完成后发送到这里
+    pub segment_emotions: Option<mpsc::UnboundedSender<SegmentEmotion>>,
+    /// 本句在会话中的序号，用于关联分段情绪和整句结果
+    pub utterance: u64,
+    /// 被新语句打断时置位，识别尽快中止且不产生结果
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// 不写入输出端，由调用方在确认结果不会被取代后写入
+    pub defer_store: bool,
+}
+
+/// 结果是否应写入输出端：低质量结果只在开启 `STORE_LOW_QUALITY` 时写入
+pub fn should_store(rejection: Option<&str>, config: &Config) -> bool {
+    rejection.is_none() || config.store_low_quality
+}
+
+/// 语句切分后等待识别的时间超过 `MAX_UTTERANCE_STALENESS_MS` 时返回 true 并记录丢弃
+///
+/// 只用于实时输入：识别跟不上时丢弃积压的旧语音，让结果跟上当前说话内容。
+pub fn is_stale(state: &AppState, utterance: &Utterance) -> bool {
+    let Some(max) = state.config().max_utterance_staleness else {
+        return false;
+    };
+    let waited = utterance.finalized_at.elapsed();
+    if waited <= max {
+        return false;
+    }
+    METRICS
+        .stale_utterances_dropped_total
+        .fetch_add(1, Ordering::Relaxed);
+    warn!("语音已排队 {:?}，超过上限 {:?}，丢弃不识别", waited, max);
+    true
+}
+
+/// 对完整语音片段执行识别、情绪分析并分发到输出端，无效语音返回 None
+///
+/// 识别文本在分析后追加到 `conversation` 的上下文，供同一会话的后续语句参考。
+/// `hooks` 可在识别过程中回报进度，或为每个新分段单独分析情绪。
+/// 启用唤醒词时，未唤醒的语句只用于检测唤醒词，返回 None。
+pub async fn process_utterance(
+    state: &AppState,
+    recognizer: &Arc<dyn Recognizer>,
+    settings: &SessionSettings,
+    conversation: &mut ConversationState,
+    utterance: &Utterance,
+    hooks: UtteranceHooks,
+) -> Option<SpeechResult> {
+    let ConversationState {
+        context,
+        dedup,
+        wake,
+    } = conversation;
+    let config = state.config();
+    let audio_data = utterance.samples.as_slice();
+    let recognition_audio = utterance.recognition_samples();
+    let cancelled = || {
+        hooks
+            .cancel
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    };
+    if cancelled() {
+        info!(target: logging::SAMPLED, "语句在识别前被新语句打断，跳过");
+        return None;
+    }
+    if !wake.is_open() && !detect_wake_phrase(recognizer, wake, &recognition_audio).await {
+        return None;
+    }
+
+    let started = Instant::now();
+    let fallback = state.recognizers.fallback();
+    let in_flight = fallback.map(ModelFallback::begin);
+    let recognizer = fallback.map_or_else(
+        || recognizer.clone(),
+        |fallback| fallback.select(recognizer),
+    );
+    let on_segment = hooks
+        .segment_emotions
+        .filter(|_| config.segment_emotion)
+        .map(|tx| segment_emotion_spawner(state, settings, wake.clone(), hooks.utterance, tx));
+    let transcript = recognize_blocking(
+        recognizer.clone(),
+        recognition_audio.clone(),
+        settings.initial_prompt.clone(),
+        hooks.on_progress,
+        on_segment,
+        hooks.cancel.clone(),
+    )
+    .await;
+    drop(in_flight);
+    if let Some(fallback) = fallback {
+        fallback.record(recognizer.as_ref(), started.elapsed());
+    }
+    let recognition_ms = started.elapsed().as_millis() as u64;
+    if cancelled() {
+        METRICS
+            .recognitions_cancelled_total
+            .fetch_add(1, Ordering::Relaxed);
+        info!("识别被新语句打断，已中止 ({}ms)", recognition_ms);
+        return None;
+    }
+    // 仅情绪模式不保存原文，也不做影子对比
+    let shadow = state
+        .recognizers
+        .shadow()
+        .filter(|shadow| shadow.language() == recognizer.language() && !settings.emotion_only);
+    if let Some(shadow) = shadow {
+        shadow::spawn(
+            state,
+            shadow.clone(),
+            ShadowJob {
+                session_id: settings.session_id.clone(),
+                primary_model: recognizer.model_name().to_string(),
+                primary_text: transcript.text.trim().to_string(),
+                primary_ms: recognition_ms,
+                audio: recognition_audio,
+                initial_prompt: settings.initial_prompt.clone(),
+            },
+        );
+    }
+    let recognized = wake.strip(transcript.text.trim());
+    // 固定窗口相互重叠，去掉与上一窗口重复的开头
+    let clean_text = if config.chunk_window.is_some() {
+        dedup.strip_window_overlap(recognized)
+    } else {
+        recognized
+    };
+
+    if let Some(window) = config.dedup_window {
+        if !clean_text.is_empty() && dedup.is_repeat(clean_text, window) {
+            info!(
+                "忽略与上一句相同的识别结果: [{}]",
+                config.log_transcripts.display(clean_text)
+            );
+            return None;
+        }
+    }
+
+    let started = Instant::now();
+
+    let mut rejection = rejection_reason(clean_text, config.min_content_chars);
+    // 过短的语句不调用 LLM；合并模式下由 LLM 进一步判断本地规则放过的文本，同时给出情绪
+    let mut known_emotion = None;
+    let skipped_short = rejection.is_none() && is_too_short(clean_text, config.emotion_min_words);
+    if skipped_short {
+        METRICS
+            .emotion_skipped_short_total
+            .fetch_add(1, Ordering::Relaxed);
+        info!(
+            target: logging::SAMPLED,
+            "语句过短，跳过情绪分析: [{}]",
+            config.log_transcripts.display(clean_text)
+        );
+        known_emotion = Some("neutral".to_string());
+    } else if rejection.is_none() && config.combined_analysis {
+        let verdict = state
+            .emotion_analyzer
+            .analyze_combined(clean_text, None, context.turns())
+            .await;
+        if verdict.meaningful {
+            known_emotion = Some(verdict.emotion);
+        } else {
+            rejection = Some("not_meaningful");
+        }
+    }
+    let emotion = match rejection {
+        Some(reason) if !config.emit_low_quality => {
+            info!(target: logging::SAMPLED, "(忽略无效语音: {})", reason);
+            return None;
+        }
+        // 低质量文本不值得调用 Ollama，也不进入对话上下文
+        Some(_) => "neutral".to_string(),
+        None => {
+            wake.keep_awake();
+            let emotion = match known_emotion {
+                Some(emotion) => emotion,
+                None => {
+                    state
+                        .emotion_analyzer
+                        .analyze_with_context(clean_text, None, context.turns())
+                        .await
+                }
+            };
+            context.push(clean_text);
+            emotion
+        }
+    };
+
+    let emotion_ms = started.elapsed().as_millis() as u64;
+
+    let text = if settings.emotion_only {
+        info!(
+            target: logging::SAMPLED,
+            "🗣️ 结果: [仅情绪模式] | 情绪: [{}]",
+            emotion
+        );
+        REDACTED_TEXT.to_string()
+    } else {
+        info!(
+            target: logging::SAMPLED,
+            "🗣️ 结果: [{}] | 情绪: [{}]",
+            config.log_transcripts.display(clean_text),
+            emotion
+        );
+        let (text, truncated) =
+            text::truncate_with_ellipsis(clean_text, config.max_transcript_chars);
+        if truncated {
+            info!(
+                "识别文本过长 ({} 字)，截断到 {} 字",
+                clean_text.chars().count(),
+                config.max_transcript_chars
+            );
+        }
+        text
+    };
+
+    let store = should_store(rejection, &config);
+    let audio_path = if store && config.store_audio && !settings.emotion_only {
+        save_clip(state, &settings.session_id, audio_data).await
+    } else {
+        None
+    };
+
+    let mut result = SpeechResult {
+        session_id: settings.session_id.clone(),
+        text,
+        // 原始文本用于排查，不去除唤醒词和窗口重叠
+        text_raw: (config.include_raw_text && !settings.emotion_only).then(|| {
+            text::truncate_with_ellipsis(transcript.raw_text.trim(), config.max_transcript_chars).0
+        }),
+        emotion,
+        confidence: transcript.confidence,
+        fingerprint: config
+            .acoustic_fingerprint
+            .then(|| acoustic::fingerprint(audio_data)),
+        vad: config.vad_metadata.then_some(utterance.vad),
+        prosody: config
+            .prosody_metrics
+            .then(|| prosody::analyze(&transcript.segments, &transcript.tokens))
+            .flatten(),
+        tokens: (config.token_timings && !settings.emotion_only).then_some(transcript.tokens),
+        timings: Timings {
+            recognition_ms,
+            emotion_ms,
+        },
+        asr_model: config
+            .include_model_ids
+            .then(|| recognizer.model_name().to_string()),
+        // 低质量和过短的文本不调用 Ollama，情绪不是模型给出的
+        emotion_model: (config.include_model_ids && rejection.is_none() && !skipped_short)
+            .then(|| state.emotion_analyzer.model_name()),
+        detected_language: transcript.detected_language,
+        created_at: result::now_rfc3339(config.timezone),
+        rejection: rejection.map(str::to_string),
+        audio_path,
+        persist_failed: false,
+        offset_ms: utterance.offset_ms,
+        segments: if settings.emotion_only {
+            Vec::new()
+        } else {
+            transcript.segments
+        },
+    };
+    if store && !hooks.defer_store {
+        result.persist_failed = !sink::dispatch(&state.sinks, &result).await;
+    }
+
+    Some(result)
+}
+
+/// 在推理线程上调用的分段回调，参数为分段序号和原始文本
+type SegmentHook = Box<dyn FnMut(usize, &str) + Send>;
+
+/// 在阻塞线程池中识别，模型重新加载和推理期间不占用异步工作线程
+///
+/// 回调在推理线程上调用；`abort` 置位后推理尽快中止。识别 panic 时在调用方继续展开。
+async fn recognize_blocking(
+    recognizer: Arc<dyn Recognizer>,
+    audio: Vec<f32>,
+    initial_prompt: Option<String>,
+    mut on_progress: Option<Box<dyn FnMut(i32) + Send>>,
+    mut on_segment: Option<SegmentHook>,
+    abort: Option<Arc<AtomicBool>>,
+) -> Transcript {
+    let recognized = tokio::task::spawn_blocking(move || {
+        recognizer.recognize_with_callbacks(
+            &audio,
+            initial_prompt.as_deref(),
+            RecognizeCallbacks {
+                on_progress: on_progress
+                    .as_mut()
+                    .map(|callback| callback.as_mut() as &mut dyn FnMut(i32)),
+                on_segment: on_segment
+                    .as_mut()
+                    .map(|callback| callback.as_mut() as &mut dyn FnMut(usize, &str)),
+                abort: abort.as_deref(),
+            },
+        )
+    })
+    .await;
+    match recognized {
+        Ok(transcript) => transcript,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => {
+            error!("识别任务被取消: {}", e);
+            Transcript::default()
+        }
+    }
+}
+
+/// 识别语句开头检测唤醒词，检测结果之外的文本不记录
+async fn detect_wake_phrase(
+    recognizer: &Arc<dyn Recognizer>,
+    wake: &mut WakeGate,
+    audio: &[f32],
+) -> bool {
+    let head = audio[..audio.len().min(wake::DETECT_SAMPLES)].to_vec();
+    let heard = recognize_blocking(recognizer.clone(), head, None, None, None, None).await;
+    let woken = wake.try_wake(&heard.text);
+    if woken {
+        info!("检测到唤醒词");
+    } else {
+        debug!("未唤醒，忽略语音");
+    }
+    woken
+}
+
+/// 为每个新识别出的分段启动一次情绪分析，结果通过 `tx` 发送
+///
+/// 回调只把分段交给识别开始前已启动的分发任务，由它为每个分段启动分析任务；
+/// 不在推理线程上创建任务，分析不必等整句识别结束才被调度。分段按识别顺序启动，但完成顺序不保证。
+fn segment_emotion_spawner(
+    state: &AppState,
+    settings: &SessionSettings,
+    wake: WakeGate,
+    utterance: u64,
+    tx: mpsc::UnboundedSender<SegmentEmotion>,
+) -> SegmentHook {
+    let analyzer = state.emotion_analyzer.clone();
+    let config = state.config();
+    let normalize = config.normalize_transcript;
+    let min_content_chars = config.min_content_chars;
+    let min_words = config.emotion_min_words;
+    let include_text = !settings.emotion_only;
+
+    // 回调随识别结束释放，发送端关闭后分发任务退出
+    let (segment_tx, mut segment_rx) = mpsc::unbounded_channel::<(usize, String)>();
+    tokio::spawn(async move {
+        while let Some((segment, text)) = segment_rx.recv().await {
+            let analyzer = analyzer.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let emotion = analyzer.analyze(&text, None).await;
+                let _ = tx.send(SegmentEmotion {
+                    utterance,
+                    segment,
+                    text: include_text.then_some(text),
+                    emotion,
+                });
+            });
+        }
+    });
+
+    Box::new(move |segment, raw| {
+        let raw = raw.trim();
+        let raw = if segment == 0 { wake.strip(raw) } else { raw };
+        let text = if normalize {
+            text::normalize_transcript(raw)
+        } else {
+            raw.to_string()
+        };
+        if rejection_reason(&text, min_content_chars).is_some() || is_too_short(&text, min_words) {
+            return;
+        }
+        let _ = segment_tx.send((segment, text));
+    })
+}
+
+/// 保存语音片段，失败时只记录日志，不影响识别结果
+async fn save_clip(state: &AppState, session_id: &str, audio_data: &[f32]) -> Option<String> {
+    match clip::save(&state.config().audio_dir, session_id, audio_data).await {
+        Ok(path) => Some(path),
+        Err(e) => {
+            warn!("保存语音片段失败: {}", e);
+            None
+        }
+    }
+}
+
+/// 词数少于 `min_words` 的语句（如"嗯"、"好"）情绪信号很弱，不值得调用 LLM
+fn is_too_short(text: &str, min_words: usize) -> bool {
+    min_words > 0 && prosody::count_words(text) < min_words
+}
+
+/// 识别结果未通过有效性检查的原因，有效时返回 None
+///
+/// 字母、数字和中日韩文字少于 `min_content_chars` 个的文本（如只有 `。` 或空白）视为没有内容。
+fn rejection_reason(text: &str, min_content_chars: usize) -> Option<&'static str> {
+    if text.is_empty() {
+        Some("empty")
+    } else if text.chars().filter(|c| c.is_alphanumeric()).count() < min_content_chars {
+        Some("no_content")
+    } else if text == "你去找我吧" {
+        // Whisper 在噪声输入上的常见幻觉输出
+        Some("hallucination")
+    } else {
+        None
+    }
+}
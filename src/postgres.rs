@@ -0,0 +1,407 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, NoTls, Row};
+use tracing::{info, warn};
+
+use crate::history::{HistoryStore, StoreError};
+use crate::result::{self, SpeechResult, Timings};
+use crate::shadow::ShadowComparison;
+use crate::storage::{self, HistoryRecord, WriteClock, MAX_TEXT_CHARS, RECORD_COLUMNS};
+use crate::text;
+
+/// 建表时持有的咨询锁，避免多个实例同时启动时并发建表冲突
+const MIGRATION_LOCK_KEY: i64 = 0x6865_6172_745f_6d72;
+
+/// 多实例共享的 Postgres 历史库
+///
+/// 首次访问时连接并建表，连接断开后下次访问重新连接。连接不加密，需经内网或隧道访问。
+pub struct PostgresStore {
+    config: tokio_postgres::Config,
+    client: Mutex<Option<Arc<Client>>>,
+    last_write: WriteClock,
+}
+
+impl PostgresStore {
+    /// 解析 `postgres://` 连接串，此时不建立连接
+    pub fn open(url: &str) -> Result<Self, tokio_postgres::Error> {
+        Ok(Self {
+            config: url.parse()?,
+            client: Mutex::new(None),
+            last_write: WriteClock::default(),
+        })
+    }
+
+    /// 返回可用的连接，尚未连接或连接已断开时重新连接
+    async fn client(&self) -> Result<Arc<Client>, tokio_postgres::Error> {
+        let mut client = self.client.lock().await;
+        if let Some(client) = client.as_ref().filter(|client| !client.is_closed()) {
+            return Ok(client.clone());
+        }
+
+        let (connected, connection) = self.config.connect(NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Postgres 连接断开: {}", e);
+            }
+        });
+        migrate(&connected).await?;
+        info!("已连接 Postgres 历史库");
+
+        let connected = Arc::new(connected);
+        *client = Some(connected.clone());
+        Ok(connected)
+    }
+}
+
+#[async_trait]
+impl HistoryStore for PostgresStore {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn insert_speech_result(&self, result: &SpeechResult) -> Result<(), StoreError> {
+        let client = self.client().await?;
+        self.last_write.mark();
+        client
+            .execute(
+                "INSERT INTO speech_results (
+                    session_id, text, emotion, confidence, recognition_ms, emotion_ms,
+                    created_at, quality, rejection, fingerprint, audio_path, vad,
+                    asr_model, emotion_model, detected_language, text_raw,
+                    prosody, created_at_ms
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)",
+                &[
+                    &result.session_id,
+                    &text::truncate_chars(&result.text, MAX_TEXT_CHARS),
+                    &result.emotion,
+                    &result.confidence,
+                    &(result.timings.recognition_ms as i64),
+                    &(result.timings.emotion_ms as i64),
+                    &result.created_at,
+                    &result.quality(),
+                    &result.rejection,
+                    &storage::to_json(result.fingerprint),
+                    &result.audio_path,
+                    &storage::to_json(result.vad),
+                    &result.asr_model,
+                    &result.emotion_model,
+                    &result.detected_language,
+                    &result
+                        .text_raw
+                        .as_deref()
+                        .map(|raw| text::truncate_chars(raw, MAX_TEXT_CHARS)),
+                    &storage::to_json(result.prosody),
+                    &DateTime::parse_from_rfc3339(&result.created_at)
+                        .ok()
+                        .map(|time| time.timestamp_millis()),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_shadow_comparison(
+        &self,
+        comparison: &ShadowComparison,
+    ) -> Result<(), StoreError> {
+        let client = self.client().await?;
+        self.last_write.mark();
+        client
+            .execute(
+                "INSERT INTO shadow_results (
+                    session_id, primary_model, shadow_model, primary_text, shadow_text,
+                    edit_distance, primary_ms, shadow_ms, created_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                &[
+                    &comparison.session_id,
+                    &comparison.primary_model,
+                    &comparison.shadow_model,
+                    &text::truncate_chars(&comparison.primary_text, MAX_TEXT_CHARS),
+                    &text::truncate_chars(&comparison.shadow_text, MAX_TEXT_CHARS),
+                    &(comparison.edit_distance as i64),
+                    &(comparison.primary_ms as i64),
+                    &(comparison.shadow_ms as i64),
+                    &comparison.created_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_speech_result(&self, id: i64) -> Result<Option<HistoryRecord>, StoreError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "SELECT {} FROM speech_results WHERE id = $1",
+                    RECORD_COLUMNS
+                ),
+                &[&id],
+            )
+            .await?;
+        Ok(row.as_ref().map(history_record_from_row).transpose()?)
+    }
+
+    async fn count_speech_results_since(&self, since_ms: Option<i64>) -> Result<usize, StoreError> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                "SELECT COUNT(*) FROM speech_results
+                 WHERE $1::BIGINT IS NULL OR created_at_ms >= $1",
+                &[&since_ms],
+            )
+            .await?;
+        Ok(row.try_get::<_, i64>(0)? as usize)
+    }
+
+    async fn list_speech_results_page(
+        &self,
+        since_ms: Option<i64>,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryRecord>, StoreError> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT {} FROM speech_results
+                     WHERE ($1::BIGINT IS NULL OR created_at_ms >= $1) AND id > $2
+                     ORDER BY id LIMIT $3",
+                    RECORD_COLUMNS
+                ),
+                &[&since_ms, &after_id, &(limit as i64)],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(history_record_from_row)
+            .collect::<Result<_, _>>()?)
+    }
+
+    async fn update_emotion(
+        &self,
+        id: i64,
+        old_emotion: &str,
+        new_emotion: &str,
+        model: &str,
+        timezone: Tz,
+    ) -> Result<(), StoreError> {
+        let client = self.client().await?;
+        self.last_write.mark();
+        // 更新和审计在同一条语句中完成，记录不存在时不写审计
+        client
+            .execute(
+                "WITH updated AS (
+                    UPDATE speech_results SET emotion = $2, emotion_model = $3
+                    WHERE id = $1 RETURNING id
+                )
+                INSERT INTO emotion_audit (record_id, old_emotion, new_emotion, model, changed_at)
+                SELECT id, $4, $2, $3, $5 FROM updated",
+                &[
+                    &id,
+                    &new_emotion,
+                    &model,
+                    &old_emotion,
+                    &result::now_rfc3339(timezone),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn check_writable(&self) -> Result<(), StoreError> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                "SELECT pg_is_in_recovery() OR current_setting('transaction_read_only') = 'on'",
+                &[],
+            )
+            .await?;
+        if row.try_get::<_, bool>(0)? {
+            return Err("数据库为只读".into());
+        }
+        Ok(())
+    }
+
+    async fn vacuum(&self) -> Result<(), StoreError> {
+        self.client()
+            .await?
+            .batch_execute("VACUUM (ANALYZE) speech_results, emotion_audit, shadow_results")
+            .await?;
+        Ok(())
+    }
+
+    fn idle_duration(&self) -> Duration {
+        self.last_write.elapsed()
+    }
+}
+
+/// 确保表结构存在，列与 SQLite 历史库一致
+async fn migrate(client: &Client) -> Result<(), tokio_postgres::Error> {
+    client
+        .batch_execute(&format!(
+            "BEGIN;
+            SELECT pg_advisory_xact_lock({});
+            CREATE TABLE IF NOT EXISTS speech_results (
+                id BIGSERIAL PRIMARY KEY,
+                session_id TEXT,
+                text TEXT NOT NULL,
+                emotion TEXT NOT NULL,
+                confidence REAL,
+                recognition_ms BIGINT,
+                emotion_ms BIGINT,
+                created_at TEXT NOT NULL,
+                quality TEXT,
+                rejection TEXT,
+                fingerprint TEXT,
+                audio_path TEXT,
+                vad TEXT,
+                asr_model TEXT,
+                emotion_model TEXT,
+                detected_language TEXT,
+                text_raw TEXT,
+                prosody TEXT,
+                created_at_ms BIGINT
+            );
+            CREATE INDEX IF NOT EXISTS speech_results_created_at_ms
+                ON speech_results (created_at_ms);
+            CREATE TABLE IF NOT EXISTS emotion_audit (
+                id BIGSERIAL PRIMARY KEY,
+                record_id BIGINT NOT NULL,
+                old_emotion TEXT NOT NULL,
+                new_emotion TEXT NOT NULL,
+                model TEXT NOT NULL,
+                changed_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS shadow_results (
+                id BIGSERIAL PRIMARY KEY,
+                session_id TEXT NOT NULL,
+                primary_model TEXT NOT NULL,
+                shadow_model TEXT NOT NULL,
+                primary_text TEXT NOT NULL,
+                shadow_text TEXT NOT NULL,
+                edit_distance BIGINT NOT NULL,
+                primary_ms BIGINT NOT NULL,
+                shadow_ms BIGINT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            COMMIT;",
+            MIGRATION_LOCK_KEY
+        ))
+        .await
+}
+
+/// 按 `RECORD_COLUMNS` 的顺序读取一行
+fn history_record_from_row(row: &Row) -> Result<HistoryRecord, tokio_postgres::Error> {
+    Ok(HistoryRecord {
+        id: row.try_get(0)?,
+        result: SpeechResult {
+            session_id: row.try_get::<_, Option<String>>(1)?.unwrap_or_default(),
+            text: row.try_get(2)?,
+            text_raw: row.try_get(15)?,
+            emotion: row.try_get(3)?,
+            confidence: row.try_get(4)?,
+            timings: Timings {
+                recognition_ms: row.try_get::<_, Option<i64>>(5)?.unwrap_or_default() as u64,
+                emotion_ms: row.try_get::<_, Option<i64>>(6)?.unwrap_or_default() as u64,
+            },
+            created_at: row.try_get(7)?,
+            rejection: row.try_get(8)?,
+            fingerprint: storage::from_json(row.try_get(9)?),
+            audio_path: row.try_get(10)?,
+            persist_failed: false,
+            vad: storage::from_json(row.try_get(11)?),
+            prosody: storage::from_json(row.try_get(16)?),
+            asr_model: row.try_get(12)?,
+            emotion_model: row.try_get(13)?,
+            detected_language: row.try_get(14)?,
+            tokens: None,
+            offset_ms: 0,
+            segments: Vec::new(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 需要可写的测试库，未设置 `TEST_POSTGRES_URL` 时跳过
+    fn test_store() -> Option<PostgresStore> {
+        let url = std::env::var("TEST_POSTGRES_URL").ok()?;
+        Some(PostgresStore::open(&url).unwrap())
+    }
+
+    #[tokio::test]
+    async fn records_round_trip_through_postgres() {
+        let Some(store) = test_store() else { return };
+        let client = store.client().await.unwrap();
+        client
+            .batch_execute("TRUNCATE speech_results, emotion_audit RESTART IDENTITY")
+            .await
+            .unwrap();
+
+        for (i, text) in ["一", "二", "三"].iter().enumerate() {
+            let result = SpeechResult {
+                session_id: "session".to_string(),
+                text: text.to_string(),
+                text_raw: None,
+                emotion: "平静".to_string(),
+                confidence: Some(0.9),
+                timings: Timings {
+                    recognition_ms: 120,
+                    emotion_ms: 80,
+                },
+                fingerprint: None,
+                vad: None,
+                prosody: None,
+                tokens: None,
+                asr_model: Some("ggml-small.bin".to_string()),
+                emotion_model: None,
+                detected_language: None,
+                created_at: format!("2024-05-01T08:0{}:00+08:00", i),
+                rejection: None,
+                audio_path: None,
+                persist_failed: false,
+                offset_ms: 0,
+                segments: Vec::new(),
+            };
+            store.insert_speech_result(&result).await.unwrap();
+        }
+        store
+            .update_emotion(2, "平静", "悲伤", "llama", Tz::Asia__Shanghai)
+            .await
+            .unwrap();
+        store.check_writable().await.unwrap();
+        store.vacuum().await.unwrap();
+
+        // 2024-05-01T00:01:00Z，即第二条记录的时间
+        let since_ms = Some(1_714_521_660_000);
+        assert_eq!(store.count_speech_results_since(None).await.unwrap(), 3);
+        assert_eq!(store.count_speech_results_since(since_ms).await.unwrap(), 2);
+        let page = store
+            .list_speech_results_page(since_ms, 0, 1)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].result.text, "二");
+        assert_eq!(page[0].result.emotion, "悲伤");
+        assert_eq!(page[0].result.emotion_model.as_deref(), Some("llama"));
+        assert_eq!(page[0].result.timings.recognition_ms, 120);
+        assert!(store.get_speech_result(4).await.unwrap().is_none());
+        let audits: i64 = client
+            .query_one("SELECT COUNT(*) FROM emotion_audit", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(audits, 1);
+    }
+}
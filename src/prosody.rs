@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+use crate::speech::{SegmentTiming, TokenTiming};
+
+/// 相邻发声之间短于该毫秒数的间隔不计为停顿，时间戳本身有几十毫秒误差
+const MIN_PAUSE_MS: u64 = 200;
+
+/// 单句语音的语速和停顿统计，用于演讲训练等场景
+///
+/// 时间均为毫秒，基于 Whisper 时间戳估计，精度与 `TokenTiming` 相同。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Prosody {
+    /// 词数：中日韩文字每字计一词，其他文字按空白分词
+    pub word_count: usize,
+    /// 第一个词开始到最后一个词结束的时长
+    pub speech_ms: u64,
+    /// 语速（词/分钟），按 `speech_ms` 计算
+    pub words_per_minute: f32,
+    /// 不短于 200ms 的停顿个数
+    pub pause_count: usize,
+    /// 最长停顿
+    pub longest_pause_ms: u64,
+    /// 停顿总时长
+    pub total_pause_ms: u64,
+}
+
+/// 计算语速和停顿，有逐 token 时间戳时按 token 计算停顿，否则按分段
+///
+/// 没有识别出文字时返回 `None`。
+pub fn analyze(segments: &[SegmentTiming], tokens: &[TokenTiming]) -> Option<Prosody> {
+    let word_count = segments
+        .iter()
+        .map(|segment| count_words(&segment.text))
+        .sum();
+    if word_count == 0 {
+        return None;
+    }
+
+    let spans: Vec<(u64, u64)> = if tokens.is_empty() {
+        segments
+            .iter()
+            .filter(|segment| !segment.text.trim().is_empty())
+            .map(|segment| (segment.start_ms, segment.end_ms))
+            .collect()
+    } else {
+        tokens
+            .iter()
+            .map(|token| (token.start_ms, token.end_ms))
+            .collect()
+    };
+    let start = spans.iter().map(|&(start, _)| start).min()?;
+    let end = spans.iter().map(|&(_, end)| end).max()?;
+    let speech_ms = end.saturating_sub(start);
+
+    let pauses: Vec<u64> = spans
+        .windows(2)
+        .map(|pair| pair[1].0.saturating_sub(pair[0].1))
+        .filter(|&gap| gap >= MIN_PAUSE_MS)
+        .collect();
+
+    Some(Prosody {
+        word_count,
+        speech_ms,
+        words_per_minute: if speech_ms == 0 {
+            0.0
+        } else {
+            word_count as f32 * 60_000.0 / speech_ms as f32
+        },
+        pause_count: pauses.len(),
+        longest_pause_ms: pauses.iter().copied().max().unwrap_or(0),
+        total_pause_ms: pauses.iter().sum(),
+    })
+}
+
+/// 中日韩文字每字计一词，其余连续的字母数字计一词，标点不计
+pub fn count_words(text: &str) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            count += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() || (in_word && c == '\'') {
+            if !in_word {
+                count += 1;
+            }
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+    count
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cjk_characters_count_as_words() {
+        assert_eq!(count_words("今天很好"), 4);
+        assert_eq!(count_words("ありがとう"), 5);
+        assert_eq!(count_words("안녕"), 2);
+        assert_eq!(count_words("今天，很好！"), 4);
+    }
+
+    #[test]
+    fn mixed_cjk_and_latin_text() {
+        assert_eq!(count_words("我用 iPhone 15 拍照"), 6);
+        assert_eq!(count_words("GPT4很棒"), 3);
+        assert_eq!(count_words("hello世界world"), 4);
+    }
+
+    #[test]
+    fn apostrophes_stay_inside_words() {
+        assert_eq!(count_words("I'm happy"), 2);
+        assert_eq!(count_words("don't 不要"), 3);
+        assert_eq!(count_words("rock'n'roll"), 1);
+        assert_eq!(count_words("'quoted' words"), 2);
+    }
+
+    #[test]
+    fn punctuation_and_whitespace_are_not_words() {
+        assert_eq!(count_words(""), 0);
+        assert_eq!(count_words("  ，。！ ... ' "), 0);
+    }
+}
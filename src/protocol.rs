@@ -1,67 +1,492 @@
-use chrono::{TimeZone, Utc};
-use chrono_tz::Asia::Shanghai;
-use rusqlite::{params, Connection, Result};
+use chrono::{Timelike, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 
+use crate::acoustic::AcousticFingerprint;
+use crate::audio::{AudioLevel, VadMetadata};
+use crate::emotion::{EmotionActions, EmotionLabels};
+use crate::framing::{Compression, Framing};
+use crate::pipeline::SegmentEmotion;
+use crate::prosody::Prosody;
+use crate::recent::RecentTranscript;
+use crate::result::SpeechResult;
+use crate::speech::TokenTiming;
+
+/// 服务器实现的协议版本
+pub const PROTOCOL_VERSION: u32 = 2;
+/// 仍兼容的最低协议版本，未声明版本的旧客户端按此处理
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// 从该版本起客户端能处理 `warning`/`info` 通知
+const NOTICES_SINCE_VERSION: u32 = 2;
+
+/// 根据客户端声明的协议版本协商实际使用的版本
+///
+/// 客户端版本高于服务器时按服务器版本降级，低于最低兼容版本时拒绝。
+pub fn negotiate_version(client_version: u32) -> Result<u32, ErrorResponse> {
+    if client_version < MIN_PROTOCOL_VERSION {
+        return Err(ErrorResponse::unsupported_protocol(client_version));
+    }
+    Ok(client_version.min(PROTOCOL_VERSION))
+}
+
+/// 协商后的版本是否支持服务器通知
+pub fn supports_notices(version: u32) -> bool {
+    version >= NOTICES_SINCE_VERSION
+}
+
+fn default_protocol_version() -> u32 {
+    MIN_PROTOCOL_VERSION
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum DeviceMessage {
-    Hello { version: String },
-    Event { key: String, value: String },
+    Hello {
+        version: String,
+        /// 客户端实现的协议版本，旧客户端不发送时视为最低兼容版本
+        #[serde(default = "default_protocol_version")]
+        protocol_version: u32,
+        /// 客户端语言，如 `zh-CN`，用于选择 Whisper 模型
+        #[serde(default)]
+        locale: Option<String>,
+        /// 本连接使用的 Whisper 提示词，用于偏向客户端领域词汇
+        #[serde(default)]
+        initial_prompt: Option<String>,
+        /// 只返回情绪，服务器不回传也不存储识别原文
+        #[serde(default)]
+        emotion_only: bool,
+        /// 握手之后使用的消息编码，`binary` 为长度前缀的二进制帧
+        #[serde(default)]
+        framing: Framing,
+        /// 服务器→客户端大消息的压缩方式，仅 `json` 编码下生效
+        #[serde(default)]
+        compression: Compression,
+        /// Opus 解码增益（dB），用于补偿音量偏低的客户端编码器
+        #[serde(default)]
+        decoder_gain_db: Option<f32>,
+        /// 每条音频消息以 1 字节编码标识开头，可在流中切换 Opus 和 PCM
+        #[serde(default)]
+        codec_header: bool,
+    },
+    Event {
+        key: String,
+        value: String,
+    },
+    /// 仅分析文本情绪，不经过语音识别
+    AnalyzeText {
+        text: String,
+        /// 文本语言提示，如 `zh`、`en`
+        #[serde(default)]
+        language: Option<String>,
+    },
+    /// 查询本连接最近的识别结果
+    GetRecent {
+        /// 返回的句数，未指定时返回全部保留的结果
+        #[serde(default)]
+        n: Option<usize>,
+    },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ServerResponse {
     #[serde(rename = "type")]
     pub msg_type: String,
     pub emotion: String,
+    /// 情绪对应的客户端动作，配置了 `EMOTION_ACTIONS_FILE` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
+    /// 规范化前的 Whisper 原始文本，开启 `INCLUDE_RAW_TEXT` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_raw: Option<String>,
+    /// 服务器协议版本，仅在初始连接响应中携带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u32>,
+    /// 识别置信度（token 概率均值）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// 粗略声学特征，用于按说话人启发式分组
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<AcousticFingerprint>,
+    /// 端点检测信息，用于排查语句切分问题
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vad: Option<VadMetadata>,
+    /// 语速和停顿统计，开启 `PROSODY_METRICS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prosody: Option<Prosody>,
+    /// 逐 token 时间戳，用于口型同步和逐字高亮
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<TokenTiming>>,
+    /// 识别使用的 Whisper 模型，开启 `INCLUDE_MODEL_IDS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asr_model: Option<String>,
+    /// 情绪分析使用的 Ollama 模型，开启 `INCLUDE_MODEL_IDS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emotion_model: Option<String>,
+    /// Whisper 自动检测出的语言代码，识别模型语言为 `auto` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    /// 语句在会话中的序号，开启 `SEGMENT_EMOTION` 或 `UTTERANCE_COALESCE_MS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utterance: Option<u64>,
+    /// 合并重新识别的结果所取代的上一句序号
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<u64>,
+    /// 结果未能写入输出端时为 `true`，正常时省略
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub persist_failed: bool,
+    /// 结果质量，未通过有效性检查的结果为 `low`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<&'static str>,
+    /// 低质量结果的原因，如 `empty`、`no_content`、`hallucination`、`not_meaningful`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
-impl ServerResponse {
-    /// 创建初始连接响应
-    pub fn initial_connection() -> Self {
+/// 单个识别分段的情绪，开启 `SEGMENT_EMOTION` 时在分析完成后立即推送
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentEmotionResponse {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub utterance: u64,
+    pub segment: usize,
+    pub emotion: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl SegmentEmotionResponse {
+    /// 由分段情绪创建推送消息，情绪替换为客户端标签
+    pub fn new(segment: SegmentEmotion, labels: &EmotionLabels) -> Self {
+        let mut emotion = segment.emotion;
+        labels.apply(&mut emotion);
         Self {
-            msg_type: "llm".to_string(),
-            emotion: "calm".to_string(),
-            text: Some("Connected & Ready".to_string()),
+            msg_type: "segment_emotion".to_string(),
+            utterance: segment.utterance,
+            segment: segment.segment,
+            emotion,
+            text: segment.text,
         }
     }
+}
+
+/// 握手确认，告知客户端协商后的协议版本
+#[derive(Debug, Serialize)]
+pub struct HelloAck {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub protocol_version: u32,
+    /// 协商后的压缩方式，不压缩时省略
+    #[serde(skip_serializing_if = "is_uncompressed")]
+    pub compression: Compression,
+}
+
+fn is_uncompressed(compression: &Compression) -> bool {
+    *compression == Compression::None
+}
+
+impl HelloAck {
+    pub fn new(protocol_version: u32, compression: Compression) -> Self {
+        Self {
+            msg_type: "hello_ack".to_string(),
+            protocol_version,
+            compression,
+        }
+    }
+}
+
+/// `get_recent` 的回复，按时间顺序排列
+#[derive(Debug, Serialize)]
+pub struct RecentResponse {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub transcripts: Vec<RecentTranscript>,
+}
 
-    /// 创建语音识别结果响应
-    pub fn speech_result(text: String, emotion: String) -> Self {
-        // Write result to SQLite database
-        if let Err(e) = insert_speech_result(&text, &emotion) {
-            eprintln!("Failed to insert speech result: {}", e);
+impl RecentResponse {
+    pub fn new(transcripts: Vec<RecentTranscript>) -> Self {
+        Self {
+            msg_type: "recent".to_string(),
+            transcripts,
         }
+    }
+}
+
+/// 定期上报的麦克风输入电平，不论是否有人说话，用于远程发现失效或饱和的麦克风
+#[derive(Debug, Serialize)]
+pub struct AudioLevelReport {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    /// 上报间隔内全部输入的 RMS（i16 幅度）
+    pub rms: f32,
+    /// 上报间隔内最安静的一帧的 RMS，近似环境噪声
+    pub noise_floor: f32,
+}
+
+impl AudioLevelReport {
+    pub fn new(level: AudioLevel) -> Self {
+        Self {
+            msg_type: "audio_level".to_string(),
+            rms: level.rms,
+            noise_floor: level.noise_floor,
+        }
+    }
+}
+
+/// 控制消息确认，`ref` 为 `hello` 或事件的 `key`
+#[derive(Debug, Serialize)]
+pub struct ControlAck {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    #[serde(rename = "ref")]
+    pub reference: String,
+}
+
+impl ControlAck {
+    pub fn new(reference: &str) -> Self {
+        Self {
+            msg_type: "ack".to_string(),
+            reference: reference.to_string(),
+        }
+    }
+}
+
+/// 服务器主动推送的状态通知（`warning`/`info`），如降级告警和恢复；错误见 `ErrorResponse`
+#[derive(Debug, Serialize)]
+pub struct ServerNotice {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl ServerNotice {
+    /// 情绪分析后端不可用，结果退化为默认情绪
+    pub fn emotion_degraded() -> Self {
+        Self {
+            msg_type: "warning".to_string(),
+            code: "emotion_degraded".to_string(),
+            message: "情绪分析服务暂不可用，情绪结果将使用默认值".to_string(),
+        }
+    }
+
+    /// 情绪分析后端已恢复
+    pub fn emotion_recovered() -> Self {
+        Self {
+            msg_type: "info".to_string(),
+            code: "emotion_recovered".to_string(),
+            message: "情绪分析服务已恢复".to_string(),
+        }
+    }
+}
+
+/// 发给客户端的错误码，客户端应按 `code` 而不是 `message` 处理错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// 客户端协议版本过旧，连接随后关闭
+    UnsupportedProtocol,
+    /// 识别模型尚未就绪，连接随后关闭
+    ModelNotReady,
+    /// 同一来源 IP 新建连接过于频繁，连接随后关闭
+    RateLimited,
+    /// 开启严格握手时握手前发送了音频，音频被丢弃
+    HelloRequired,
+    /// 二进制帧或 NDJSON 上传的 Opus 帧无法解析，整条消息被丢弃
+    InvalidFrame,
+    /// 音频消息的编码标识无效，该消息被丢弃
+    InvalidCodec,
+    /// 二进制帧的操作码服务器不支持，该帧被忽略
+    UnsupportedOpcode,
+    /// 服务器内部错误，连接随后关闭
+    Internal,
+}
+
+/// 统一的错误消息：`{"type": "error", "code": ..., "message": ...}`
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub code: ErrorCode,
+    /// 供人阅读的说明，内容可能变化
+    pub message: String,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            msg_type: "error".to_string(),
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// 识别模型不可用，连接将被关闭
+    pub fn model_not_ready() -> Self {
+        Self::new(ErrorCode::ModelNotReady, "语音识别模型尚未就绪，请稍后重连")
+    }
+
+    /// 来源 IP 连接过于频繁，连接将被关闭
+    pub fn rate_limited() -> Self {
+        Self::new(ErrorCode::RateLimited, "连接过于频繁，请稍后重连")
+    }
+
+    /// 开启严格握手时，`hello` 之前发送的音频被丢弃
+    pub fn hello_required() -> Self {
+        Self::new(
+            ErrorCode::HelloRequired,
+            "请先发送 hello 握手消息，握手前的音频已被丢弃",
+        )
+    }
+
+    /// 客户端协议版本过旧，连接将被关闭
+    pub fn unsupported_protocol(client_version: u32) -> Self {
+        Self::new(
+            ErrorCode::UnsupportedProtocol,
+            format!(
+                "不支持的协议版本 {}，服务器支持 {} 至 {}",
+                client_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+        )
+    }
+
+    /// 帧解析失败，`detail` 为具体原因
+    pub fn invalid_frame(detail: impl std::fmt::Display) -> Self {
+        Self::new(
+            ErrorCode::InvalidFrame,
+            format!("音频帧解析失败: {}", detail),
+        )
+    }
+
+    /// 音频消息的编码标识无效
+    pub fn invalid_codec(detail: impl std::fmt::Display) -> Self {
+        Self::new(
+            ErrorCode::InvalidCodec,
+            format!("音频消息编码标识无效: {}", detail),
+        )
+    }
+
+    /// 不支持的二进制帧操作码
+    pub fn unsupported_opcode(opcode: impl std::fmt::Debug) -> Self {
+        Self::new(
+            ErrorCode::UnsupportedOpcode,
+            format!("不支持的操作码: {:?}", opcode),
+        )
+    }
+
+    /// 识别任务异常退出，连接将被关闭
+    pub fn internal() -> Self {
+        Self::new(ErrorCode::Internal, "服务器内部错误，请重新连接")
+    }
+}
+
+impl ServerResponse {
+    /// 创建初始连接响应，给出 `greeting_timezone` 时按该时区的时间段生成问候语
+    pub fn initial_connection(greeting_timezone: Option<Tz>) -> Self {
+        let (emotion, text) = match greeting_timezone {
+            Some(timezone) => greeting_for_hour(Utc::now().with_timezone(&timezone).hour()),
+            None => ("calm", "Connected & Ready"),
+        };
+
         Self {
             msg_type: "llm".to_string(),
+            emotion: emotion.to_string(),
+            text: Some(text.to_string()),
+            text_raw: None,
+            protocol_version: Some(PROTOCOL_VERSION),
+            confidence: None,
+            fingerprint: None,
+            vad: None,
+            prosody: None,
+            tokens: None,
+            asr_model: None,
+            emotion_model: None,
+            detected_language: None,
+            utterance: None,
+            replaces: None,
+            action: None,
+            persist_failed: false,
+            quality: None,
+            reason: None,
+        }
+    }
+
+    /// 创建文本情绪分析响应
+    pub fn text_emotion(text: String, emotion: String) -> Self {
+        Self {
+            msg_type: "emotion".to_string(),
             emotion,
             text: Some(text),
+            text_raw: None,
+            protocol_version: None,
+            confidence: None,
+            fingerprint: None,
+            vad: None,
+            prosody: None,
+            tokens: None,
+            asr_model: None,
+            emotion_model: None,
+            detected_language: None,
+            utterance: None,
+            replaces: None,
+            action: None,
+            persist_failed: false,
+            quality: None,
+            reason: None,
         }
     }
+
+    /// 由识别结果创建响应，`include_text` 为 false 时（仅情绪模式）不携带文本
+    pub fn speech_result(result: SpeechResult, include_text: bool) -> Self {
+        let quality = result.quality();
+        Self {
+            msg_type: "llm".to_string(),
+            emotion: result.emotion,
+            text: include_text.then_some(result.text),
+            text_raw: result.text_raw.filter(|_| include_text),
+            protocol_version: None,
+            confidence: result.confidence,
+            fingerprint: result.fingerprint,
+            vad: result.vad,
+            prosody: result.prosody,
+            tokens: result.tokens.filter(|_| include_text),
+            asr_model: result.asr_model,
+            emotion_model: result.emotion_model,
+            detected_language: result.detected_language,
+            utterance: None,
+            replaces: None,
+            action: None,
+            persist_failed: result.persist_failed,
+            quality,
+            reason: result.rejection,
+        }
+    }
+}
+
+impl ServerResponse {
+    /// 按映射填入情绪对应的动作，未配置映射时不变
+    pub fn with_action(mut self, actions: Option<&EmotionActions>) -> Self {
+        self.action = actions
+            .and_then(|actions| actions.action_for(&self.emotion))
+            .map(str::to_string);
+        self
+    }
+
+    /// 替换为客户端情绪标签，需在 `with_action` 之后调用
+    pub fn with_label(mut self, labels: &EmotionLabels) -> Self {
+        labels.apply(&mut self.emotion);
+        self
+    }
 }
 
-/// 将情绪识别结果插入到SQLite数据库
-/// 格式：id, text, emotion, created_at（ISO 8601时间戳）
-fn insert_speech_result(text: &str, emotion: &str) -> rusqlite::Result<()> {
-    let conn = Connection::open("history-emotion.db")?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS speech_results (
-            id INTEGER PRIMARY KEY,
-            text TEXT NOT NULL,
-            emotion TEXT NOT NULL,
-            created_at TEXT NOT NULL
-        )",
-        [],
-    )?;
-    let now = Shanghai
-        .from_utc_datetime(&Utc::now().naive_utc())
-        .to_rfc3339();
-    conn.execute(
-        "INSERT INTO speech_results (text, emotion, created_at) VALUES (?1, ?2, ?3)",
-        params![text, emotion, now],
-    )?;
-    Ok(())
+/// 根据小时返回问候情绪和问候语
+fn greeting_for_hour(hour: u32) -> (&'static str, &'static str) {
+    match hour {
+        5..=10 => ("calm", "早上好"),
+        11..=13 => ("joy", "中午好"),
+        14..=17 => ("joy", "下午好"),
+        18..=22 => ("calm", "晚上好"),
+        _ => ("sleep", "夜深了，早点休息"),
+    }
 }
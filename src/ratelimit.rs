@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+/// 记录的来源地址超过该数量时清理令牌已补满的条目
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// 按来源 IP 限制新连接速率的令牌桶
+///
+/// 每个 IP 最多积累 `burst` 个令牌，每秒补充 `rate` 个，每个新连接消耗一个。
+pub struct ConnectionLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl ConnectionLimiter {
+    /// `rate` 为每秒补充的令牌数，`burst` 不小于 1
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// 允许来自 `ip` 的新连接时消耗一个令牌并返回 true
+    pub fn try_acquire(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// 补充到 `now` 时的令牌数
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn burst_is_spent_per_address() {
+        let limiter = ConnectionLimiter::new(0.0, 3);
+
+        assert!((0..3).all(|_| limiter.try_acquire(CLIENT)));
+        assert!(!limiter.try_acquire(CLIENT));
+        assert!(limiter.try_acquire(OTHER));
+    }
+
+    #[test]
+    fn zero_burst_still_allows_one_connection() {
+        let limiter = ConnectionLimiter::new(0.0, 0);
+
+        assert!(limiter.try_acquire(CLIENT));
+        assert!(!limiter.try_acquire(CLIENT));
+    }
+
+    #[test]
+    fn tokens_refill_over_time_up_to_burst() {
+        let limiter = ConnectionLimiter::new(10.0, 2);
+        assert!(limiter.try_acquire(CLIENT));
+        assert!(limiter.try_acquire(CLIENT));
+        assert!(!limiter.try_acquire(CLIENT));
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(limiter.try_acquire(CLIENT));
+        assert!(!limiter.try_acquire(CLIENT));
+
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(limiter.try_acquire(CLIENT));
+        assert!(limiter.try_acquire(CLIENT));
+        assert!(!limiter.try_acquire(CLIENT));
+    }
+}
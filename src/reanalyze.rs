@@ -0,0 +1,113 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::emotion::EmotionError;
+use crate::pipeline::REDACTED_TEXT;
+use crate::state::AppState;
+use crate::storage::HistoryRecord;
+
+/// 每次从历史库读取的记录数
+const PAGE_SIZE: usize = 100;
+
+/// 同一时间只允许一个重新分析任务
+#[derive(Default)]
+pub struct ReanalyzeJob {
+    running: AtomicBool,
+}
+
+impl ReanalyzeJob {
+    /// 尝试占用任务槽，已有任务运行时返回 None；返回的守卫释放时让出任务槽
+    pub fn try_start(self: &Arc<Self>) -> Option<ReanalyzeGuard> {
+        self.running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| ReanalyzeGuard { job: self.clone() })
+    }
+}
+
+/// 占用中的任务槽，任务结束或 panic 时释放
+pub struct ReanalyzeGuard {
+    job: Arc<ReanalyzeJob>,
+}
+
+impl Drop for ReanalyzeGuard {
+    fn drop(&mut self) {
+        self.job.running.store(false, Ordering::Release);
+    }
+}
+
+/// 用当前模型重新分析 `since_ms`（Unix 毫秒）之后历史记录的情绪，情绪变化时更新并写入审计表
+///
+/// 记录按 ID 分页读取，每条请求之间间隔 `REANALYZE_INTERVAL_MS`，Ollama 熔断时中止。
+pub async fn run(state: AppState, since_ms: Option<i64>, _guard: ReanalyzeGuard) {
+    let analyzer = &state.emotion_analyzer;
+    let (mut processed, mut changed, mut skipped, mut failed) = (0, 0, 0, 0);
+    let mut after_id = 0;
+
+    info!("开始重新分析历史记录，模型: {}", analyzer.model_name());
+
+    'pages: loop {
+        let page = match state
+            .history
+            .list_speech_results_page(since_ms, after_id, PAGE_SIZE)
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                warn!("读取历史记录失败，中止重新分析: {}", e);
+                break;
+            }
+        };
+        let Some(last) = page.last() else { break };
+        after_id = last.id;
+
+        for HistoryRecord { id, result: record } in page {
+            if processed > 0 {
+                tokio::time::sleep(state.config().reanalyze_interval).await;
+            }
+            processed += 1;
+
+            if record.text == REDACTED_TEXT || record.rejection.is_some() {
+                skipped += 1;
+                continue;
+            }
+
+            match analyzer.try_analyze(&record.text, None, &[]).await {
+                Ok(emotion) if emotion == record.emotion => {}
+                Ok(emotion) => {
+                    let updated = state
+                        .history
+                        .update_emotion(
+                            id,
+                            &record.emotion,
+                            &emotion,
+                            &analyzer.model_name(),
+                            state.config().timezone,
+                        )
+                        .await;
+                    match updated {
+                        Ok(()) => changed += 1,
+                        Err(e) => {
+                            warn!("更新记录 {} 情绪失败: {}", id, e);
+                            failed += 1;
+                        }
+                    }
+                }
+                Err(EmotionError::CircuitOpen) => {
+                    warn!("Ollama 熔断，中止重新分析（已处理 {} 条）", processed - 1);
+                    break 'pages;
+                }
+                Err(e) => {
+                    warn!("重新分析记录 {} 失败: {}", id, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    info!(
+        "重新分析完成: 处理 {} 条，更新 {} 条，跳过 {} 条，失败 {} 条",
+        processed, changed, skipped, failed
+    );
+}
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// 连接内最近的一句识别结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentTranscript {
+    /// 语句序号，开启 `SEGMENT_EMOTION` 或 `UTTERANCE_COALESCE_MS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utterance: Option<u64>,
+    pub text: String,
+    pub emotion: String,
+    /// 结果送达连接的时间（`TZ_NAME` 时区的 RFC 3339）
+    pub created_at: String,
+}
+
+/// 连接内最近若干句识别结果的环形缓冲，供客户端查询而不必读数据库
+#[derive(Debug)]
+pub struct RecentTranscripts {
+    capacity: usize,
+    entries: VecDeque<RecentTranscript>,
+}
+
+impl RecentTranscripts {
+    /// `capacity` 为 0 时不保留
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// 追加一句，超出容量时丢弃最早的
+    pub fn push(&mut self, entry: RecentTranscript) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// 移除被合并重新识别的结果取代的语句
+    pub fn remove(&mut self, utterance: u64) {
+        self.entries
+            .retain(|entry| entry.utterance != Some(utterance));
+    }
+
+    /// 最近 `n` 句，按时间顺序排列；`n` 为空时返回全部
+    pub fn last(&self, n: Option<usize>) -> Vec<RecentTranscript> {
+        let n = n.unwrap_or(self.entries.len()).min(self.entries.len());
+        self.entries
+            .iter()
+            .skip(self.entries.len() - n)
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,227 @@
+use serde::Serialize;
+use std::fmt::Debug;
+use std::io;
+use std::panic;
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use crate::config::{self, Config};
+use crate::state::AppState;
+
+/// 避免并发的重新加载互相覆盖
+static RELOAD_LOCK: Mutex<()> = Mutex::new(());
+
+/// 把配置字段分为可热更新和需重启两类
+///
+/// 可热更新的字段在每个连接、每句语音或每次请求时读取；需重启的字段只在启动时使用，
+/// 如监听地址、已加载的模型和输出端。解构保证新增字段时必须在这里归类，否则无法编译。
+macro_rules! reload_fields {
+    (runtime: [$($runtime:ident),* $(,)?], restart: [$($restart:ident),* $(,)?] $(,)?) => {
+        /// 返回 `fresh` 中相对 `current` 有变化的可热更新字段和需重启字段
+        fn diff(current: &Config, fresh: &Config) -> (Vec<&'static str>, Vec<&'static str>) {
+            let Config { $($runtime: _,)* $($restart: _,)* } = fresh;
+            let runtime = [$((stringify!($runtime), differs(&current.$runtime, &fresh.$runtime))),*];
+            let restart = [$((stringify!($restart), differs(&current.$restart, &fresh.$restart))),*];
+            (changed_names(&runtime), changed_names(&restart))
+        }
+
+        /// 用 `fresh` 中可热更新的字段覆盖 `target`
+        fn apply_runtime(target: &mut Config, fresh: &Config) {
+            $(target.$runtime = fresh.$runtime.clone();)*
+        }
+    };
+}
+
+reload_fields! {
+    runtime: [
+        time_greeting,
+        legacy_ping_match,
+        timezone,
+        store_audio,
+        audio_dir,
+        max_transcript_chars,
+        log_transcripts,
+        acoustic_fingerprint,
+        prosody_metrics,
+        vad_metadata,
+        segment_emotion,
+        emotion_actions,
+        emotion_labels,
+        coalesce_interim,
+        strict_handshake,
+        barge_in_cancel,
+        include_model_ids,
+        include_raw_text,
+        control_acks,
+        require_model_ready,
+        emit_low_quality,
+        min_content_chars,
+        combined_analysis,
+        store_low_quality,
+        max_total_audio_bytes,
+        decode_float,
+        opus_detect_channels,
+        limiter_threshold,
+        limiter_ratio,
+        pre_emphasis,
+        pre_emphasis_coeff,
+        startup_mute,
+        vad_cooldown,
+        vad_sustain_threshold,
+        vad_max_unsustained,
+        vad_energy_window,
+        vad_frame,
+        silero_vad_threshold,
+        wake_phrase,
+        wake_window,
+        dedup_window,
+        max_utterance_staleness,
+        utterance_coalesce_window,
+        utterance_coalesce_max,
+        ollama_host,
+        ollama_model,
+        ollama_api,
+        emotion_context_turns,
+        recent_transcripts,
+        emotion_min_words,
+        audio_level_interval,
+        compress_min_bytes,
+        monitor_token,
+        emotion_smoothing_window,
+        reanalyze_interval,
+    ],
+    restart: [
+        listen_addr,
+        listen_backlog,
+        worker_threads,
+        shutdown_grace,
+        tcp_keepalive_time,
+        tcp_keepalive_interval,
+        tcp_keepalive_retries,
+        tcp_user_timeout,
+        connection_rate,
+        connection_burst,
+        whisper_models,
+        silero_vad_model,
+        model_idle_unload,
+        fallback_model,
+        fallback_latency,
+        fallback_queue_depth,
+        fallback_hold,
+        shadow_model,
+        default_language,
+        persist_history,
+        db_path,
+        database_url,
+        normalize_transcript,
+        whisper_temperature,
+        whisper_no_speech_thold,
+        whisper_suppress_blank,
+        whisper_suppress_tokens,
+        whisper_min_audio,
+        token_timings,
+        vacuum_interval,
+        vacuum_idle,
+        result_sinks,
+        mq_url,
+        mq_topic,
+        subtitle_dir,
+        subtitle_format,
+        chunk_window,
+        chunk_overlap,
+        ollama_probe_interval,
+        ollama_retries,
+        ollama_timeout,
+        breaker_threshold,
+        breaker_cooldown,
+        emotion_selection,
+        emotion_single_flight,
+        emotion_max_chars,
+    ],
+}
+
+/// 字段类型不都实现 `PartialEq`，按调试输出比较
+fn differs(current: &dyn Debug, fresh: &dyn Debug) -> bool {
+    format!("{:?}", current) != format!("{:?}", fresh)
+}
+
+fn changed_names(fields: &[(&'static str, bool)]) -> Vec<&'static str> {
+    fields
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// 重新加载的结果
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    /// 已生效的设置
+    pub applied: Vec<&'static str>,
+    /// 有变化但需要重启才能生效的设置
+    pub restart_required: Vec<&'static str>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error("读取 CONFIG_FILE 失败: {0}")]
+    File(#[from] io::Error),
+    #[error("配置无效，保留当前配置")]
+    Invalid,
+}
+
+/// 重新读取 `CONFIG_FILE` 和环境变量，应用可热更新的设置
+///
+/// 新配置整体替换旧配置：逐句读取的设置对下一句生效，VAD 等按连接读取的设置对新连接生效。
+pub fn reload(state: &AppState) -> Result<ReloadReport, ReloadError> {
+    let _guard = RELOAD_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    config::load_config_file()?;
+    // 时区、动作映射等无效时 `from_env` 直接 panic，启动时这样做是对的，重新加载时保留旧配置
+    let fresh = panic::catch_unwind(Config::from_env).map_err(|_| ReloadError::Invalid)?;
+
+    let current = state.config();
+    let (applied, restart_required) = diff(&current, &fresh);
+    if applied.is_empty() {
+        info!("配置已重新加载，没有可热更新的变化");
+    } else {
+        let mut next = (*current).clone();
+        apply_runtime(&mut next, &fresh);
+        state.emotion_analyzer.set_endpoint(&next);
+        state.replace_config(next);
+        info!("配置已重新加载，已更新: {}", applied.join(", "));
+    }
+    if !restart_required.is_empty() {
+        warn!("以下设置需要重启才能生效: {}", restart_required.join(", "));
+    }
+
+    Ok(ReloadReport {
+        applied,
+        restart_required,
+    })
+}
+
+/// 收到 SIGHUP 时重新加载配置
+#[cfg(unix)]
+pub fn spawn_sighup_listener(state: AppState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!("无法监听 SIGHUP: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("收到 SIGHUP，重新加载配置");
+            if let Err(e) = reload(&state) {
+                error!("重新加载配置失败: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_sighup_listener(_state: AppState) {}
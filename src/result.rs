@@ -0,0 +1,88 @@
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::acoustic::AcousticFingerprint;
+use crate::audio::VadMetadata;
+use crate::prosody::Prosody;
+use crate::speech::{SegmentTiming, TokenTiming};
+
+/// 一条完整的识别结果，在输出端、历史接口和响应构建之间共享
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeechResult {
+    /// 产生该结果的连接或 SSE 会话
+    pub session_id: String,
+    pub text: String,
+    /// 规范化前的 Whisper 原始文本，开启 `INCLUDE_RAW_TEXT` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_raw: Option<String>,
+    pub emotion: String,
+    /// Whisper token 概率均值，旧记录或识别失败时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    pub timings: Timings,
+    /// 粗略声学特征，开启 `ACOUSTIC_FINGERPRINT` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<AcousticFingerprint>,
+    /// 端点检测信息，开启 `VAD_METADATA` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vad: Option<VadMetadata>,
+    /// 语速和停顿统计，开启 `PROSODY_METRICS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prosody: Option<Prosody>,
+    /// 逐 token 时间戳，开启 `TOKEN_TIMINGS` 时存在；不写入数据库
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<TokenTiming>>,
+    /// 识别使用的 Whisper 模型文件名，开启 `INCLUDE_MODEL_IDS` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asr_model: Option<String>,
+    /// 情绪分析使用的 Ollama 模型，开启 `INCLUDE_MODEL_IDS` 且实际调用了分析时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emotion_model: Option<String>,
+    /// Whisper 自动检测出的语言代码，识别模型语言为 `auto` 时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    /// 结果产生时间（`TZ_NAME` 时区的 RFC 3339）
+    pub created_at: String,
+    /// 未通过有效性检查的原因，仅在输出低质量结果时存在
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejection: Option<String>,
+    /// 语音片段文件路径，开启 `STORE_AUDIO` 时存在；只在服务端使用，不对外输出
+    #[serde(skip)]
+    pub audio_path: Option<String>,
+    /// 写入输出端失败，结果未被保存
+    #[serde(skip)]
+    pub persist_failed: bool,
+    /// 语句开头相对会话音频开头的毫秒数；只在服务端使用，供字幕输出端计算时间轴
+    #[serde(skip)]
+    pub offset_ms: u64,
+    /// 识别分段及其相对语句开头的时间，仅情绪模式下为空；只在服务端使用
+    #[serde(skip)]
+    pub segments: Vec<SegmentTiming>,
+}
+
+impl SpeechResult {
+    /// 结果质量标记，低质量结果为 `low`
+    pub fn quality(&self) -> Option<&'static str> {
+        self.rejection.as_ref().map(|_| "low")
+    }
+}
+
+/// 各处理阶段耗时
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Timings {
+    pub recognition_ms: u64,
+    pub emotion_ms: u64,
+}
+
+/// `timezone` 时区当前时间的 RFC 3339 字符串
+pub fn now_rfc3339(timezone: Tz) -> String {
+    timezone
+        .from_utc_datetime(&Utc::now().naive_utc())
+        .to_rfc3339()
+}
+
+/// 生成新的会话 ID
+pub fn new_session_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
@@ -0,0 +1,132 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::metrics::METRICS;
+use crate::result;
+use crate::speech::{Recognizer, RecognizerOptions, SpeechRecognizer};
+use crate::state::AppState;
+
+/// 正在进行影子识别，同一时间只运行一个，期间到达的语句不做对比
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// 影子模型对同一句语音的识别结果与主模型的对比
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowComparison {
+    pub session_id: String,
+    pub primary_model: String,
+    pub shadow_model: String,
+    pub primary_text: String,
+    pub shadow_text: String,
+    /// 两者文本的字符编辑距离，0 表示一致
+    pub edit_distance: usize,
+    pub primary_ms: u64,
+    pub shadow_ms: u64,
+    /// 对比完成时间（`TZ_NAME` 时区的 RFC 3339）
+    pub created_at: String,
+}
+
+/// 按配置加载影子模型，未配置或加载失败时返回 None
+///
+/// 影子模型只用于默认语言，结果不发给客户端。
+pub fn load(config: &Config, options: &RecognizerOptions) -> Option<Arc<SpeechRecognizer>> {
+    let model_path = config.shadow_model.as_deref()?;
+    info!("加载影子 Whisper 模型: {}", model_path);
+    match SpeechRecognizer::try_load(model_path, &config.default_language, options.clone()) {
+        Ok(model) => Some(Arc::new(model)),
+        Err(e) => {
+            warn!("影子 Whisper 模型加载失败，不启用影子识别: {}", e);
+            None
+        }
+    }
+}
+
+/// 一句主模型识别完成的语音，交给影子模型重新识别
+pub struct ShadowJob {
+    pub session_id: String,
+    pub primary_model: String,
+    pub primary_text: String,
+    pub primary_ms: u64,
+    pub audio: Vec<f32>,
+    pub initial_prompt: Option<String>,
+}
+
+/// 在后台用影子模型识别并记录对比结果，不影响主流程的延迟和结果
+///
+/// 上一句的影子识别尚未完成时丢弃本句，影子识别跟不上时不会在阻塞线程池中堆积。
+pub fn spawn(state: &AppState, shadow: Arc<SpeechRecognizer>, job: ShadowJob) {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        METRICS.shadow_skipped_total.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let config = state.config();
+    let history = state.history.clone();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let _running = RunningGuard;
+        let started = Instant::now();
+        let transcript = shadow.recognize(&job.audio, job.initial_prompt.as_deref());
+        let shadow_text = transcript.text.trim().to_string();
+        let comparison = ShadowComparison {
+            edit_distance: edit_distance(&job.primary_text, &shadow_text),
+            session_id: job.session_id,
+            primary_model: job.primary_model,
+            shadow_model: shadow.model_name().to_string(),
+            primary_text: job.primary_text,
+            shadow_text,
+            primary_ms: job.primary_ms,
+            shadow_ms: started.elapsed().as_millis() as u64,
+            created_at: result::now_rfc3339(config.timezone),
+        };
+
+        METRICS
+            .shadow_recognitions_total
+            .fetch_add(1, Ordering::Relaxed);
+        if comparison.edit_distance > 0 {
+            METRICS
+                .shadow_mismatches_total
+                .fetch_add(1, Ordering::Relaxed);
+            info!(
+                "影子模型结果不一致 (距离 {}): 主 [{}] ({}ms) | 影子 [{}] ({}ms)",
+                comparison.edit_distance,
+                config.log_transcripts.display(&comparison.primary_text),
+                comparison.primary_ms,
+                config.log_transcripts.display(&comparison.shadow_text),
+                comparison.shadow_ms
+            );
+        }
+
+        if config.persist_history {
+            if let Err(e) = runtime.block_on(history.insert_shadow_comparison(&comparison)) {
+                warn!("保存影子模型对比失败: {}", e);
+            }
+        }
+    });
+}
+
+/// 影子识别结束或 panic 时清除运行标志
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+/// 按字符计算的编辑距离
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
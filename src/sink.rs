@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::history::HistoryStore;
+use crate::metrics::METRICS;
+use crate::result::SpeechResult;
+use crate::subtitle::{self, SubtitleFormat};
+
+/// 输出端写入错误
+pub type SinkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 识别结果输出端
+#[async_trait]
+pub trait ResultSink: Send + Sync {
+    /// 输出端名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 写入一条结果，失败时由 `dispatch` 统一记录日志和指标
+    async fn write(&self, result: &SpeechResult) -> Result<(), SinkError>;
+}
+
+/// 写入历史记录存储（默认为 SQLite）
+pub struct HistorySink {
+    store: Arc<dyn HistoryStore>,
+}
+
+#[async_trait]
+impl ResultSink for HistorySink {
+    fn name(&self) -> &'static str {
+        self.store.name()
+    }
+
+    async fn write(&self, result: &SpeechResult) -> Result<(), SinkError> {
+        self.store.insert_speech_result(result).await
+    }
+}
+
+/// 以 JSON 行输出到标准输出，便于接入日志采集
+pub struct StdoutSink;
+
+#[async_trait]
+impl ResultSink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    async fn write(&self, result: &SpeechResult) -> Result<(), SinkError> {
+        println!("{}", serde_json::to_string(result)?);
+        Ok(())
+    }
+}
+
+/// 以 JSON 发布到 Redis pub/sub 频道，首次写入时建立连接，断线后自动重连
+pub struct RedisSink {
+    client: redis::Client,
+    topic: String,
+    connection: OnceCell<ConnectionManager>,
+}
+
+impl RedisSink {
+    pub fn new(url: &str, topic: &str) -> redis::RedisResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            topic: topic.to_string(),
+            connection: OnceCell::new(),
+        })
+    }
+
+    async fn publish(&self, payload: String) -> redis::RedisResult<()> {
+        let mut connection = self
+            .connection
+            .get_or_try_init(|| self.client.get_connection_manager())
+            .await?
+            .clone();
+        connection.publish(&self.topic, payload).await
+    }
+}
+
+#[async_trait]
+impl ResultSink for RedisSink {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn write(&self, result: &SpeechResult) -> Result<(), SinkError> {
+        self.publish(serde_json::to_string(result)?).await?;
+        Ok(())
+    }
+}
+
+/// 按会话把识别分段追加到字幕文件 `<dir>/<session_id>.srt|vtt`
+///
+/// 时间轴以会话音频开头为零点。没有分段的结果（仅情绪模式、低质量结果）不写入。
+pub struct SubtitleSink {
+    dir: PathBuf,
+    format: SubtitleFormat,
+}
+
+impl SubtitleSink {
+    pub fn new(dir: &str, format: SubtitleFormat) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            format,
+        }
+    }
+}
+
+#[async_trait]
+impl ResultSink for SubtitleSink {
+    fn name(&self) -> &'static str {
+        "subtitle"
+    }
+
+    async fn write(&self, result: &SpeechResult) -> Result<(), SinkError> {
+        if result.rejection.is_some() || result.segments.is_empty() {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self
+            .dir
+            .join(format!("{}.{}", result.session_id, self.format.extension()));
+        // 续写时 SRT 序号接着已有条目编号
+        let existing = match std::fs::read_to_string(&path) {
+            Ok(content) => Some(subtitle::count_cues(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut cues = String::new();
+        if existing.is_none() {
+            cues.push_str(self.format.header());
+        }
+        cues.push_str(&subtitle::format_cues(
+            self.format,
+            &result.segments,
+            result.offset_ms,
+            existing.unwrap_or(0) + 1,
+        ));
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.write_all(cues.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// 根据配置构建输出端列表
+///
+/// `sqlite` 输出端写入 `history` 存储，名称沿用以兼容已有配置。
+pub fn build_sinks(config: &Config, history: &Arc<dyn HistoryStore>) -> Vec<Box<dyn ResultSink>> {
+    let mut sinks: Vec<Box<dyn ResultSink>> = Vec::new();
+
+    for name in &config.result_sinks {
+        match name.as_str() {
+            "sqlite" if !config.persist_history => {
+                info!("PERSIST_HISTORY=false，跳过 sqlite 输出端");
+            }
+            "sqlite" => sinks.push(Box::new(HistorySink {
+                store: history.clone(),
+            })),
+            "stdout" => sinks.push(Box::new(StdoutSink)),
+            "subtitle" => sinks.push(Box::new(SubtitleSink::new(
+                &config.subtitle_dir,
+                config.subtitle_format,
+            ))),
+            "redis" => match config.mq_url.as_deref() {
+                Some(url) => match RedisSink::new(url, &config.mq_topic) {
+                    Ok(sink) => sinks.push(Box::new(sink)),
+                    Err(e) => error!("MQ_URL 无效，跳过 redis 输出端: {}", e),
+                },
+                None => warn!("未设置 MQ_URL，跳过 redis 输出端"),
+            },
+            other => warn!("未知的结果输出端: {}", other),
+        }
+    }
+
+    info!(
+        "结果输出端: {:?}",
+        sinks.iter().map(|sink| sink.name()).collect::<Vec<_>>()
+    );
+    sinks
+}
+
+/// 将结果并发写入全部输出端，返回是否全部写入成功
+pub async fn dispatch(sinks: &[Box<dyn ResultSink>], result: &SpeechResult) -> bool {
+    let outcomes = futures::future::join_all(sinks.iter().map(|sink| sink.write(result))).await;
+
+    let mut all_written = true;
+    for (sink, outcome) in sinks.iter().zip(outcomes) {
+        if let Err(e) = outcome {
+            all_written = false;
+            METRICS
+                .result_sink_failures_total
+                .fetch_add(1, Ordering::Relaxed);
+            error!("识别结果写入输出端 {} 失败，结果已丢失: {}", sink.name(), e);
+        }
+    }
+    all_written
+}
@@ -1,44 +1,384 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void, CStr};
 use std::path::Path;
-use tracing::{error, info};
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperSysContext,
+    WhisperSysState, WhisperTokenId,
+};
+
+use crate::config::Config;
+use crate::fallback::ModelFallback;
+use crate::logging;
+use crate::shadow;
+use crate::text;
+
+/// 按文本查找单个 token 时分词的最大长度
+const MAX_SUPPRESS_TOKENIZE: usize = 8;
+/// 客户端自定义提示词的最大字符数
+const MAX_PROMPT_CHARS: usize = 200;
+/// Whisper 输入音频的采样率
+const SAMPLE_RATE: usize = 16000;
+/// 检查模型是否空闲的最长间隔
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// 模型语言设为该值时由 Whisper 自动检测语言
+pub const AUTO_LANGUAGE: &str = "auto";
 
 pub struct SpeechRecognizer {
-    context: WhisperContext,
+    model_path: String,
+    /// 已加载的模型，空闲卸载后为 None，下次使用时重新加载；锁只在取出和替换时短暂持有
+    context: Mutex<Option<Arc<WhisperContext>>>,
+    /// 与 `context` 同步，供异步代码无锁查询
+    loaded: AtomicBool,
+    /// 让并发的重新加载排队，只在阻塞线程上持有
+    loading: Mutex<()>,
+    /// `last_used_ms` 的起点
+    created: Instant,
+    /// 最近一次被会话选中或用于识别的时间，自 `created` 起的毫秒数
+    last_used_ms: AtomicU64,
+    language: String,
+    options: RecognizerOptions,
+    /// 由 `options.suppress_tokens` 解析出的词表 ID，同一模型文件重新加载后不变
+    suppressed_tokens: Vec<WhisperTokenId>,
+    /// 最近一次重新加载失败，成功加载后清除
+    load_failed: AtomicBool,
+}
+
+/// 识别器的可配置项
+#[derive(Debug, Clone)]
+pub struct RecognizerOptions {
+    /// 对识别结果做繁简与全半角规范化
+    pub normalize: bool,
+    /// 解码初始温度，0 为贪心解码
+    pub temperature: f32,
+    /// 无语音概率高于该阈值的分段视为静音
+    pub no_speech_thold: f32,
+    /// 抑制分段开头的空白输出
+    pub suppress_blank: bool,
+    /// 输出逐 token 时间戳
+    pub token_timestamps: bool,
+    /// 解码时禁止输出的 token（词表 ID 或文本）
+    pub suppress_tokens: Vec<String>,
+    /// 音频不足该样本数时在末尾补静音，0 表示不补
+    pub min_audio_samples: usize,
+}
+
+impl RecognizerOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            normalize: config.normalize_transcript,
+            temperature: config.whisper_temperature,
+            no_speech_thold: config.whisper_no_speech_thold,
+            suppress_blank: config.whisper_suppress_blank,
+            token_timestamps: config.token_timings,
+            suppress_tokens: config.whisper_suppress_tokens.clone(),
+            min_audio_samples: SAMPLE_RATE * config.whisper_min_audio.as_millis() as usize / 1000,
+        }
+    }
+}
+
+/// 按语言选用的语音识别器
+///
+/// 连接和识别流程经由该 trait 使用识别器，不依赖具体的 Whisper 模型。
+pub trait Recognizer: Send + Sync {
+    /// 模型名称，用于标识产生结果的模型
+    fn model_name(&self) -> &str;
+
+    /// 识别器对应的语言
+    fn language(&self) -> &str;
+
+    /// 识别并在推理过程中回报进度和新分段
+    fn recognize_with_callbacks(
+        &self,
+        audio_data: &[f32],
+        initial_prompt: Option<&str>,
+        callbacks: RecognizeCallbacks<'_>,
+    ) -> Transcript;
+
+    /// 对音频数据进行语音识别，`initial_prompt` 覆盖默认提示词
+    fn recognize(&self, audio_data: &[f32], initial_prompt: Option<&str>) -> Transcript {
+        self.recognize_with_callbacks(audio_data, initial_prompt, RecognizeCallbacks::default())
+    }
+
+    /// 可用于识别
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// 被会话选中时调用，可在后台提前准备模型
+    fn prepare(self: Arc<Self>) {}
+
+    /// 空闲超过 `idle` 时释放模型占用的内存
+    fn unload_if_idle(&self, _idle: Duration) {}
+}
+
+/// 按语言索引的语音识别器集合，连接根据协商的语言选择其一
+pub struct RecognizerRegistry {
+    recognizers: HashMap<String, Arc<dyn Recognizer>>,
+    default_language: String,
+    /// 负载过高时使用的较小模型
+    fallback: Option<ModelFallback>,
+    /// 在后台对比识别结果的影子模型
+    shadow: Option<Arc<SpeechRecognizer>>,
+}
+
+impl RecognizerRegistry {
+    /// 加载配置中的全部模型
+    pub async fn load(config: &Config) -> Self {
+        let options = RecognizerOptions::from_config(config);
+        let default_language = config.default_language.as_str();
+        let mut recognizers: HashMap<String, Arc<dyn Recognizer>> = HashMap::new();
+
+        for (language, model_path) in &config.whisper_models {
+            info!("加载 Whisper 模型 [{}]: {}", language, model_path);
+            let recognizer = SpeechRecognizer::new(model_path, language, options.clone()).await;
+            recognizers.insert(language.clone(), Arc::new(recognizer));
+        }
+
+        if !recognizers.contains_key(default_language) {
+            panic!(
+                "❌ 错误: 默认语言 '{}' 没有配置对应的 Whisper 模型",
+                default_language
+            );
+        }
+
+        Self {
+            recognizers,
+            default_language: default_language.to_string(),
+            fallback: ModelFallback::from_config(config, &options),
+            shadow: shadow::load(config, &options),
+        }
+    }
+
+    /// 只包含一个识别器的注册表，不启用降级和影子对比
+    pub fn single(recognizer: Arc<dyn Recognizer>) -> Self {
+        let default_language = recognizer.language().to_string();
+        Self {
+            recognizers: HashMap::from([(default_language.clone(), recognizer)]),
+            default_language,
+            fallback: None,
+            shadow: None,
+        }
+    }
+
+    /// 负载降级用的备用模型，未配置时为 None
+    pub fn fallback(&self) -> Option<&ModelFallback> {
+        self.fallback.as_ref()
+    }
+
+    /// 影子对比用的模型，未配置时为 None
+    pub fn shadow(&self) -> Option<&Arc<SpeechRecognizer>> {
+        self.shadow.as_ref()
+    }
+
+    /// 根据客户端语言（如 `zh-CN`、`en_US`）选择识别器，未匹配时使用默认语言
+    ///
+    /// 模型已被空闲卸载时在后台开始重新加载，以便第一句语音到来前就绪。
+    pub fn select(&self, locale: Option<&str>) -> Arc<dyn Recognizer> {
+        let recognizer = locale
+            .map(primary_language)
+            .and_then(|language| self.recognizers.get(&language))
+            .unwrap_or_else(|| &self.recognizers[&self.default_language])
+            .clone();
+        recognizer.clone().prepare();
+        recognizer
+    }
+
+    /// 启动空闲卸载任务：没有会话使用且空闲超过 `idle` 的模型被卸载以释放内存
+    pub fn spawn_idle_unload(self: &Arc<Self>, idle: Duration) {
+        let registry = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(idle.min(IDLE_CHECK_INTERVAL));
+            loop {
+                ticker.tick().await;
+                // 注册表自身持有一个引用，更多引用说明仍有会话在使用；
+                // 正在进行的识别持有自己的引用，不受影响
+                for recognizer in registry.recognizers.values() {
+                    if Arc::strong_count(recognizer) == 1 {
+                        recognizer.unload_if_idle(idle);
+                    }
+                    // 重新加载失败后不等下一个连接，在后台重试
+                    if !recognizer.is_ready() {
+                        recognizer.clone().prepare();
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// 清理客户端提供的提示词：去除控制字符并截断长度，清理后为空则忽略
+pub fn sanitize_prompt(raw: &str) -> Option<String> {
+    let prompt: String = raw
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_PROMPT_CHARS)
+        .collect();
+    let prompt = prompt.trim();
+
+    (!prompt.is_empty()).then(|| prompt.to_string())
+}
+
+/// 提取语言标签的主语言部分
+fn primary_language(locale: &str) -> String {
+    locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+/// 识别过程中的回调，均在推理线程上同步调用
+#[derive(Default)]
+pub struct RecognizeCallbacks<'a> {
+    /// 推理进度百分比（0–100）
+    pub on_progress: Option<&'a mut dyn FnMut(i32)>,
+    /// 每个新识别出的分段
+    pub on_segment: Option<SegmentCallback<'a>>,
+    /// 置位后尽快中止推理，返回空结果
+    pub abort: Option<&'a AtomicBool>,
+}
+
+/// 新分段回调，参数为分段序号和原始文本
+pub type SegmentCallback<'a> = &'a mut dyn FnMut(usize, &str);
+
+/// 一次识别的输出
+#[derive(Debug, Default)]
+pub struct Transcript {
+    pub text: String,
+    /// Whisper 原始输出，未做繁简与全半角规范化
+    pub raw_text: String,
+    /// 全部 token 概率的均值，没有 token 时为 None
+    pub confidence: Option<f32>,
+    /// 逐 token 时间戳，未开启 `token_timestamps` 时为空
+    pub tokens: Vec<TokenTiming>,
+    /// 各分段的文本和起止时间
+    pub segments: Vec<SegmentTiming>,
+    /// Whisper 检测出的语言代码，仅模型语言为 `auto` 时存在
+    pub detected_language: Option<String>,
+}
+
+/// 一个 Whisper 分段在语音片段中的起止时间，精度与 `TokenTiming` 相同
+#[derive(Debug, Clone)]
+pub struct SegmentTiming {
+    pub text: String,
+    /// 相对语音片段开头的毫秒数
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// 一个 token 在语音片段中的起止时间
+///
+/// Whisper 时间戳以 10ms 为单位，且由 token 级时间戳启发式估计，
+/// 实际误差通常在几十到上百毫秒。中文大多一个 token 一个字，
+/// 被拆成多个字节 token 的字会合并为一项。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTiming {
+    pub text: String,
+    /// 相对语音片段开头的毫秒数
+    pub start_ms: u64,
+    pub end_ms: u64,
 }
 
 impl SpeechRecognizer {
     /// 创建新的语音识别器
-    pub async fn new(model_path: &str) -> Self {
+    pub async fn new(model_path: &str, language: &str, options: RecognizerOptions) -> Self {
         Self::validate_model_path(model_path);
 
         info!("正在加载 Whisper 模型...");
-        let context =
-            WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
-                .expect("模型加载失败");
+        let recognizer = Self::try_load(model_path, language, options).expect("模型加载失败");
 
         info!("✅ Whisper 模型加载完毕");
 
-        Self { context }
+        recognizer
     }
 
-    /// 对音频数据进行语音识别
-    pub fn recognize(&self, audio_data: &[f32]) -> String {
-        let mut state = match self.context.create_state() {
-            Ok(state) => state,
-            Err(e) => {
-                error!("无法创建 Whisper State: {}", e);
-                return String::new();
-            }
-        };
+    /// 加载模型，失败时返回错误而不是 panic
+    pub fn try_load(
+        model_path: &str,
+        language: &str,
+        options: RecognizerOptions,
+    ) -> Result<Self, whisper_rs::WhisperError> {
+        let context =
+            WhisperContext::new_with_params(model_path, WhisperContextParameters::default())?;
+        let suppressed_tokens = resolve_suppress_tokens(&context, &options.suppress_tokens);
+        if !suppressed_tokens.is_empty() {
+            info!(
+                "Whisper 模型 [{}] 抑制 token: {:?}",
+                language, suppressed_tokens
+            );
+        }
 
-        let params = self.create_inference_params();
+        Ok(Self {
+            model_path: model_path.to_string(),
+            context: Mutex::new(Some(Arc::new(context))),
+            loaded: AtomicBool::new(true),
+            loading: Mutex::new(()),
+            created: Instant::now(),
+            last_used_ms: AtomicU64::new(0),
+            language: language.to_string(),
+            options,
+            suppressed_tokens,
+            load_failed: AtomicBool::new(false),
+        })
+    }
 
-        if let Err(e) = state.full(params, audio_data) {
-            error!("Whisper推理失败: {}", e);
-            return String::new();
+    /// 模型当前是否已加载
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Relaxed)
+    }
+
+    /// 已加载的模型，已卸载时为 `None`
+    fn loaded_context(&self) -> Option<Arc<WhisperContext>> {
+        self.context
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 取得已加载的模型，已卸载时同步重新加载
+    ///
+    /// 重新加载可能耗时数秒，调用方须在阻塞线程上识别（见 `pipeline::recognize_blocking`），不可直接在异步任务中调用。
+    ///
+    /// 加载在 `context` 锁外进行，期间其他线程仍可查询；并发的重新加载在 `loading` 上排队，只加载一次。
+    fn context(&self) -> Result<Arc<WhisperContext>, whisper_rs::WhisperError> {
+        self.touch();
+        if let Some(context) = self.loaded_context() {
+            return Ok(context);
+        }
+        let _loading = self.loading.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(context) = self.loaded_context() {
+            return Ok(context);
         }
 
-        self.extract_text_from_segments(&state)
+        info!(
+            "重新加载 Whisper 模型 [{}]: {}",
+            self.language, self.model_path
+        );
+        let started = Instant::now();
+        let loaded =
+            WhisperContext::new_with_params(&self.model_path, WhisperContextParameters::default());
+        self.load_failed.store(loaded.is_err(), Ordering::Relaxed);
+        let loaded = Arc::new(loaded?);
+        info!(
+            "Whisper 模型 [{}] 加载完成，耗时 {:?}",
+            self.language,
+            started.elapsed()
+        );
+        *self.context.lock().unwrap_or_else(PoisonError::into_inner) = Some(loaded.clone());
+        self.loaded.store(true, Ordering::Relaxed);
+        Ok(loaded)
+    }
+
+    /// 记录一次使用
+    fn touch(&self) {
+        self.last_used_ms
+            .store(self.created.elapsed().as_millis() as u64, Ordering::Relaxed);
     }
 
     /// 验证模型文件是否存在
@@ -52,10 +392,18 @@ impl SpeechRecognizer {
     }
 
     /// 创建推理参数
-    fn create_inference_params(&self) -> FullParams {
+    fn create_inference_params(&self, initial_prompt: Option<&str>) -> FullParams<'_, '_> {
         let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-        params.set_language(Some("zh"));
-        params.set_initial_prompt("简体中文");
+        params.set_language(Some(&self.language));
+        match initial_prompt {
+            Some(prompt) => params.set_initial_prompt(prompt),
+            None if self.language == "zh" => params.set_initial_prompt("简体中文"),
+            None => {}
+        }
+        params.set_temperature(self.options.temperature);
+        params.set_no_speech_thold(self.options.no_speech_thold);
+        params.set_suppress_blank(self.options.suppress_blank);
+        params.set_token_timestamps(self.options.token_timestamps);
         params.set_n_threads(4);
         params.set_print_special(false);
         params.set_print_progress(false);
@@ -75,4 +423,341 @@ impl SpeechRecognizer {
 
         result
     }
+
+    /// 收集各分段的文本和起止时间
+    fn segment_timings(&self, state: &whisper_rs::WhisperState) -> Vec<SegmentTiming> {
+        (0..state.full_n_segments())
+            .filter_map(|i| state.get_segment(i))
+            .map(|segment| {
+                let text = segment.to_string();
+                SegmentTiming {
+                    text: if self.options.normalize {
+                        text::normalize_transcript(&text)
+                    } else {
+                        text
+                    },
+                    start_ms: centiseconds_to_ms(segment.start_timestamp()),
+                    end_ms: centiseconds_to_ms(segment.end_timestamp()),
+                }
+            })
+            .collect()
+    }
+
+    /// 收集文本 token 的时间戳，跳过时间戳等特殊 token，并把不完整的 UTF-8 字节合并到下一个 token
+    fn token_timings(
+        &self,
+        context: &WhisperContext,
+        state: &whisper_rs::WhisperState,
+    ) -> Vec<TokenTiming> {
+        let eot = context.token_eot();
+        let mut timings = Vec::new();
+        let mut pending: Vec<u8> = Vec::new();
+        let mut pending_start = 0;
+
+        for i in 0..state.full_n_segments() {
+            let Some(segment) = state.get_segment(i) else {
+                continue;
+            };
+            for t in 0..segment.n_tokens() {
+                let Some(token) = segment.get_token(t) else {
+                    continue;
+                };
+                if token.token_id() >= eot {
+                    continue;
+                }
+                let Ok(bytes) = token.to_bytes() else {
+                    continue;
+                };
+                let data = token.token_data();
+                if pending.is_empty() {
+                    pending_start = data.t0;
+                }
+                pending.extend_from_slice(bytes);
+
+                let Ok(text) = std::str::from_utf8(&pending) else {
+                    continue;
+                };
+                let text = if self.options.normalize {
+                    text::normalize_transcript(text)
+                } else {
+                    text.to_string()
+                };
+                if !text.trim().is_empty() {
+                    timings.push(TokenTiming {
+                        text,
+                        start_ms: centiseconds_to_ms(pending_start),
+                        end_ms: centiseconds_to_ms(data.t1),
+                    });
+                }
+                pending.clear();
+            }
+        }
+
+        timings
+    }
+}
+
+impl Recognizer for SpeechRecognizer {
+    /// 模型文件名，如 `ggml-base.bin`，用于标识产生结果的模型
+    fn model_name(&self) -> &str {
+        Path::new(&self.model_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.model_path)
+    }
+
+    /// 识别器对应的语言
+    fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// 识别并在推理过程中回报进度和新分段
+    fn recognize_with_callbacks(
+        &self,
+        audio_data: &[f32],
+        initial_prompt: Option<&str>,
+        callbacks: RecognizeCallbacks<'_>,
+    ) -> Transcript {
+        let RecognizeCallbacks {
+            mut on_progress,
+            mut on_segment,
+            abort,
+        } = callbacks;
+        // 静音补在末尾，时间戳仍相对原音频开头
+        let padded;
+        let audio_data = if audio_data.len() < self.options.min_audio_samples {
+            padded = [
+                audio_data,
+                &vec![0.0; self.options.min_audio_samples - audio_data.len()],
+            ]
+            .concat();
+            &padded
+        } else {
+            audio_data
+        };
+        let context = match self.context() {
+            Ok(context) => context,
+            Err(e) => {
+                error!("无法加载 Whisper 模型: {}", e);
+                return Transcript::default();
+            }
+        };
+        let mut state = match context.create_state() {
+            Ok(state) => state,
+            Err(e) => {
+                error!("无法创建 Whisper State: {}", e);
+                return Transcript::default();
+            }
+        };
+
+        let mut params = self.create_inference_params(initial_prompt);
+        if let Some(callback) = on_progress.as_mut() {
+            // whisper-rs 的安全版本会泄漏闭包，这里改为传入栈上闭包的指针。
+            // SAFETY: `state.full` 同步执行，回调只会在其返回前被调用，期间 `callback` 一直有效
+            unsafe {
+                params.set_progress_callback(Some(progress_trampoline));
+                params.set_progress_callback_user_data(
+                    callback as *mut &mut dyn FnMut(i32) as *mut c_void,
+                );
+            }
+        }
+        if let Some(callback) = on_segment.as_mut() {
+            // SAFETY: 同上，`callback` 在 `state.full` 返回前一直有效
+            unsafe {
+                params.set_new_segment_callback(Some(segment_trampoline));
+                params.set_new_segment_callback_user_data(
+                    callback as *mut &mut dyn FnMut(usize, &str) as *mut c_void,
+                );
+            }
+        }
+
+        if let Some(flag) = abort {
+            // SAFETY: `flag` 在 `state.full` 返回前一直有效，回调只读取它
+            unsafe {
+                params.set_abort_callback(Some(abort_trampoline));
+                params.set_abort_callback_user_data(flag as *const AtomicBool as *mut c_void);
+            }
+        }
+        if !self.suppressed_tokens.is_empty() {
+            // SAFETY: `self.suppressed_tokens` 在 `state.full` 返回前一直有效且不会被修改，
+            // 其中的 ID 在加载时已确认小于词表大小
+            unsafe {
+                params.set_filter_logits_callback(Some(suppress_trampoline));
+                params.set_filter_logits_callback_user_data(
+                    &self.suppressed_tokens as *const Vec<WhisperTokenId> as *mut c_void,
+                );
+            }
+        }
+
+        if let Err(e) = state.full(params, audio_data) {
+            if abort.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+                info!(target: logging::SAMPLED, "识别已中止");
+            } else {
+                error!("Whisper推理失败: {}", e);
+            }
+            return Transcript::default();
+        }
+
+        let raw_text = self.extract_text_from_segments(&state);
+        Transcript {
+            text: if self.options.normalize {
+                text::normalize_transcript(&raw_text)
+            } else {
+                raw_text.clone()
+            },
+            raw_text,
+            confidence: mean_token_probability(&state),
+            tokens: if self.options.token_timestamps {
+                self.token_timings(&context, &state)
+            } else {
+                Vec::new()
+            },
+            segments: self.segment_timings(&state),
+            detected_language: (self.language == AUTO_LANGUAGE)
+                .then(|| whisper_rs::get_lang_str(state.full_lang_id_from_state()))
+                .flatten()
+                .map(str::to_string),
+        }
+    }
+
+    /// 模型可用于识别：已加载，或被空闲卸载但上次重新加载没有失败
+    fn is_ready(&self) -> bool {
+        !self.load_failed.load(Ordering::Relaxed)
+    }
+
+    /// 记录使用；模型已被空闲卸载时在后台开始重新加载，以便第一句语音到来前就绪
+    fn prepare(self: Arc<Self>) {
+        self.touch();
+        if !self.is_loaded() {
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = self.context() {
+                    error!("重新加载 Whisper 模型失败: {}", e);
+                }
+            });
+        }
+    }
+
+    /// 空闲超过 `idle` 时卸载模型，下次使用时重新加载
+    fn unload_if_idle(&self, idle: Duration) {
+        let last_used = Duration::from_millis(self.last_used_ms.load(Ordering::Relaxed));
+        let idle_for = self.created.elapsed().saturating_sub(last_used);
+        if idle_for < idle {
+            return;
+        }
+
+        let mut context = self.context.lock().unwrap_or_else(PoisonError::into_inner);
+        self.loaded.store(false, Ordering::Relaxed);
+        if context.take().is_some() {
+            info!(
+                "Whisper 模型 [{}] 已空闲 {:?}，卸载以释放内存",
+                self.language, idle_for
+            );
+        }
+    }
+}
+
+/// Whisper 时间戳（10ms 单位）转为毫秒
+fn centiseconds_to_ms(t: i64) -> u64 {
+    t.max(0) as u64 * 10
+}
+
+/// whisper.cpp 中止回调，`user_data` 指向调用方持有的中止标志
+unsafe extern "C" fn abort_trampoline(user_data: *mut c_void) -> bool {
+    (*(user_data as *const AtomicBool)).load(Ordering::Relaxed)
+}
+
+/// whisper.cpp 进度回调，`user_data` 指向 `recognize_with_callbacks` 栈上的闭包
+unsafe extern "C" fn progress_trampoline(
+    _: *mut WhisperSysContext,
+    _: *mut WhisperSysState,
+    progress: c_int,
+    user_data: *mut c_void,
+) {
+    let callback = &mut *(user_data as *mut &mut dyn FnMut(i32));
+    callback(progress);
+}
+
+/// whisper.cpp 新分段回调，`user_data` 指向 `recognize_with_callbacks` 栈上的闭包
+unsafe extern "C" fn segment_trampoline(
+    _: *mut WhisperSysContext,
+    state: *mut WhisperSysState,
+    n_new: c_int,
+    user_data: *mut c_void,
+) {
+    let callback = &mut *(user_data as *mut &mut dyn FnMut(usize, &str));
+    let n_segments = whisper_rs_sys::whisper_full_n_segments_from_state(state);
+    for i in (n_segments - n_new).max(0)..n_segments {
+        let text = whisper_rs_sys::whisper_full_get_segment_text_from_state(state, i);
+        if !text.is_null() {
+            callback(i as usize, &CStr::from_ptr(text).to_string_lossy());
+        }
+    }
+}
+
+/// whisper.cpp logits 过滤回调，`user_data` 指向识别器的 `suppressed_tokens`
+unsafe extern "C" fn suppress_trampoline(
+    _: *mut WhisperSysContext,
+    _: *mut WhisperSysState,
+    _: *const whisper_rs_sys::whisper_token_data,
+    _: c_int,
+    logits: *mut f32,
+    user_data: *mut c_void,
+) {
+    let suppressed = &*(user_data as *const Vec<WhisperTokenId>);
+    for &id in suppressed {
+        *logits.add(id as usize) = f32::NEG_INFINITY;
+    }
+}
+
+/// 把配置的抑制项解析为模型词表中的 token ID
+///
+/// 纯数字直接视为 ID；文本按模型的 BPE 分词，原文和带词首空格的写法中
+/// 恰好对应一个 token 的会被抑制。会被拆成多个 token 的文本只记录警告而不抑制，
+/// 以免误伤其中常见的片段，这种情况请改写 ID。多语言模型和 `.en` 模型词表不同，
+/// 同一文本在不同模型下的 ID 也可能不同。
+fn resolve_suppress_tokens(context: &WhisperContext, entries: &[String]) -> Vec<WhisperTokenId> {
+    let n_vocab = context.n_vocab();
+    let mut ids = Vec::new();
+    for entry in entries {
+        if let Ok(id) = entry.parse::<WhisperTokenId>() {
+            if (0..n_vocab).contains(&id) {
+                ids.push(id);
+            } else {
+                warn!("抑制 token ID {} 超出词表大小 {}，已忽略", id, n_vocab);
+            }
+            continue;
+        }
+
+        let before = ids.len();
+        for variant in [entry.clone(), format!(" {}", entry)] {
+            if let Ok(tokens) = context.tokenize(&variant, MAX_SUPPRESS_TOKENIZE) {
+                if let [id] = tokens[..] {
+                    ids.push(id);
+                }
+            }
+        }
+        if ids.len() == before {
+            warn!("抑制项 '{}' 在当前模型中不是单个 token，已忽略", entry);
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// 全部分段中 token 概率的均值
+fn mean_token_probability(state: &whisper_rs::WhisperState) -> Option<f32> {
+    let (mut sum, mut count) = (0.0f32, 0usize);
+    for i in 0..state.full_n_segments() {
+        let Some(segment) = state.get_segment(i) else {
+            continue;
+        };
+        for t in 0..segment.n_tokens() {
+            if let Some(token) = segment.get_token(t) {
+                sum += token.token_probability();
+                count += 1;
+            }
+        }
+    }
+    (count > 0).then(|| sum / count as f32)
 }
@@ -0,0 +1,50 @@
+use std::sync::{Arc, PoisonError, RwLock};
+use tokio::sync::{broadcast, watch};
+
+use crate::config::Config;
+use crate::emotion::EmotionAnalyzer;
+use crate::history::HistoryStore;
+use crate::protocol::ServerResponse;
+use crate::ratelimit::ConnectionLimiter;
+use crate::reanalyze::ReanalyzeJob;
+use crate::sink::ResultSink;
+use crate::speech::RecognizerRegistry;
+use crate::stream::StreamSessions;
+use crate::vad::SileroModel;
+
+/// 各连接共享的服务器状态
+#[derive(Clone)]
+pub struct AppState {
+    /// 当前生效的配置，重新加载时整体替换
+    pub config: Arc<RwLock<Arc<Config>>>,
+    pub recognizers: Arc<RecognizerRegistry>,
+    /// 启动时加载的 Silero VAD 模型，各连接共享，未配置或加载失败时为 `None`
+    pub silero_vad: Option<Arc<SileroModel>>,
+    pub emotion_analyzer: Arc<EmotionAnalyzer>,
+    pub sinks: Arc<Vec<Box<dyn ResultSink>>>,
+    /// 历史记录存储，供查询接口使用
+    pub history: Arc<dyn HistoryStore>,
+    pub stream_sessions: Arc<StreamSessions>,
+    pub reanalyze_job: Arc<ReanalyzeJob>,
+    /// 所有设备连接的识别结果广播，供 `/monitor` 订阅
+    pub monitor: broadcast::Sender<ServerResponse>,
+    /// 按来源 IP 限制新连接速率，未配置 `CONNECTION_RATE` 时为 `None`
+    pub connection_limiter: Option<Arc<ConnectionLimiter>>,
+    /// 服务器开始退出时变为 `true`，长连接据此关闭
+    pub shutdown: watch::Receiver<bool>,
+}
+
+impl AppState {
+    /// 当前配置的快照，重新加载不影响已取出的快照
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 替换当前配置
+    pub fn replace_config(&self, config: Config) {
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+    }
+}
@@ -0,0 +1,592 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+use serde::{de::DeserializeOwned, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::result::{self, SpeechResult, Timings};
+use crate::shadow::ShadowComparison;
+use crate::text;
+
+pub const DEFAULT_DB_PATH: &str = "history-emotion.db";
+/// 表示使用内存数据库的路径
+pub const MEMORY_DB_PATH: &str = ":memory:";
+/// 单条记录文本的最大字符数，兜底上限，通常文本已按 `MAX_TRANSCRIPT_CHARS` 截断
+pub const MAX_TEXT_CHARS: usize = 2000;
+/// 数据库被其他连接锁定时等待的时长
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// 连接池中保留的空闲连接上限，超出的连接用完即关闭
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// 已打开的内存数据库数，用于区分各自的共享缓存 URI
+static MEMORY_DB_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// 查询记录时读取的列，顺序与 `history_record_from_row` 一致
+pub const RECORD_COLUMNS: &str = "id, session_id, text, emotion, confidence, recognition_ms, \
+     emotion_ms, created_at, rejection, fingerprint, audio_path, vad, asr_model, emotion_model, \
+     detected_language, text_raw, prosody";
+
+/// `speech_results` 表中的一条记录
+#[derive(Debug, Serialize)]
+pub struct HistoryRecord {
+    pub id: i64,
+    #[serde(flatten)]
+    pub result: SpeechResult,
+}
+
+/// 已完成建表和迁移的数据库，复用空闲连接
+pub struct Database {
+    /// 打开连接使用的路径或 URI
+    uri: String,
+    idle: Mutex<Vec<Connection>>,
+    /// 写入与整理互斥：普通写入共享读锁，`VACUUM` 独占写锁
+    maintenance: RwLock<()>,
+    /// 最近一次写入的时间
+    last_write: WriteClock,
+    /// 内存数据库在最后一个连接关闭时销毁，这里始终保留一个连接
+    _keeper: Option<Mutex<Connection>>,
+}
+
+impl Database {
+    /// 打开数据库并执行一次建表和迁移，`:memory:` 为本实例独有的内存数据库
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let (uri, keeper) = if path == MEMORY_DB_PATH {
+            // 共享缓存让同一实例的各个连接看到同一份数据，不同实例互不影响
+            let uri = format!(
+                "file:heart-mirror-history-{}?mode=memory&cache=shared",
+                MEMORY_DB_COUNT.fetch_add(1, Ordering::Relaxed)
+            );
+            let keeper = Connection::open(&uri)?;
+            (uri, Some(Mutex::new(keeper)))
+        } else {
+            (path.to_string(), None)
+        };
+        let conn = connect(&uri)?;
+        // WAL 模式下读写互不阻塞，设置会持久保存在数据库文件中
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        migrate(&conn)?;
+        Ok(Self {
+            uri,
+            idle: Mutex::new(vec![conn]),
+            maintenance: RwLock::new(()),
+            last_write: WriteClock::default(),
+            _keeper: keeper,
+        })
+    }
+
+    /// 取出一个空闲连接，没有时新建
+    fn connection(&self) -> rusqlite::Result<PooledConnection<'_>> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => connect(&self.uri)?,
+        };
+        Ok(PooledConnection {
+            database: self,
+            conn: Some(conn),
+        })
+    }
+}
+
+/// 从连接池借出的连接，释放时放回池中
+struct PooledConnection<'a> {
+    database: &'a Database,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("连接在释放前始终存在")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("连接在释放前始终存在")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        let mut idle = self
+            .database
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(conn);
+        }
+    }
+}
+
+impl Database {
+    /// 将情绪识别结果插入到SQLite数据库
+    /// 格式：id, text, emotion, created_at（ISO 8601时间戳）及会话、置信度、耗时、质量等元数据
+    pub fn insert_speech_result(&self, result: &SpeechResult) -> rusqlite::Result<()> {
+        let _guard = self
+            .maintenance
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.last_write.mark();
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO speech_results (
+                session_id, text, emotion, confidence, recognition_ms, emotion_ms,
+                created_at, quality, rejection, fingerprint, audio_path, vad,
+                asr_model, emotion_model, detected_language, text_raw,
+                prosody, created_at_ms
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                result.session_id,
+                text::truncate_chars(&result.text, MAX_TEXT_CHARS),
+                result.emotion,
+                result.confidence,
+                result.timings.recognition_ms,
+                result.timings.emotion_ms,
+                result.created_at,
+                result.quality(),
+                result.rejection,
+                to_json(result.fingerprint),
+                result.audio_path,
+                to_json(result.vad),
+                result.asr_model,
+                result.emotion_model,
+                result.detected_language,
+                result
+                    .text_raw
+                    .as_deref()
+                    .map(|raw| text::truncate_chars(raw, MAX_TEXT_CHARS)),
+                to_json(result.prosody),
+                DateTime::parse_from_rfc3339(&result.created_at)
+                    .ok()
+                    .map(|time| time.timestamp_millis()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 写入一条影子模型对比记录
+    pub fn insert_shadow_comparison(&self, comparison: &ShadowComparison) -> rusqlite::Result<()> {
+        let _guard = self
+            .maintenance
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.last_write.mark();
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO shadow_results (
+                session_id, primary_model, shadow_model, primary_text, shadow_text,
+                edit_distance, primary_ms, shadow_ms, created_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                comparison.session_id,
+                comparison.primary_model,
+                comparison.shadow_model,
+                text::truncate_chars(&comparison.primary_text, MAX_TEXT_CHARS),
+                text::truncate_chars(&comparison.shadow_text, MAX_TEXT_CHARS),
+                comparison.edit_distance,
+                comparison.primary_ms,
+                comparison.shadow_ms,
+                comparison.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 检查数据库是否可写
+    pub fn check_writable(&self) -> rusqlite::Result<()> {
+        let conn = self.connection()?;
+        conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")
+    }
+
+    /// 按 ID 查询单条识别记录
+    pub fn get_speech_result(&self, id: i64) -> rusqlite::Result<Option<HistoryRecord>> {
+        let conn = self.connection()?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM speech_results WHERE id = ?1",
+                RECORD_COLUMNS
+            ),
+            params![id],
+            history_record_from_row,
+        )
+        .optional()
+    }
+
+    /// 统计 `since_ms`（Unix 毫秒）之后的记录数，`None` 表示全部
+    pub fn count_speech_results_since(&self, since_ms: Option<i64>) -> rusqlite::Result<usize> {
+        let conn = self.connection()?;
+        conn.query_row(
+            "SELECT COUNT(*) FROM speech_results WHERE ?1 IS NULL OR created_at_ms >= ?1",
+            params![since_ms],
+            |row| row.get(0),
+        )
+    }
+
+    /// 按 ID 升序查询 `since_ms` 之后、ID 大于 `after_id` 的至多 `limit` 条记录
+    pub fn list_speech_results_page(
+        &self,
+        since_ms: Option<i64>,
+        after_id: i64,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<HistoryRecord>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM speech_results
+             WHERE (?1 IS NULL OR created_at_ms >= ?1) AND id > ?2
+             ORDER BY id LIMIT ?3",
+            RECORD_COLUMNS
+        ))?;
+        let records = stmt
+            .query_map(params![since_ms, after_id, limit], history_record_from_row)?
+            .collect();
+        records
+    }
+
+    /// 更新记录的情绪和情绪模型并写入审计记录，修改时间按 `timezone` 记录
+    pub fn update_emotion(
+        &self,
+        id: i64,
+        old_emotion: &str,
+        new_emotion: &str,
+        model: &str,
+        timezone: Tz,
+    ) -> rusqlite::Result<()> {
+        let _guard = self
+            .maintenance
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.last_write.mark();
+        let mut conn = self.connection()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE speech_results SET emotion = ?1, emotion_model = ?2 WHERE id = ?3",
+            params![new_emotion, model, id],
+        )?;
+        tx.execute(
+            "INSERT INTO emotion_audit (record_id, old_emotion, new_emotion, model, changed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                id,
+                old_emotion,
+                new_emotion,
+                model,
+                result::now_rfc3339(timezone)
+            ],
+        )?;
+        tx.commit()
+    }
+
+    /// 整理数据库并回收已删除记录占用的空间，期间阻塞写入
+    pub fn vacuum(&self) -> rusqlite::Result<()> {
+        let _guard = self
+            .maintenance
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let conn = self.connection()?;
+        conn.execute_batch("PRAGMA optimize; VACUUM;")
+    }
+
+    /// 距离最近一次写入的时间
+    pub fn idle_duration(&self) -> Duration {
+        self.last_write.elapsed()
+    }
+}
+
+/// 新建连接，不执行迁移
+fn connect(uri: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(uri)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+/// 确保表结构存在并补齐旧数据库缺少的列
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS speech_results (
+            id INTEGER PRIMARY KEY,
+            text TEXT NOT NULL,
+            emotion TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    ensure_column(conn, "speech_results", "quality", "TEXT")?;
+    ensure_column(conn, "speech_results", "session_id", "TEXT")?;
+    ensure_column(conn, "speech_results", "confidence", "REAL")?;
+    ensure_column(conn, "speech_results", "recognition_ms", "INTEGER")?;
+    ensure_column(conn, "speech_results", "emotion_ms", "INTEGER")?;
+    ensure_column(conn, "speech_results", "rejection", "TEXT")?;
+    ensure_column(conn, "speech_results", "fingerprint", "TEXT")?;
+    ensure_column(conn, "speech_results", "audio_path", "TEXT")?;
+    ensure_column(conn, "speech_results", "vad", "TEXT")?;
+    ensure_column(conn, "speech_results", "asr_model", "TEXT")?;
+    ensure_column(conn, "speech_results", "emotion_model", "TEXT")?;
+    ensure_column(conn, "speech_results", "detected_language", "TEXT")?;
+    ensure_column(conn, "speech_results", "text_raw", "TEXT")?;
+    ensure_column(conn, "speech_results", "prosody", "TEXT")?;
+    ensure_column(conn, "speech_results", "created_at_ms", "INTEGER")?;
+    // `created_at` 带写入时所配时区的偏移，不能按文本比较；旧记录按其偏移换算为 UTC 毫秒
+    conn.execute(
+        "UPDATE speech_results
+         SET created_at_ms = CAST(ROUND((julianday(created_at) - 2440587.5) * 86400000) AS INTEGER)
+         WHERE created_at_ms IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS speech_results_created_at_ms
+         ON speech_results (created_at_ms)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS emotion_audit (
+            id INTEGER PRIMARY KEY,
+            record_id INTEGER NOT NULL,
+            old_emotion TEXT NOT NULL,
+            new_emotion TEXT NOT NULL,
+            model TEXT NOT NULL,
+            changed_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shadow_results (
+            id INTEGER PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            primary_model TEXT NOT NULL,
+            shadow_model TEXT NOT NULL,
+            primary_text TEXT NOT NULL,
+            shadow_text TEXT NOT NULL,
+            edit_distance INTEGER NOT NULL,
+            primary_ms INTEGER NOT NULL,
+            shadow_ms INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 写入失败的类别，便于区分锁冲突、磁盘已满和其他错误
+pub fn failure_kind(error: &rusqlite::Error) -> &'static str {
+    match error.sqlite_error_code() {
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => "locked",
+        Some(ErrorCode::DiskFull) => "disk_full",
+        _ => "other",
+    }
+}
+
+/// 为旧数据库补充新增的列
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// 旧版本写入的记录缺少新增列，读取时使用默认值
+fn history_record_from_row(row: &Row<'_>) -> rusqlite::Result<HistoryRecord> {
+    Ok(HistoryRecord {
+        id: row.get(0)?,
+        result: SpeechResult {
+            session_id: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            text: row.get(2)?,
+            text_raw: row.get(15)?,
+            emotion: row.get(3)?,
+            confidence: row.get(4)?,
+            timings: Timings {
+                recognition_ms: row.get::<_, Option<u64>>(5)?.unwrap_or_default(),
+                emotion_ms: row.get::<_, Option<u64>>(6)?.unwrap_or_default(),
+            },
+            created_at: row.get(7)?,
+            rejection: row.get(8)?,
+            fingerprint: from_json(row.get(9)?),
+            audio_path: row.get(10)?,
+            persist_failed: false,
+            vad: from_json(row.get(11)?),
+            prosody: from_json(row.get(16)?),
+            asr_model: row.get(12)?,
+            emotion_model: row.get(13)?,
+            detected_language: row.get(14)?,
+            tokens: None,
+            offset_ms: 0,
+            segments: Vec::new(),
+        },
+    })
+}
+
+/// 可选的结构化字段以 JSON 文本存储
+pub fn to_json<T: Serialize>(value: Option<T>) -> Option<String> {
+    value.and_then(|value| serde_json::to_string(&value).ok())
+}
+
+/// 读取 JSON 文本列，无法解析的旧数据视为空
+pub fn from_json<T: DeserializeOwned>(json: Option<String>) -> Option<T> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+/// 记录最近一次写入的 Unix 时间（秒），供定期整理判断是否空闲
+#[derive(Default)]
+pub struct WriteClock(AtomicU64);
+
+impl WriteClock {
+    /// 记为刚刚写入
+    pub fn mark(&self) {
+        self.0.store(unix_now(), Ordering::Relaxed);
+    }
+
+    /// 距离最近一次写入的时间
+    pub fn elapsed(&self) -> Duration {
+        let last_write = self.0.load(Ordering::Relaxed);
+        Duration::from_secs(unix_now().saturating_sub(last_write))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speech_result(text: &str, created_at: &str) -> SpeechResult {
+        SpeechResult {
+            session_id: "session".to_string(),
+            text: text.to_string(),
+            text_raw: None,
+            emotion: "平静".to_string(),
+            confidence: Some(0.9),
+            timings: Timings {
+                recognition_ms: 120,
+                emotion_ms: 80,
+            },
+            fingerprint: None,
+            vad: None,
+            prosody: None,
+            tokens: None,
+            asr_model: Some("ggml-small.bin".to_string()),
+            emotion_model: Some("qwen".to_string()),
+            detected_language: None,
+            created_at: created_at.to_string(),
+            rejection: None,
+            audio_path: None,
+            persist_failed: false,
+            offset_ms: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    /// 打开独立的内存数据库并按顺序写入 `texts`，第 i 条的时间为 2024-05-01 08:0i:00+08:00
+    fn database_with(texts: &[&str]) -> Database {
+        let database = Database::open(MEMORY_DB_PATH).unwrap();
+        for (i, text) in texts.iter().enumerate() {
+            let created_at = format!("2024-05-01T08:0{}:00+08:00", i);
+            database
+                .insert_speech_result(&speech_result(text, &created_at))
+                .unwrap();
+        }
+        database
+    }
+
+    #[test]
+    fn inserted_result_round_trips() {
+        let database = database_with(&["今天很开心"]);
+
+        let record = database.get_speech_result(1).unwrap().expect("记录应存在");
+        assert_eq!(record.id, 1);
+        assert_eq!(record.result.text, "今天很开心");
+        assert_eq!(record.result.emotion, "平静");
+        assert_eq!(record.result.confidence, Some(0.9));
+        assert_eq!(record.result.timings.recognition_ms, 120);
+        assert_eq!(record.result.asr_model.as_deref(), Some("ggml-small.bin"));
+        assert_eq!(record.result.created_at, "2024-05-01T08:00:00+08:00");
+        assert!(database.get_speech_result(2).unwrap().is_none());
+    }
+
+    #[test]
+    fn memory_databases_are_isolated() {
+        let first = database_with(&["一", "二"]);
+        let second = database_with(&[]);
+
+        assert_eq!(first.count_speech_results_since(None).unwrap(), 2);
+        assert_eq!(second.count_speech_results_since(None).unwrap(), 0);
+    }
+
+    #[test]
+    fn pages_filter_by_utc_time_and_id() {
+        let database = database_with(&["一", "二", "三", "四"]);
+        // 2024-05-01T00:01:00Z，即第二条记录的时间
+        let since_ms = Some(1_714_521_660_000);
+
+        assert_eq!(database.count_speech_results_since(None).unwrap(), 4);
+        assert_eq!(database.count_speech_results_since(since_ms).unwrap(), 3);
+
+        let texts = |records: Vec<HistoryRecord>| -> Vec<String> {
+            records
+                .into_iter()
+                .map(|record| record.result.text)
+                .collect()
+        };
+        let first = database.list_speech_results_page(since_ms, 0, 2).unwrap();
+        let after_id = first.last().unwrap().id;
+        assert_eq!(texts(first), ["二", "三"]);
+        let rest = database
+            .list_speech_results_page(since_ms, after_id, 2)
+            .unwrap();
+        assert_eq!(texts(rest), ["四"]);
+    }
+
+    #[test]
+    fn update_emotion_changes_record_and_writes_audit() {
+        let database = database_with(&["有点难过"]);
+
+        database
+            .update_emotion(1, "平静", "悲伤", "llama", Tz::Asia__Shanghai)
+            .unwrap();
+
+        let record = database.get_speech_result(1).unwrap().unwrap();
+        assert_eq!(record.result.emotion, "悲伤");
+        assert_eq!(record.result.emotion_model.as_deref(), Some("llama"));
+        let audit: (i64, String, String) = database
+            .connection()
+            .unwrap()
+            .query_row(
+                "SELECT record_id, old_emotion, new_emotion FROM emotion_audit",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(audit, (1, "平静".to_string(), "悲伤".to_string()));
+    }
+}
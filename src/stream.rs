@@ -0,0 +1,467 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::audio::{AudioProcessor, Utterance};
+use crate::clip::{self, WavHeader};
+use crate::coalesce::{Coalescer, Interim};
+use crate::emotion::EmotionSmoother;
+use crate::framing::{self, Opcode};
+use crate::pipeline::{self, ConversationState, SegmentEmotion, SessionSettings, UtteranceHooks};
+use crate::protocol::{ErrorResponse, SegmentEmotionResponse, ServerResponse};
+use crate::result;
+use crate::speech::Recognizer;
+use crate::state::AppState;
+
+/// 会话结果未被读取时的保留时长
+const SESSION_TTL: Duration = Duration::from_secs(300);
+/// 标准输入模式每次读取的字节数
+const STDIN_CHUNK_BYTES: usize = 8192;
+
+/// 等待 SSE 客户端读取的会话
+#[derive(Default)]
+pub struct StreamSessions {
+    receivers: Mutex<HashMap<String, mpsc::UnboundedReceiver<StreamEvent>>>,
+}
+
+/// 会话中产生的事件
+enum StreamEvent {
+    /// 当前语句的识别进度百分比
+    Progress(i32),
+    Result(Box<ServerResponse>),
+    /// 分段情绪，开启 `SEGMENT_EMOTION` 时推送
+    SegmentEmotion(SegmentEmotionResponse),
+    Done,
+}
+
+impl Interim for StreamEvent {
+    fn interim_kind(&self) -> Option<&'static str> {
+        match self {
+            StreamEvent::Progress(_) => Some("progress"),
+            StreamEvent::SegmentEmotion(_) => Some("segment_emotion"),
+            StreamEvent::Result(_) | StreamEvent::Done => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ProgressEvent {
+    percent: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartParams {
+    /// 客户端语言，如 `zh-CN`
+    locale: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StartResponse {
+    session_id: String,
+}
+
+/// 上传一段 16kHz 单声道 PCM16LE 音频并创建会话，结果通过 `GET /stream/{id}` 读取
+pub async fn start(
+    State(state): State<AppState>,
+    Query(params): Query<StartParams>,
+    body: Bytes,
+) -> Result<Json<StartResponse>, (StatusCode, String)> {
+    let audio_processor = AudioProcessor::from_config(&state.config(), state.silero_vad.as_ref())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let session_id = result::new_session_id();
+    let (tx, rx) = mpsc::unbounded_channel();
+    state
+        .stream_sessions
+        .receivers
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), rx);
+
+    info!("SSE 会话 {} 开始，音频 {} 字节", session_id, body.len());
+    tokio::spawn(run_session(
+        state,
+        session_id.clone(),
+        audio_processor,
+        params.locale,
+        body,
+        tx,
+    ));
+
+    Ok(Json(StartResponse { session_id }))
+}
+
+/// 以 Server-Sent Events 推送会话的识别结果，结束时发送 `done` 事件
+pub async fn events(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    let rx = state
+        .stream_sessions
+        .receivers
+        .lock()
+        .unwrap()
+        .remove(&session_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let coalescer = Coalescer::new(rx, state.config().coalesce_interim);
+    let events = stream::unfold(coalescer, |mut rx| async move {
+        let event = match rx.recv().await? {
+            StreamEvent::Progress(percent) => Event::default()
+                .event("progress")
+                .json_data(ProgressEvent { percent })
+                .unwrap_or_default(),
+            StreamEvent::Result(response) => Event::default()
+                .event("result")
+                .json_data(&response)
+                .unwrap_or_default(),
+            StreamEvent::SegmentEmotion(segment) => Event::default()
+                .event("segment_emotion")
+                .json_data(&segment)
+                .unwrap_or_default(),
+            StreamEvent::Done => Event::default().event("done").data("{}"),
+        };
+        Some((Ok(event), rx))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// HTTP 流式会话的处理状态，与 WebSocket 会话一样维护对话上下文和情绪平滑
+struct HttpSession {
+    recognizer: Arc<dyn Recognizer>,
+    settings: SessionSettings,
+    smoother: EmotionSmoother,
+    conversation: ConversationState,
+    /// 已识别的语句数，作为下一句的序号
+    utterances: u64,
+}
+
+impl HttpSession {
+    fn new(state: &AppState, session_id: String, locale: Option<&str>) -> Self {
+        let config = state.config();
+        Self {
+            recognizer: state.recognizers.select(locale),
+            settings: SessionSettings::new(session_id),
+            smoother: EmotionSmoother::new(config.emotion_smoothing_window),
+            conversation: ConversationState::new(&config),
+            utterances: 0,
+        }
+    }
+
+    /// 处理一个完整语音片段，无效语音返回 None
+    ///
+    /// `hooks.utterance` 由这里按会话内顺序填写。
+    async fn process(
+        &mut self,
+        state: &AppState,
+        utterance: &Utterance,
+        mut hooks: UtteranceHooks,
+    ) -> Option<ServerResponse> {
+        let sequence = self.utterances;
+        self.utterances += 1;
+        hooks.utterance = sequence;
+        let mut result = pipeline::process_utterance(
+            state,
+            &self.recognizer,
+            &self.settings,
+            &mut self.conversation,
+            utterance,
+            hooks,
+        )
+        .await?;
+
+        if result.rejection.is_none() {
+            result.emotion = self.smoother.update(result.emotion);
+        }
+        let config = state.config();
+        let mut response = ServerResponse::speech_result(result, true)
+            .with_action(config.emotion_actions.as_ref())
+            .with_label(&config.emotion_labels);
+        if config.segment_emotion {
+            response.utterance = Some(sequence);
+        }
+        Some(response)
+    }
+}
+
+/// 按帧送入 VAD，逐句识别并推送结果
+async fn run_session(
+    state: AppState,
+    session_id: String,
+    mut audio_processor: AudioProcessor,
+    locale: Option<String>,
+    body: Bytes,
+    tx: mpsc::UnboundedSender<StreamEvent>,
+) {
+    let mut session = HttpSession::new(&state, session_id.clone(), locale.as_deref());
+
+    let mut utterances = audio_processor.process_pcm_bytes(&body);
+    utterances.extend(audio_processor.flush());
+
+    // 分段情绪任务可能晚于整句结果完成，全部转发后才发送 done
+    let (segment_tx, mut segment_rx) = mpsc::unbounded_channel::<SegmentEmotion>();
+    let forward_tx = tx.clone();
+    let labels = state.config().emotion_labels.clone();
+    let forwarder = tokio::spawn(async move {
+        while let Some(segment) = segment_rx.recv().await {
+            let segment = SegmentEmotionResponse::new(segment, &labels);
+            let _ = forward_tx.send(StreamEvent::SegmentEmotion(segment));
+        }
+    });
+
+    for utterance in utterances {
+        let mut last_percent = None;
+        let progress_tx = tx.clone();
+        let on_progress = move |percent: i32| {
+            // whisper.cpp 可能重复回报同一进度
+            if last_percent != Some(percent) {
+                last_percent = Some(percent);
+                let _ = progress_tx.send(StreamEvent::Progress(percent));
+            }
+        };
+        let hooks = UtteranceHooks {
+            on_progress: Some(Box::new(on_progress)),
+            segment_emotions: Some(segment_tx.clone()),
+            ..Default::default()
+        };
+        if let Some(response) = session.process(&state, &utterance, hooks).await {
+            let _ = tx.send(StreamEvent::Result(Box::new(response)));
+        }
+    }
+    drop(segment_tx);
+    let _ = forwarder.await;
+    let _ = tx.send(StreamEvent::Done);
+    info!("SSE 会话 {} 处理完成", session_id);
+
+    tokio::time::sleep(SESSION_TTL).await;
+    if state
+        .stream_sessions
+        .receivers
+        .lock()
+        .unwrap()
+        .remove(&session_id)
+        .is_some()
+    {
+        warn!("SSE 会话 {} 超时未被读取，已丢弃", session_id);
+    }
+}
+
+/// 上传音频的编码
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    /// 16kHz 单声道 PCM16LE
+    #[default]
+    Pcm,
+    /// 长度前缀的 Opus 帧，格式同二进制帧协议的 `audio` 帧
+    Opus,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamAudioParams {
+    /// 客户端语言，如 `zh-CN`
+    locale: Option<String>,
+    #[serde(default)]
+    format: AudioFormat,
+}
+
+/// 以分块请求体持续上传音频，识别结果逐句以 NDJSON 写回响应体
+pub async fn stream_audio(
+    State(state): State<AppState>,
+    Query(params): Query<StreamAudioParams>,
+    body: Body,
+) -> Result<Response, (StatusCode, String)> {
+    let audio_processor = AudioProcessor::from_config(&state.config(), state.silero_vad.as_ref())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(run_stream_audio(state, audio_processor, params, body, tx));
+
+    let lines = stream::unfold(rx, |mut rx| async move {
+        let line: String = rx.recv().await?;
+        Some((Ok::<_, Infallible>(line), rx))
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// 边读取请求体边识别，每句结果作为一行 JSON 发送
+async fn run_stream_audio(
+    state: AppState,
+    mut audio_processor: AudioProcessor,
+    params: StreamAudioParams,
+    body: Body,
+    tx: mpsc::UnboundedSender<String>,
+) {
+    let session_id = result::new_session_id();
+    let mut session = HttpSession::new(&state, session_id.clone(), params.locale.as_deref());
+    info!("NDJSON 会话 {} 开始 ({:?})", session_id, params.format);
+
+    let mut chunks = body.into_data_stream();
+    let mut pending = Vec::new();
+
+    while let Some(chunk) = chunks.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!("NDJSON 会话 {} 读取请求体失败: {}", session_id, e);
+                break;
+            }
+        };
+
+        pending.extend_from_slice(&chunk);
+        let utterances = match params.format {
+            AudioFormat::Pcm => drain_pcm(&mut audio_processor, &mut pending),
+            AudioFormat::Opus => match drain_opus(&mut audio_processor, &mut pending) {
+                Ok(utterances) => utterances,
+                Err(e) => {
+                    warn!("NDJSON 会话 {} 音频帧解析失败: {}", session_id, e);
+                    if let Ok(json) = serde_json::to_string(&ErrorResponse::invalid_frame(e)) {
+                        let _ = tx.send(json + "\n");
+                    }
+                    break;
+                }
+            },
+        };
+
+        if !send_lines(&state, &mut session, &tx, utterances).await {
+            info!("NDJSON 会话 {} 客户端已断开", session_id);
+            return;
+        }
+    }
+
+    let remaining = audio_processor.flush().into_iter().collect();
+    send_lines(&state, &mut session, &tx, remaining).await;
+    info!("NDJSON 会话 {} 处理完成", session_id);
+}
+
+/// 逐句识别并以 JSON 行发送，客户端断开时返回 false
+async fn send_lines(
+    state: &AppState,
+    session: &mut HttpSession,
+    tx: &mpsc::UnboundedSender<String>,
+    utterances: Vec<Utterance>,
+) -> bool {
+    for utterance in utterances {
+        if pipeline::is_stale(state, &utterance) {
+            continue;
+        }
+        let Some(response) = session
+            .process(state, &utterance, UtteranceHooks::default())
+            .await
+        else {
+            continue;
+        };
+        let Ok(json) = serde_json::to_string(&response) else {
+            continue;
+        };
+        if tx.send(json + "\n").is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// 消费缓冲区中完整的 PCM 样本，奇数字节留到下一块
+fn drain_pcm(audio_processor: &mut AudioProcessor, pending: &mut Vec<u8>) -> Vec<Utterance> {
+    let complete = pending.len() - pending.len() % 2;
+    let utterances = audio_processor.process_pcm_bytes(&pending[..complete]);
+    pending.drain(..complete);
+    utterances
+}
+
+/// 消费缓冲区中完整的 Opus 帧，不完整的帧留到下一块
+fn drain_opus(
+    audio_processor: &mut AudioProcessor,
+    pending: &mut Vec<u8>,
+) -> Result<Vec<Utterance>, framing::FrameError> {
+    let (frames, consumed) = framing::decode_prefix(pending)?;
+    let utterances = frames
+        .into_iter()
+        .filter(|frame| frame.opcode == Opcode::Audio)
+        .flat_map(|frame| audio_processor.process_audio(frame.payload))
+        .collect();
+    pending.drain(..consumed);
+    Ok(utterances)
+}
+
+/// 从标准输入读取音频，识别结果逐句以 JSON 行写到标准输出，用于本地调试
+///
+/// 输入为 16kHz 单声道 PCM16LE，开头带 WAV 头部时自动跳过。与 NDJSON 接口共用切分、识别和
+/// 情绪分析流程，结果同样写入配置的输出端。
+pub async fn run_stdin(state: AppState) -> io::Result<()> {
+    let mut audio_processor =
+        AudioProcessor::from_config(&state.config(), state.silero_vad.as_ref())
+            .map_err(io::Error::other)?;
+    let session_id = result::new_session_id();
+    let mut session = HttpSession::new(&state, session_id.clone(), None);
+    info!("标准输入会话 {} 开始", session_id);
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let printer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = rx.recv().await {
+            stdout.write_all(line.as_bytes()).await?;
+            stdout.flush().await?;
+        }
+        Ok::<_, io::Error>(())
+    });
+
+    let mut stdin = tokio::io::stdin();
+    let mut chunk = vec![0; STDIN_CHUNK_BYTES];
+    let mut pending = Vec::new();
+    let mut header_done = false;
+    loop {
+        let read = stdin.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        pending.extend_from_slice(&chunk[..read]);
+        if !header_done {
+            match clip::parse_wav_header(&pending) {
+                WavHeader::Incomplete => continue,
+                WavHeader::NotWav => {}
+                header @ WavHeader::Data { offset, .. } => {
+                    if !header.is_supported_format() {
+                        warn!(
+                            "WAV 格式不是 16kHz 单声道 16 位 PCM，仍按该格式处理: {:?}",
+                            header
+                        );
+                    }
+                    pending.drain(..offset);
+                }
+            }
+            header_done = true;
+        }
+        let utterances = drain_pcm(&mut audio_processor, &mut pending);
+        send_lines(&state, &mut session, &tx, utterances).await;
+    }
+
+    let remaining = audio_processor.flush().into_iter().collect();
+    send_lines(&state, &mut session, &tx, remaining).await;
+    drop(tx);
+    info!("标准输入会话 {} 处理完成", session_id);
+    printer.await.map_err(io::Error::other)?
+}
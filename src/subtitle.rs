@@ -0,0 +1,96 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::speech::SegmentTiming;
+
+/// 字幕文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    /// 文件扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+        }
+    }
+
+    /// 新文件开头需要写入的内容
+    pub fn header(self) -> &'static str {
+        match self {
+            Self::Srt => "",
+            Self::Vtt => "WEBVTT\n\n",
+        }
+    }
+}
+
+impl FromStr for SubtitleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "srt" => Ok(Self::Srt),
+            "vtt" => Ok(Self::Vtt),
+            other => Err(format!("未知的字幕格式: {}", other)),
+        }
+    }
+}
+
+/// 把一句语音的分段格式化为字幕条目
+///
+/// 分段时间相对语句开头，加上 `offset_ms`（语句相对会话开头的偏移）后写入；
+/// `first_index` 为本句第一条的序号，仅 SRT 使用。空白分段被跳过。
+pub fn format_cues(
+    format: SubtitleFormat,
+    segments: &[SegmentTiming],
+    offset_ms: u64,
+    first_index: usize,
+) -> String {
+    let mut out = String::new();
+    let cues = segments
+        .iter()
+        .filter(|segment| !segment.text.trim().is_empty());
+    for (i, segment) in cues.enumerate() {
+        let start = offset_ms + segment.start_ms;
+        let end = offset_ms + segment.end_ms.max(segment.start_ms);
+        if format == SubtitleFormat::Srt {
+            let _ = writeln!(out, "{}", first_index + i);
+        }
+        let _ = writeln!(
+            out,
+            "{} --> {}",
+            timestamp(format, start),
+            timestamp(format, end)
+        );
+        let _ = writeln!(out, "{}\n", segment.text.trim());
+    }
+    out
+}
+
+/// 已写入的字幕条数，用于续写 SRT 序号
+pub fn count_cues(content: &str) -> usize {
+    content
+        .lines()
+        .filter(|line| line.contains(" --> "))
+        .count()
+}
+
+/// SRT 使用 `00:00:01,500`，VTT 使用 `00:00:01.500`
+fn timestamp(format: SubtitleFormat, ms: u64) -> String {
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::Vtt => '.',
+    };
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
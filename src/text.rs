@@ -0,0 +1,205 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use zhconv::{zhconv, Variant};
+
+/// 日志中记录用户文本的方式，与是否持久化无关
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptLogging {
+    /// 记录原文，用于开发调试
+    Full,
+    /// 只记录文本哈希和长度，便于关联同一内容
+    Hash,
+    /// 只记录长度
+    Redact,
+}
+
+impl TranscriptLogging {
+    /// 按当前方式包装文本，用于日志格式化
+    pub fn display<'a>(&self, text: &'a str) -> LoggedText<'a> {
+        LoggedText { mode: *self, text }
+    }
+}
+
+impl FromStr for TranscriptLogging {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "hash" => Ok(Self::Hash),
+            "redact" | "off" => Ok(Self::Redact),
+            other => Err(format!("未知的日志脱敏方式: {}", other)),
+        }
+    }
+}
+
+/// 按脱敏方式格式化的文本
+pub struct LoggedText<'a> {
+    mode: TranscriptLogging,
+    text: &'a str,
+}
+
+impl fmt::Display for LoggedText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chars = self.text.chars().count();
+        match self.mode {
+            TranscriptLogging::Full => f.write_str(self.text),
+            TranscriptLogging::Hash => {
+                let mut hasher = DefaultHasher::new();
+                self.text.hash(&mut hasher);
+                write!(f, "#{:016x} ({} 字)", hasher.finish(), chars)
+            }
+            TranscriptLogging::Redact => write!(f, "<已隐藏 {} 字>", chars),
+        }
+    }
+}
+
+/// 规范化识别文本：繁体转简体，全角 ASCII 字符转半角
+pub fn normalize_transcript(text: &str) -> String {
+    let simplified = zhconv(text, Variant::ZhHans);
+    simplified.chars().map(to_half_width).collect()
+}
+
+/// 按字符数截断文本，截断点总在字符边界上，不会切断多字节的中文字符
+pub fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((byte_index, _)) => &text[..byte_index],
+        None => text,
+    }
+}
+
+/// 按字符数截断文本，超出时以 `…` 结尾且总长不超过 `max_chars`，返回是否发生截断
+pub fn truncate_with_ellipsis(text: &str, max_chars: usize) -> (String, bool) {
+    if text.chars().nth(max_chars).is_none() {
+        return (text.to_string(), false);
+    }
+    let kept = truncate_chars(text, max_chars.saturating_sub(1));
+    (format!("{}…", kept), true)
+}
+
+/// 截断到句子边界时至少保留 `max_chars` 的这一比例，否则直接按字符截断
+const MIN_SENTENCE_KEEP_RATIO: f32 = 0.5;
+
+/// 按字符数截断文本，尽量在句末标点之后截断，返回截断后的文本和是否发生截断
+///
+/// 最后一个完整句子过短时退回按字符截断。
+pub fn truncate_at_sentence(text: &str, max_chars: usize) -> (&str, bool) {
+    let kept = truncate_chars(text, max_chars);
+    if kept.len() == text.len() {
+        return (text, false);
+    }
+
+    let min_chars = (max_chars as f32 * MIN_SENTENCE_KEEP_RATIO) as usize;
+    let sentence_end = kept
+        .char_indices()
+        .enumerate()
+        .filter(|&(_, (_, c))| {
+            matches!(c, '。' | '！' | '？' | '；' | '.' | '!' | '?' | ';' | '\n')
+        })
+        .filter(|&(count, _)| count + 1 >= min_chars)
+        .map(|(_, (index, c))| index + c.len_utf8())
+        .last();
+    match sentence_end {
+        Some(end) => (&kept[..end], true),
+        None => (kept, true),
+    }
+}
+
+/// 重叠部分至少多少字才去重，过短的重合多半是巧合
+const MIN_OVERLAP_CHARS: usize = 2;
+
+/// 去掉 `next` 开头与 `previous` 结尾重复的文本，用于拼接重叠窗口的识别结果
+///
+/// 比较时忽略 `previous` 末尾的标点和空白，去重后再去掉 `next` 剩余部分开头的标点。
+pub fn strip_overlap<'a>(previous: &str, next: &'a str) -> &'a str {
+    let previous = previous.trim_end_matches(|c: char| !c.is_alphanumeric());
+    let overlap_end = next
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([next.len()])
+        .skip(MIN_OVERLAP_CHARS)
+        .filter(|&end| previous.ends_with(&next[..end]))
+        .last();
+
+    match overlap_end {
+        Some(end) => next[end..].trim_start_matches(|c: char| !c.is_alphanumeric()),
+        None => next,
+    }
+}
+
+/// 全角 ASCII（U+FF01–U+FF5E）及全角空格转为半角
+fn to_half_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 中英文、emoji 混排，字符占 1 到 4 个字节
+    const MIXED: &str = "今天天气不错。Let's go出去走走吧😀！好的";
+
+    #[test]
+    fn truncate_chars_lands_on_char_boundaries() {
+        let total = MIXED.chars().count();
+        for max_chars in 0..=total + 1 {
+            let kept = truncate_chars(MIXED, max_chars);
+            assert!(MIXED.is_char_boundary(kept.len()));
+            assert_eq!(kept.chars().count(), max_chars.min(total));
+            assert!(MIXED.starts_with(kept));
+        }
+    }
+
+    #[test]
+    fn truncate_with_ellipsis_respects_limit() {
+        let total = MIXED.chars().count();
+        assert_eq!(
+            truncate_with_ellipsis(MIXED, total),
+            (MIXED.to_string(), false)
+        );
+        assert_eq!(
+            truncate_with_ellipsis(MIXED, total + 1),
+            (MIXED.to_string(), false)
+        );
+
+        for max_chars in 1..total {
+            let (kept, truncated) = truncate_with_ellipsis(MIXED, max_chars);
+            assert!(truncated);
+            assert_eq!(kept.chars().count(), max_chars);
+            let body = kept.strip_suffix('…').unwrap();
+            assert!(MIXED.starts_with(body));
+        }
+        // emoji 前后截断不会切断其 4 字节编码
+        let emoji = MIXED.chars().position(|c| c == '😀').unwrap();
+        assert_eq!(
+            truncate_with_ellipsis(MIXED, emoji + 2).0,
+            "今天天气不错。Let's go出去走走吧😀…"
+        );
+    }
+
+    #[test]
+    fn truncate_at_sentence_prefers_sentence_end() {
+        let total = MIXED.chars().count();
+        assert_eq!(truncate_at_sentence(MIXED, total), (MIXED, false));
+
+        for max_chars in 0..total {
+            let (kept, truncated) = truncate_at_sentence(MIXED, max_chars);
+            assert!(truncated);
+            assert!(MIXED.is_char_boundary(kept.len()));
+            assert!(kept.chars().count() <= max_chars);
+        }
+        // 第一句足够长时截在句号之后
+        assert_eq!(truncate_at_sentence(MIXED, 12), ("今天天气不错。", true));
+        // 句子过短时退回按字符截断
+        assert_eq!(
+            truncate_at_sentence(MIXED, 20),
+            ("今天天气不错。Let's go出去走走吧", true)
+        );
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use ort::session::Session;
+use ort::value::Tensor;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::audio::{calculate_rms, AudioOptions};
+use crate::config::Config;
+
+const SAMPLE_RATE: usize = 16000;
+pub const VAD_THRESHOLD_START: f32 = 800.0;
+pub const VAD_THRESHOLD_END: f32 = 500.0;
+/// 静音持续多久后结束录音，按样本数计时，与客户端帧长无关
+const MAX_SILENCE_MS: usize = 240;
+const MAX_SILENCE_SAMPLES: usize = SAMPLE_RATE * MAX_SILENCE_MS / 1000;
+
+/// Silero 模型在 16kHz 下每次推理的样本数
+const SILERO_CHUNK_SAMPLES: usize = 512;
+/// 拼接在每块前的上一块末尾样本数
+const SILERO_CONTEXT_SAMPLES: usize = 64;
+/// 循环状态张量大小，形状为 [2, 1, 128]
+const SILERO_STATE_SIZE: usize = 2 * 128;
+/// 结束判定阈值比开始阈值低多少，避免在阈值附近反复开始结束
+const SILERO_THRESHOLD_MARGIN: f32 = 0.15;
+
+/// 创建检测器：启动时加载了 Silero 模型时使用 Silero，否则使用能量检测
+pub fn detector(
+    silero: Option<&Arc<SileroModel>>,
+    config: &Config,
+    options: &AudioOptions,
+) -> Box<dyn VoiceActivityDetector> {
+    match silero {
+        Some(model) => Box::new(SileroVad::new(model.clone(), config.silero_vad_threshold)),
+        None => Box::new(EnergyVad::new(options)),
+    }
+}
+
+/// 语音活动检测器，逐帧判断录音的开始与结束
+///
+/// 输入为 16kHz 单声道、[-1, 1] 范围的浮点样本，帧长由客户端决定。
+/// `AudioProcessor` 负责缓冲、背压和结果组装，检测器只做判断。
+pub trait VoiceActivityDetector: Send {
+    /// 处理一帧并给出判断
+    fn process(&mut self, frame: &[f32]) -> VadDecision;
+
+    /// 录音被外部结束（输入结束、缓冲已满等）时清空内部状态
+    fn reset(&mut self);
+}
+
+/// 检测器对一帧的判断
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadDecision {
+    /// 未在录音，忽略该帧
+    Idle,
+    /// 开始录音，该帧属于语音
+    Start,
+    /// 继续录音，`voiced` 表示该帧是否判为语音
+    Continue { voiced: bool },
+    /// 录音在该帧之后结束
+    End(EndReason),
+}
+
+/// 语音片段结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// 静音时长达到阈值
+    Silence,
+    /// 全局音频缓冲已满，提前结束
+    BufferFull,
+    /// 单句录音超过最大时长，提前结束
+    Overflow,
+    /// 输入结束
+    Flush,
+    /// 固定窗口模式凑满一个窗口
+    Window,
+    /// 能量长时间停留在结束阈值和持续语音阈值之间
+    Unsustained,
+}
+
+/// 固定时长窗口上的 RMS 能量
+///
+/// RMS 本身已按样本数平均，但帧越短受单个瞬态的影响越大：2.5ms 的帧里一声咔哒就能越过开始阈值，
+/// 60ms 的帧则会把它平均掉。按固定时长计算后，同一阈值对任何客户端帧长对应相同的响度。
+/// 窗口以时长配置、在构造时换算为样本数，输入采样率变化时阈值含义不变。
+#[derive(Debug)]
+struct EnergyWindow {
+    samples: VecDeque<f32>,
+    /// `0` 时直接使用当前帧
+    len: usize,
+}
+
+impl EnergyWindow {
+    fn new(len: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(len),
+            len,
+        }
+    }
+
+    /// 加入一帧并返回最近 `len` 个样本的能量，帧长不短于窗口时只用该帧
+    fn energy(&mut self, frame: &[f32]) -> f32 {
+        if frame.len() >= self.len {
+            if self.len > 0 {
+                self.samples.clear();
+                self.samples.extend(&frame[frame.len() - self.len..]);
+            }
+            return calculate_rms(frame);
+        }
+
+        let overflow = (self.samples.len() + frame.len()).saturating_sub(self.len);
+        self.samples.drain(..overflow);
+        self.samples.extend(frame);
+        calculate_rms(self.samples.make_contiguous())
+    }
+}
+
+/// 基于 RMS 能量的默认检测器：能量超过开始阈值开始录音，低于结束阈值持续 240ms 结束
+#[derive(Debug)]
+pub struct EnergyVad {
+    window: EnergyWindow,
+    is_recording: bool,
+    silence_samples: usize,
+    /// 距上次达到持续语音阈值经过的样本数
+    unsustained_samples: usize,
+    sustain_threshold: f32,
+    /// `0` 时不限制
+    max_unsustained_samples: usize,
+}
+
+impl EnergyVad {
+    pub fn new(options: &AudioOptions) -> Self {
+        Self {
+            window: EnergyWindow::new(options.energy_window_samples),
+            is_recording: false,
+            silence_samples: 0,
+            unsustained_samples: 0,
+            sustain_threshold: options.sustain_threshold,
+            max_unsustained_samples: options.max_unsustained_samples,
+        }
+    }
+}
+
+impl VoiceActivityDetector for EnergyVad {
+    fn process(&mut self, frame: &[f32]) -> VadDecision {
+        let energy = self.window.energy(frame);
+
+        if !self.is_recording {
+            if energy <= VAD_THRESHOLD_START {
+                return VadDecision::Idle;
+            }
+            self.is_recording = true;
+            return VadDecision::Start;
+        }
+
+        let voiced = energy >= VAD_THRESHOLD_END;
+        if voiced {
+            self.silence_samples = 0;
+        } else {
+            self.silence_samples += frame.len();
+        }
+        if self.silence_samples >= MAX_SILENCE_SAMPLES {
+            self.reset();
+            return VadDecision::End(EndReason::Silence);
+        }
+
+        if energy >= self.sustain_threshold {
+            self.unsustained_samples = 0;
+        } else {
+            self.unsustained_samples += frame.len();
+        }
+        if self.max_unsustained_samples > 0
+            && self.unsustained_samples >= self.max_unsustained_samples
+        {
+            self.reset();
+            return VadDecision::End(EndReason::Unsustained);
+        }
+
+        VadDecision::Continue { voiced }
+    }
+
+    fn reset(&mut self) {
+        self.is_recording = false;
+        self.silence_samples = 0;
+        self.unsustained_samples = 0;
+    }
+}
+
+/// 启动时加载一次的 Silero VAD 模型，各连接共享，循环状态由每个 `SileroVad` 自己保存
+///
+/// ort 推理需要会话的可变引用，一块 512 个样本的推理很快，各连接用互斥锁串行。
+pub struct SileroModel {
+    session: Mutex<Session>,
+}
+
+impl SileroModel {
+    /// 加载 Silero VAD 模型
+    pub fn load(model_path: &str) -> ort::Result<Self> {
+        let session = Session::builder()?.commit_from_file(model_path)?;
+        info!("Silero VAD 模型加载完成: {}", model_path);
+        Ok(Self {
+            session: Mutex::new(session),
+        })
+    }
+
+    /// 按配置加载模型，未配置、模型不存在或加载失败时返回 `None`，连接回退到能量检测
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        let model_path = config.silero_vad_model.as_ref()?;
+        if !Path::new(model_path).exists() {
+            warn!("Silero VAD 模型不存在: {}，回退到能量检测", model_path);
+            return None;
+        }
+
+        // 找不到 ONNX Runtime 动态库时 ort 会 panic 而不是返回错误
+        match panic::catch_unwind(AssertUnwindSafe(|| Self::load(model_path))) {
+            Ok(Ok(model)) => Some(Arc::new(model)),
+            Ok(Err(e)) => {
+                warn!("Silero VAD 模型加载失败: {}，回退到能量检测", e);
+                None
+            }
+            Err(_) => {
+                warn!("无法加载 ONNX Runtime，回退到能量检测");
+                None
+            }
+        }
+    }
+}
+
+/// 基于 Silero VAD ONNX 模型的检测器，噪声环境下比能量阈值稳定得多
+///
+/// 模型要求 16kHz 下每次输入 512 个样本，客户端帧长不固定，
+/// 因此输入先累积到整块再推理，帧的判断使用最近一块的语音概率。
+pub struct SileroVad {
+    model: Arc<SileroModel>,
+    threshold: f32,
+    /// 模型循环状态，跨块保留
+    state: Vec<f32>,
+    /// 上一块末尾的样本
+    context: Vec<f32>,
+    /// 尚未凑满一块的样本
+    pending: Vec<f32>,
+    probability: f32,
+    is_recording: bool,
+    silence_samples: usize,
+    /// 推理失败只记录一次日志
+    failed: bool,
+}
+
+impl SileroVad {
+    /// 使用共享的模型创建检测器，循环状态从零开始
+    pub fn new(model: Arc<SileroModel>, threshold: f32) -> Self {
+        Self {
+            model,
+            threshold,
+            state: vec![0.0; SILERO_STATE_SIZE],
+            context: vec![0.0; SILERO_CONTEXT_SAMPLES],
+            pending: Vec::with_capacity(SILERO_CHUNK_SAMPLES * 2),
+            probability: 0.0,
+            is_recording: false,
+            silence_samples: 0,
+            failed: false,
+        }
+    }
+
+    /// 对一块 512 个样本推理，返回语音概率并更新循环状态
+    fn infer(&mut self, chunk: &[f32]) -> ort::Result<f32> {
+        let mut input = Vec::with_capacity(SILERO_CONTEXT_SAMPLES + SILERO_CHUNK_SAMPLES);
+        input.extend_from_slice(&self.context);
+        input.extend_from_slice(chunk);
+
+        let mut session = self
+            .model
+            .session
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let outputs = session.run(ort::inputs![
+            "input" => Tensor::from_array(([1, input.len()], input))?,
+            "state" => Tensor::from_array(([2, 1, 128], self.state.clone()))?,
+            "sr" => Tensor::from_array(((), vec![SAMPLE_RATE as i64]))?,
+        ])?;
+
+        let (_, probability) = outputs["output"].try_extract_tensor::<f32>()?;
+        let (_, state) = outputs["stateN"].try_extract_tensor::<f32>()?;
+        let probability = probability.first().copied().unwrap_or(0.0);
+        self.state.copy_from_slice(state);
+        self.context
+            .copy_from_slice(&chunk[SILERO_CHUNK_SAMPLES - SILERO_CONTEXT_SAMPLES..]);
+        Ok(probability)
+    }
+
+    /// 累积样本并对凑满的块推理，更新最近的语音概率
+    fn update_probability(&mut self, frame: &[f32]) {
+        self.pending.extend_from_slice(frame);
+        while self.pending.len() >= SILERO_CHUNK_SAMPLES {
+            let chunk: Vec<f32> = self.pending.drain(..SILERO_CHUNK_SAMPLES).collect();
+            match self.infer(&chunk) {
+                Ok(probability) => self.probability = probability,
+                Err(e) => {
+                    if !self.failed {
+                        warn!("Silero VAD 推理失败: {}", e);
+                        self.failed = true;
+                    }
+                    self.probability = 0.0;
+                }
+            }
+        }
+    }
+}
+
+impl VoiceActivityDetector for SileroVad {
+    fn process(&mut self, frame: &[f32]) -> VadDecision {
+        self.update_probability(frame);
+
+        if !self.is_recording {
+            if self.probability < self.threshold {
+                return VadDecision::Idle;
+            }
+            self.is_recording = true;
+            self.silence_samples = 0;
+            return VadDecision::Start;
+        }
+
+        let voiced = self.probability >= self.threshold - SILERO_THRESHOLD_MARGIN;
+        if voiced {
+            self.silence_samples = 0;
+        } else {
+            self.silence_samples += frame.len();
+        }
+        if self.silence_samples >= MAX_SILENCE_SAMPLES {
+            self.is_recording = false;
+            self.silence_samples = 0;
+            return VadDecision::End(EndReason::Silence);
+        }
+
+        VadDecision::Continue { voiced }
+    }
+
+    /// 只结束当前录音，模型状态保留以便连续判断后续输入
+    fn reset(&mut self) {
+        self.is_recording = false;
+        self.silence_samples = 0;
+    }
+}
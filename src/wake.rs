@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// 唤醒词检测只识别语句开头的这段音频（2 秒），减少未唤醒时的计算量
+pub const DETECT_SAMPLES: usize = 16000 * 2;
+
+/// 唤醒词门控：未唤醒时语句只用于检测唤醒词，不做完整识别、分析或存储
+///
+/// 检测到唤醒词后在 `window` 内保持唤醒，每处理一句有效语音都会续期。
+#[derive(Debug, Clone)]
+pub struct WakeGate {
+    /// 规范化后的唤醒词，`None` 时不启用门控
+    phrase: Option<Vec<char>>,
+    window: Duration,
+    awake_until: Option<Instant>,
+}
+
+impl WakeGate {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            phrase: config
+                .wake_phrase
+                .as_deref()
+                .map(|phrase| normalized(phrase).map(|(_, c)| c).collect())
+                .filter(|phrase: &Vec<char>| !phrase.is_empty()),
+            window: config.wake_window,
+            awake_until: None,
+        }
+    }
+
+    /// 当前是否允许完整识别
+    pub fn is_open(&self) -> bool {
+        self.phrase.is_none() || self.awake_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// 检查文本是否包含唤醒词，包含时进入唤醒状态
+    pub fn try_wake(&mut self, text: &str) -> bool {
+        if self.phrase_end(text).is_none() {
+            return false;
+        }
+        self.keep_awake();
+        true
+    }
+
+    /// 延长唤醒状态
+    pub fn keep_awake(&mut self) {
+        if self.phrase.is_some() {
+            self.awake_until = Some(Instant::now() + self.window);
+        }
+    }
+
+    /// 去掉文本中唤醒词及其之前的部分，不含唤醒词时原样返回
+    pub fn strip<'a>(&self, text: &'a str) -> &'a str {
+        match self.phrase_end(text) {
+            Some(end) => text[end..].trim_start_matches(|c: char| !c.is_alphanumeric()),
+            None => text,
+        }
+    }
+
+    /// 唤醒词在原文中结束位置的字节偏移，比较时忽略大小写、空白和标点
+    fn phrase_end(&self, text: &str) -> Option<usize> {
+        let phrase = self.phrase.as_ref()?;
+        let chars: Vec<(usize, char)> = normalized(text).collect();
+        chars
+            .windows(phrase.len())
+            .find(|window| window.iter().map(|&(_, c)| c).eq(phrase.iter().copied()))
+            .map(|window| window[window.len() - 1].0)
+    }
+}
+
+/// 只保留字母数字并转为小写，附带每个字符在原文中结束位置的字节偏移
+fn normalized(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    text.char_indices()
+        .filter(|(_, c)| c.is_alphanumeric())
+        .flat_map(|(index, c)| {
+            let end = index + c.len_utf8();
+            c.to_lowercase().map(move |lower| (end, lower))
+        })
+}
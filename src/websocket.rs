@@ -1,21 +1,229 @@
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use chrono_tz::Tz;
+use futures::FutureExt;
+use serde::Serialize;
+use std::any::Any;
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tracing::{info, warn};
-
-use crate::audio::AudioProcessor;
-use crate::emotion::EmotionAnalyzer;
-use crate::protocol::{DeviceMessage, ServerResponse};
-use crate::speech::SpeechRecognizer;
-
-/// WebSocket连接处理器
-pub async fn handle_connection(
-    mut socket: WebSocket,
-    speech_recognizer: Arc<SpeechRecognizer>,
-    emotion_analyzer: Arc<EmotionAnalyzer>,
-) {
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval};
+use tracing::{error, info, warn};
+
+use crate::audio::{AudioProcessor, Utterance};
+use crate::coalesce::{Coalescer, Interim};
+use crate::emotion::{EmotionActions, EmotionLabels, EmotionSmoother};
+use crate::framing::{self, AudioCodec, Compression, Framing, Opcode};
+use crate::logging;
+use crate::metrics::METRICS;
+use crate::pipeline::{self, ConversationState, SegmentEmotion, SessionSettings, UtteranceHooks};
+use crate::protocol::{
+    self, AudioLevelReport, ControlAck, DeviceMessage, ErrorResponse, HelloAck, RecentResponse,
+    SegmentEmotionResponse, ServerNotice, ServerResponse,
+};
+use crate::recent::{RecentTranscript, RecentTranscripts};
+use crate::result::{self, SpeechResult};
+use crate::sink;
+use crate::speech::{self, Recognizer};
+use crate::state::AppState;
+
+/// 识别任务队列长度，排满后连接主循环等待，不再读取新音频
+const RECOGNITION_QUEUE: usize = 4;
+/// 上一句结束后等待可合并的下一句时，在停顿和短语句时长上限之外留出的静音判定和排队余量
+const COALESCE_FLUSH_GRACE: Duration = Duration::from_secs(1);
+
+/// 单个连接的会话状态
+struct Session {
+    audio_processor: AudioProcessor,
+    speech_recognizer: Arc<dyn Recognizer>,
+    settings: SessionSettings,
+    /// 协商后的协议版本，握手前按最低兼容版本处理
+    protocol_version: u32,
+    /// 握手后使用的消息编码
+    framing: Framing,
+    /// 协商压缩后，不小于该字节数的 JSON 结果压缩发送
+    compress_above: Option<usize>,
+    /// 音频消息首字节为编码标识，否则按裸 Opus 处理
+    codec_header: bool,
+    /// 已完成 `hello` 握手
+    handshaken: bool,
+    /// 已提示过握手前的音频被丢弃，每个连接只提示一次
+    pre_hello_notified: bool,
+    /// 切分出的语句交给识别任务
+    jobs: mpsc::Sender<RecognitionJob>,
+    /// 已交给识别任务、尚未被打断的语句的取消标志，仅开启 `BARGE_IN_CANCEL` 时记录
+    in_flight: Vec<Arc<AtomicBool>>,
+    /// 最近送达的识别结果，供 `get_recent` 查询
+    recent: RecentTranscripts,
+}
+
+/// 交给识别任务的一句语音，附带切分时的会话设置
+struct RecognitionJob {
+    utterance: Utterance,
+    recognizer: Arc<dyn Recognizer>,
+    settings: SessionSettings,
+    /// 用户再次开口时置位
+    cancel: Arc<AtomicBool>,
+}
+
+/// 识别任务跨语句保留的状态
+///
+/// 识别在独立任务中按顺序进行，推理本身在阻塞线程池中执行，不占用异步工作线程；
+/// 连接主循环因此在任意工作线程数下都能继续接收音频，在识别进行中发现新语句开始并打断旧的识别。
+struct Recognition {
+    /// 语音结果的情绪平滑
+    smoother: EmotionSmoother,
+    /// 对话上下文、去重和唤醒状态
+    conversation: ConversationState,
+    /// 已识别的语句数，作为下一句的序号
+    utterances: u64,
+    /// 开启 `UTTERANCE_COALESCE_MS` 时，可能与紧随其后的短语句合并的上一句
+    previous: Option<PreviousUtterance>,
+    /// 分段情绪由后台任务发回连接主循环
+    segment_emotions: mpsc::UnboundedSender<SegmentEmotion>,
+}
+
+/// 可能被合并重新识别的结果取代的上一句
+struct PreviousUtterance {
+    sequence: u64,
+    utterance: Utterance,
+    /// 识别文本已追加到情绪分析的上下文，被取代时需撤回
+    in_context: bool,
+    /// 尚未写入输出端的结果，确认不会被取代后再写入
+    unwritten: Option<SpeechResult>,
+    /// 超过该时间仍没有可合并的下一句时写入结果，不再参与合并
+    flush_at: Instant,
+}
+
+/// 新语句与上一句的合并判断
+enum Coalesced {
+    /// 与上一句合并后的音频
+    Merged(Utterance),
+    /// 不合并，原样识别
+    Separate(Utterance),
+}
+
+impl Interim for SegmentEmotion {
+    fn interim_kind(&self) -> Option<&'static str> {
+        Some("segment_emotion")
+    }
+}
+
+/// 升级为 WebSocket 连接
+///
+/// 开启 `REQUIRE_MODEL_READY` 且默认语言的模型最近一次加载失败时，升级后立即发送 `model_not_ready`
+/// 并以 1013 关闭；握手选择其他语言时由 `check_model_ready` 检查该语言的模型。
+pub fn upgrade(ws: WebSocketUpgrade, state: AppState) -> Response {
+    if state.config().require_model_ready && !state.recognizers.select(None).is_ready() {
+        warn!("默认识别模型未就绪，拒绝连接");
+        return ws.on_upgrade(|socket| {
+            reject(
+                socket,
+                ErrorResponse::model_not_ready(),
+                CloseReason::ModelNotReady,
+            )
+        });
+    }
+    ws.on_upgrade(move |socket| handle_connection(socket, state))
+}
+
+/// WebSocket连接处理器，单个连接内的 panic 被隔离，只关闭该连接
+pub async fn handle_connection(socket: WebSocket, state: AppState) {
+    let result = AssertUnwindSafe(run_connection(socket, state))
+        .catch_unwind()
+        .await;
+
+    if let Err(panic) = result {
+        error!(
+            "连接处理发生 panic，已关闭该连接: {}",
+            panic_message(&panic)
+        );
+    }
+}
+
+/// 服务器主动关闭连接的原因，对应 WebSocket 关闭码和原因字符串
+///
+/// 客户端据此区分预期的关闭和服务器崩溃：1001、1013 可以稍后重连，1002 需要升级客户端。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// 识别模型尚未就绪
+    ModelNotReady,
+    /// 来源 IP 连接过于频繁
+    RateLimited,
+    /// 客户端协议版本不兼容
+    UnsupportedProtocol,
+    /// 服务器内部错误，如识别任务异常退出
+    Internal,
+    /// 服务器正在退出
+    ShuttingDown,
+}
+
+impl CloseReason {
+    /// 1001 Going Away、1002 Protocol Error、1011 Internal Error、1013 Try Again Later
+    pub fn code(self) -> u16 {
+        match self {
+            Self::ShuttingDown => 1001,
+            Self::UnsupportedProtocol => 1002,
+            Self::Internal => 1011,
+            Self::ModelNotReady | Self::RateLimited => 1013,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::ModelNotReady => "model not ready",
+            Self::RateLimited => "rate limited",
+            Self::UnsupportedProtocol => "unsupported protocol version",
+            Self::Internal => "internal error",
+            Self::ShuttingDown => "server shutting down",
+        }
+    }
+}
+
+/// 发送关闭帧，之后不再收发消息
+pub async fn close(socket: &mut WebSocket, reason: CloseReason) {
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code: reason.code(),
+            reason: reason.reason().into(),
+        })))
+        .await;
+}
+
+/// 来源 IP 连接过于频繁时，告知设备原因后关闭连接
+pub async fn reject_rate_limited(socket: WebSocket) {
+    reject(
+        socket,
+        ErrorResponse::rate_limited(),
+        CloseReason::RateLimited,
+    )
+    .await;
+}
+
+/// 发送错误消息后关闭连接
+async fn reject(mut socket: WebSocket, error: ErrorResponse, reason: CloseReason) {
+    send_json(&mut socket, &error).await;
+    close(&mut socket, reason).await;
+}
+
+/// 提取 panic 信息
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("未知错误")
+}
+
+/// 连接主循环
+async fn run_connection(mut socket: WebSocket, state: AppState) {
     info!("新连接");
 
-    let mut audio_processor = match AudioProcessor::new() {
+    let config = state.config();
+    let audio_processor = match AudioProcessor::from_config(&config, state.silero_vad.as_ref()) {
         Ok(processor) => processor,
         Err(e) => {
             warn!("音频处理器初始化失败: {}", e);
@@ -23,98 +231,900 @@ pub async fn handle_connection(
         }
     };
 
-    send_initial_response(&mut socket).await;
-
-    while let Some(msg) = socket.recv().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                handle_text_message(&mut socket, &text).await;
-            }
-            Ok(Message::Binary(data)) => {
-                handle_audio_message(
-                    &mut socket,
-                    &mut audio_processor,
-                    &speech_recognizer,
-                    &emotion_analyzer,
-                    &data,
-                )
-                .await;
+    let (segment_emotions, segment_emotion_rx) = mpsc::unbounded_channel();
+    let mut segment_emotion_rx = Coalescer::new(segment_emotion_rx, config.coalesce_interim);
+    let (jobs, job_rx) = mpsc::channel(RECOGNITION_QUEUE);
+    let (result_tx, mut result_rx) = mpsc::unbounded_channel();
+    let recognition = Recognition {
+        smoother: EmotionSmoother::new(config.emotion_smoothing_window),
+        conversation: ConversationState::new(&config),
+        utterances: 0,
+        previous: None,
+        segment_emotions,
+    };
+    tokio::spawn(run_recognition(
+        state.clone(),
+        recognition,
+        job_rx,
+        result_tx,
+    ));
+
+    let mut session = Session {
+        audio_processor,
+        speech_recognizer: state.recognizers.select(None),
+        settings: SessionSettings::new(result::new_session_id()),
+        protocol_version: protocol::MIN_PROTOCOL_VERSION,
+        framing: Framing::Json,
+        compress_above: None,
+        codec_header: false,
+        handshaken: false,
+        pre_hello_notified: false,
+        jobs,
+        in_flight: Vec::new(),
+        recent: RecentTranscripts::new(config.recent_transcripts),
+    };
+
+    let greeting_timezone = config.time_greeting.then_some(config.timezone);
+    send_initial_response(
+        &mut socket,
+        greeting_timezone,
+        config.emotion_actions.as_ref(),
+        &config.emotion_labels,
+    )
+    .await;
+    session.audio_processor.mute_for(config.startup_mute);
+
+    let mut degraded = state.emotion_analyzer.subscribe_degraded();
+    let mut shutdown = state.shutdown.clone();
+    let mut level_ticker = config
+        .audio_level_interval
+        .map(|period| tokio::time::interval_at(Instant::now() + period, period));
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => {
+                let Some(msg) = msg else { break };
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        let flow =
+                            handle_text_message(&mut socket, &mut session, &state, &text).await;
+                        if let ControlFlow::Break(reason) = flow {
+                            close(&mut socket, reason).await;
+                            break;
+                        }
+                    }
+                    Ok(Message::Binary(data)) => {
+                        let flow =
+                            handle_binary_message(&mut socket, &mut session, &state, &data).await;
+                        if let ControlFlow::Break(reason) = flow {
+                            close(&mut socket, reason).await;
+                            break;
+                        }
+                    }
+                    Ok(Message::Close(_)) => break,
+                    _ => {}
+                }
+            }
+            response = result_rx.recv() => {
+                let Some(response) = response else {
+                    error!("识别任务异常退出，关闭连接");
+                    send_json(&mut socket, &ErrorResponse::internal()).await;
+                    close(&mut socket, CloseReason::Internal).await;
+                    break;
+                };
+                remember_recent(&mut session.recent, &state, &response);
+                // 没有监控端订阅时发送失败，忽略即可
+                let _ = state.monitor.send(response.clone());
+                send_response(&mut socket, session.framing, session.compress_above, &response).await;
+            }
+            Some(segment) = segment_emotion_rx.recv() => {
+                let labels = &state.config().emotion_labels;
+                send_json(&mut socket, &SegmentEmotionResponse::new(segment, labels)).await;
+            }
+            Ok(()) = degraded.changed() => {
+                let is_degraded = *degraded.borrow_and_update();
+                if protocol::supports_notices(session.protocol_version) {
+                    send_degraded_notice(&mut socket, is_degraded).await;
+                }
+            }
+            _ = next_tick(&mut level_ticker) => {
+                // v1 客户端不认识该消息；期间没有收到音频时不上报
+                let level = session.audio_processor.take_level();
+                if let Some(level) = level.filter(|_| protocol::supports_notices(session.protocol_version)) {
+                    send_json(&mut socket, &AudioLevelReport::new(level)).await;
+                }
+            }
+            Ok(()) = shutdown.changed() => {
+                if *shutdown.borrow_and_update() {
+                    info!("服务器退出，关闭连接");
+                    close(&mut socket, CloseReason::ShuttingDown).await;
+                    break;
+                }
             }
-            Ok(Message::Close(_)) => break,
-            _ => {}
         }
     }
 
     info!("连接断开");
 }
 
+/// 等待下一次电平上报，未开启时永远不就绪
+async fn next_tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// 记录送达的识别结果；仅情绪模式没有文本，低质量结果不代表用户说过的话，都不记录
+fn remember_recent(recent: &mut RecentTranscripts, state: &AppState, response: &ServerResponse) {
+    if let Some(replaced) = response.replaces {
+        recent.remove(replaced);
+    }
+    let Some(text) = response.text.clone().filter(|_| response.quality.is_none()) else {
+        return;
+    };
+    let entry = RecentTranscript {
+        utterance: response.utterance,
+        text,
+        emotion: response.emotion.clone(),
+        created_at: result::now_rfc3339(state.config().timezone),
+    };
+    recent.push(entry);
+}
+
 /// 发送初始连接响应
-async fn send_initial_response(socket: &mut WebSocket) {
-    let response = ServerResponse::initial_connection();
-    if let Ok(json) = serde_json::to_string(&response) {
+async fn send_initial_response(
+    socket: &mut WebSocket,
+    greeting_timezone: Option<Tz>,
+    actions: Option<&EmotionActions>,
+    labels: &EmotionLabels,
+) {
+    let response = ServerResponse::initial_connection(greeting_timezone)
+        .with_action(actions)
+        .with_label(labels);
+    send_json(socket, &response).await;
+}
+
+/// 发送情绪分析降级或恢复通知
+async fn send_degraded_notice(socket: &mut WebSocket, degraded: bool) {
+    let notice = if degraded {
+        ServerNotice::emotion_degraded()
+    } else {
+        ServerNotice::emotion_recovered()
+    };
+    send_json(socket, &notice).await;
+}
+
+/// 序列化并发送 JSON 文本帧
+async fn send_json<T: Serialize>(socket: &mut WebSocket, message: &T) {
+    if let Ok(json) = serde_json::to_string(message) {
         let _ = socket.send(Message::Text(json)).await;
     }
 }
 
-/// 处理文本消息
-async fn handle_text_message(socket: &mut WebSocket, text: &str) {
-    info!("收到文本帧: {}", text);
+/// 序列化 JSON，协商压缩且超过阈值时以 zstd 压缩的二进制消息发送
+async fn send_compressible<T: Serialize>(
+    socket: &mut WebSocket,
+    compress_above: Option<usize>,
+    message: &T,
+) {
+    let Ok(json) = serde_json::to_string(message) else {
+        return;
+    };
+    if compress_above.is_some_and(|min| json.len() >= min) {
+        match framing::compress(json.as_bytes()) {
+            Ok(compressed) => {
+                let _ = socket.send(Message::Binary(compressed)).await;
+                return;
+            }
+            Err(e) => warn!("压缩消息失败，改为发送原文: {}", e),
+        }
+    }
+    let _ = socket.send(Message::Text(json)).await;
+}
 
-    if text.contains("ping") {
-        let _ = socket.send(Message::Text("pong".to_string())).await;
+/// 按会话协商的编码发送识别或情绪结果
+///
+/// 二进制编码下文本和情绪分别编码为 `Transcript`、`Emotion` 帧，合并在一条消息中发送。
+async fn send_response(
+    socket: &mut WebSocket,
+    framing: Framing,
+    compress_above: Option<usize>,
+    response: &ServerResponse,
+) {
+    if framing == Framing::Json {
+        send_compressible(socket, compress_above, response).await;
         return;
     }
 
+    let mut frames = Vec::new();
+    if let Some(text) = &response.text {
+        frames.push((Opcode::Transcript, text.as_bytes()));
+    }
+    frames.push((Opcode::Emotion, response.emotion.as_bytes()));
+    send_frames(socket, &frames).await;
+}
+
+/// 编码并发送一条包含若干二进制帧的消息
+async fn send_frames(socket: &mut WebSocket, frames: &[(Opcode, &[u8])]) {
+    let mut message = Vec::new();
+    for &(opcode, payload) in frames {
+        match framing::encode(opcode, payload) {
+            Ok(frame) => message.extend(frame),
+            Err(e) => {
+                warn!("二进制帧编码失败: {}", e);
+                return;
+            }
+        }
+    }
+    let _ = socket.send(Message::Binary(message)).await;
+}
+
+/// 文本心跳：完整的 `ping` 消息；`legacy` 开启时沿用旧客户端的子串匹配
+///
+/// WebSocket 协议层的 Ping 帧由 axum 自动回复 Pong，不经过这里。
+fn is_heartbeat(text: &str, legacy: bool) -> bool {
+    text.trim() == "ping" || (legacy && text.contains("ping"))
+}
+
+/// 处理文本消息，返回 `Break` 时以其中的原因关闭连接
+async fn handle_text_message(
+    socket: &mut WebSocket,
+    session: &mut Session,
+    state: &AppState,
+    text: &str,
+) -> ControlFlow<CloseReason> {
+    let log_mode = state.config().log_transcripts;
+    info!(target: logging::SAMPLED, "收到文本帧: {}", log_mode.display(text));
+
+    if is_heartbeat(text, state.config().legacy_ping_match) {
+        let _ = socket.send(Message::Text("pong".to_string())).await;
+        return ControlFlow::Continue(());
+    }
+
     match serde_json::from_str::<DeviceMessage>(text) {
-        Ok(DeviceMessage::Hello { version }) => {
-            info!("APP握手: {}", version);
+        Ok(DeviceMessage::Hello {
+            version,
+            protocol_version,
+            locale,
+            initial_prompt,
+            emotion_only,
+            framing,
+            compression,
+            decoder_gain_db,
+            codec_header,
+        }) => {
+            info!(
+                "APP握手: {} (协议: v{}, 语言: {:?})",
+                version, protocol_version, locale
+            );
+            match protocol::negotiate_version(protocol_version) {
+                Ok(negotiated) => session.protocol_version = negotiated,
+                Err(notice) => {
+                    warn!("拒绝不兼容的协议版本: v{}", protocol_version);
+                    send_json(socket, &notice).await;
+                    return ControlFlow::Break(CloseReason::UnsupportedProtocol);
+                }
+            }
+            session.handshaken = true;
+            session.framing = framing;
+            session.codec_header = codec_header;
+            // 压缩结果需在握手确认中告知客户端，v1 客户端和二进制编码不压缩
+            let min_bytes = state.config().compress_min_bytes;
+            let compression = if compression == Compression::Zstd
+                && framing == Framing::Json
+                && min_bytes > 0
+                && protocol::supports_notices(session.protocol_version)
+            {
+                session.compress_above = Some(min_bytes);
+                Compression::Zstd
+            } else {
+                session.compress_above = None;
+                Compression::None
+            };
+            if let Some(gain_db) = decoder_gain_db {
+                match session.audio_processor.set_decoder_gain(gain_db) {
+                    Ok(applied) => info!("Opus 解码增益: {:.1}dB", applied),
+                    Err(e) => warn!("设置 Opus 解码增益失败: {}", e),
+                }
+            }
+            if framing == Framing::Binary {
+                let version = u8::try_from(session.protocol_version).unwrap_or(u8::MAX);
+                send_frames(socket, &[(Opcode::Hello, &[version])]).await;
+            }
+            // v1 客户端不认识握手确认和通知消息
+            if protocol::supports_notices(session.protocol_version) {
+                if framing == Framing::Json {
+                    send_json(
+                        socket,
+                        &HelloAck::new(session.protocol_version, compression),
+                    )
+                    .await;
+                }
+                if *state.emotion_analyzer.subscribe_degraded().borrow() {
+                    send_json(socket, &ServerNotice::emotion_degraded()).await;
+                }
+            }
+            session.speech_recognizer = state.recognizers.select(locale.as_deref());
+            if locale.is_some() {
+                check_model_ready(socket, session.speech_recognizer.as_ref(), state).await?;
+            }
+            session.settings.initial_prompt =
+                initial_prompt.as_deref().and_then(speech::sanitize_prompt);
+            session.settings.emotion_only = emotion_only;
+            info!(
+                "使用识别语言: {}, 提示词: {:?}, 仅情绪: {}",
+                session.speech_recognizer.language(),
+                session.settings.initial_prompt,
+                emotion_only
+            );
+            if state.config().control_acks {
+                send_json(socket, &ControlAck::new("hello")).await;
+            }
         }
         Ok(DeviceMessage::Event { key, value }) => {
             info!("APP事件: {} -> {}", key, value);
+            if state.config().control_acks {
+                send_json(socket, &ControlAck::new(&key)).await;
+            }
+        }
+        Ok(DeviceMessage::AnalyzeText { text, language }) => {
+            let emotion = state
+                .emotion_analyzer
+                .analyze(&text, language.as_deref())
+                .await;
+            info!(
+                target: logging::SAMPLED,
+                "📝 文本情绪: [{}] | 情绪: [{}]",
+                log_mode.display(&text),
+                emotion
+            );
+            send_response(
+                socket,
+                session.framing,
+                session.compress_above,
+                &ServerResponse::text_emotion(text, emotion)
+                    .with_action(state.config().emotion_actions.as_ref())
+                    .with_label(&state.config().emotion_labels),
+            )
+            .await;
+        }
+        Ok(DeviceMessage::GetRecent { n }) => {
+            let recent = RecentResponse::new(session.recent.last(n));
+            send_compressible(socket, session.compress_above, &recent).await;
         }
         Err(_) => {
-            info!("Raw Text: {}", text);
+            info!(target: logging::SAMPLED, "Raw Text: {}", log_mode.display(text));
         }
     }
+
+    ControlFlow::Continue(())
 }
 
-/// 处理音频消息
-async fn handle_audio_message(
+/// 开启 `REQUIRE_MODEL_READY` 且握手所选语言的模型最近一次加载失败时，发送 `model_not_ready` 并要求以 1013 关闭
+///
+/// 默认语言的模型已在升级时检查，这里只检查握手中指定了语言的会话。
+async fn check_model_ready(
     socket: &mut WebSocket,
-    audio_processor: &mut AudioProcessor,
-    speech_recognizer: &Arc<SpeechRecognizer>,
-    emotion_analyzer: &Arc<EmotionAnalyzer>,
+    recognizer: &dyn Recognizer,
+    state: &AppState,
+) -> ControlFlow<CloseReason> {
+    if !state.config().require_model_ready || recognizer.is_ready() {
+        return ControlFlow::Continue(());
+    }
+    warn!("识别模型 [{}] 未就绪，拒绝连接", recognizer.language());
+    send_json(socket, &ErrorResponse::model_not_ready()).await;
+    ControlFlow::Break(CloseReason::ModelNotReady)
+}
+
+/// 处理二进制消息：JSON 编码下为裸 Opus 帧，二进制编码下为长度前缀帧，返回 `Break` 时以其中的原因关闭连接
+async fn handle_binary_message(
+    socket: &mut WebSocket,
+    session: &mut Session,
+    state: &AppState,
+    data: &[u8],
+) -> ControlFlow<CloseReason> {
+    if state.config().strict_handshake && !session.handshaken {
+        if !session.pre_hello_notified {
+            warn!("握手前收到音频，已丢弃");
+            send_json(socket, &ErrorResponse::hello_required()).await;
+            session.pre_hello_notified = true;
+        }
+        return ControlFlow::Continue(());
+    }
+    if session.framing == Framing::Json {
+        if let Err(error) = handle_audio_message(session, state, data).await {
+            send_json(socket, &error).await;
+        }
+        return ControlFlow::Continue(());
+    }
+
+    let frames = match framing::decode(data) {
+        Ok(frames) => frames,
+        Err(e) => {
+            warn!("二进制帧解析失败: {}", e);
+            send_json(socket, &ErrorResponse::invalid_frame(e)).await;
+            return ControlFlow::Continue(());
+        }
+    };
+
+    for frame in frames {
+        match frame.opcode {
+            Opcode::Audio => {
+                if let Err(error) = handle_audio_message(session, state, frame.payload).await {
+                    send_json(socket, &error).await;
+                }
+            }
+            Opcode::Ping => send_frames(socket, &[(Opcode::Pong, &[])]).await,
+            other => {
+                warn!("客户端发送了不支持的操作码: {:?}", other);
+                send_json(socket, &ErrorResponse::unsupported_opcode(other)).await;
+            }
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/// 处理音频消息，协商了编码标识时按首字节选择解码方式
+///
+/// 开启 `BARGE_IN_CANCEL` 时，消息中开始了新的录音就打断此前尚未完成的识别。
+/// 消息无法处理时返回需要告知客户端的错误。
+async fn handle_audio_message(
+    session: &mut Session,
+    state: &AppState,
     audio_data: &[u8],
+) -> Result<(), ErrorResponse> {
+    let (codec, audio_data) = if session.codec_header {
+        match framing::split_codec(audio_data) {
+            Ok(split) => split,
+            Err(e) => {
+                warn!("音频消息编码标识无效: {}", e);
+                return Err(ErrorResponse::invalid_codec(e));
+            }
+        }
+    } else {
+        (AudioCodec::Opus, audio_data)
+    };
+
+    let recordings_before = session.audio_processor.recordings_started();
+    let utterances = match codec {
+        AudioCodec::Opus => session.audio_processor.process_audio(audio_data),
+        AudioCodec::Pcm16 => session.audio_processor.process_pcm_bytes(audio_data),
+    };
+    if session.audio_processor.recordings_started() != recordings_before {
+        for cancel in session.in_flight.drain(..) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    for utterance in utterances {
+        let cancel = Arc::new(AtomicBool::new(false));
+        if state.config().barge_in_cancel {
+            session.in_flight.push(cancel.clone());
+        }
+        let job = RecognitionJob {
+            utterance,
+            recognizer: session.speech_recognizer.clone(),
+            settings: session.settings.clone(),
+            cancel,
+        };
+        if session.jobs.send(job).await.is_err() {
+            warn!("识别任务已退出，丢弃语句");
+        }
+    }
+    Ok(())
+}
+
+/// 按顺序识别交来的语句，结果发回连接主循环；主循环退出后随之结束
+async fn run_recognition(
+    state: AppState,
+    mut recognition: Recognition,
+    mut jobs: mpsc::Receiver<RecognitionJob>,
+    results: mpsc::UnboundedSender<ServerResponse>,
 ) {
-    if let Some(complete_audio) = audio_processor.process_audio(audio_data) {
-        process_complete_speech(socket, speech_recognizer, emotion_analyzer, complete_audio).await;
+    loop {
+        let flush_at = recognition
+            .previous
+            .as_ref()
+            .map(|previous| previous.flush_at);
+        tokio::select! {
+            // 已排队的语句优先，避免在可合并的下一句到达前写入上一句
+            biased;
+            job = jobs.recv() => {
+                let Some(job) = job else { break };
+                if let Some(response) = process_complete_speech(&state, &mut recognition, job).await {
+                    if results.send(response).is_err() {
+                        break;
+                    }
+                }
+            }
+            _ = sleep_until(flush_at) => flush_previous(&state, &mut recognition).await,
+        }
+    }
+    flush_previous(&state, &mut recognition).await;
+}
+
+/// 等到 `deadline`，为 `None` 时永远不就绪
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 上一句不会再被合并取代，写入其尚未写入输出端的结果
+async fn flush_previous(state: &AppState, recognition: &mut Recognition) {
+    let unwritten = recognition
+        .previous
+        .take()
+        .and_then(|previous| previous.unwritten);
+    if let Some(result) = unwritten {
+        sink::dispatch(&state.sinks, &result).await;
     }
 }
 
 /// 处理完整的语音片段
 async fn process_complete_speech(
-    socket: &mut WebSocket,
-    speech_recognizer: &Arc<SpeechRecognizer>,
-    emotion_analyzer: &Arc<EmotionAnalyzer>,
-    audio_data: Vec<f32>,
-) {
-    let text = speech_recognizer.recognize(&audio_data);
-    let clean_text = text.trim();
+    state: &AppState,
+    recognition: &mut Recognition,
+    job: RecognitionJob,
+) -> Option<ServerResponse> {
+    if pipeline::is_stale(state, &job.utterance) {
+        return None;
+    }
+    let (superseded, utterance) =
+        match coalesce_with_previous(state, recognition.previous.as_ref(), job.utterance) {
+            Coalesced::Merged(merged) => {
+                let superseded = recognition.previous.take();
+                // 合并结果包含上一句的文本，上一句不再单独作为上下文
+                if superseded
+                    .as_ref()
+                    .is_some_and(|previous| previous.in_context)
+                {
+                    recognition.conversation.context.pop_last();
+                }
+                (superseded, merged)
+            }
+            Coalesced::Separate(utterance) => {
+                flush_previous(state, recognition).await;
+                (None, utterance)
+            }
+        };
+    let sequence = recognition.utterances;
+    recognition.utterances += 1;
+    let config = state.config();
+    let hooks = UtteranceHooks {
+        on_progress: None,
+        segment_emotions: Some(recognition.segment_emotions.clone()),
+        utterance: sequence,
+        cancel: Some(job.cancel.clone()),
+        defer_store: config.utterance_coalesce_window.is_some(),
+    };
+    let result = pipeline::process_utterance(
+        state,
+        &job.recognizer,
+        &job.settings,
+        &mut recognition.conversation,
+        &utterance,
+        hooks,
+    )
+    .await;
+    let Some(mut result) = result else {
+        // 合并后没有产生结果时，被取代的上一句照常写入
+        if let Some(result) = superseded.and_then(|previous| previous.unwritten) {
+            sink::dispatch(&state.sinks, &result).await;
+        }
+        return None;
+    };
+    if let Some(window) = config.utterance_coalesce_window {
+        let store = pipeline::should_store(result.rejection.as_deref(), &config);
+        recognition.previous = Some(PreviousUtterance {
+            sequence,
+            flush_at: Instant::from_std(utterance.finalized_at)
+                + window
+                + config.utterance_coalesce_max
+                + COALESCE_FLUSH_GRACE,
+            utterance,
+            in_context: result.rejection.is_none(),
+            unwritten: store.then(|| result.clone()),
+        });
+    }
 
-    if is_valid_speech(clean_text) {
-        let emotion = emotion_analyzer.analyze(clean_text).await;
-        info!("🗣️ 结果: [{}] | 情绪: [{}]", clean_text, emotion);
+    if result.rejection.is_none() {
+        result.emotion = recognition.smoother.update(result.emotion);
+    }
+    let mut response = ServerResponse::speech_result(result, !job.settings.emotion_only)
+        .with_action(config.emotion_actions.as_ref())
+        .with_label(&config.emotion_labels);
+    if config.segment_emotion || config.utterance_coalesce_window.is_some() {
+        response.utterance = Some(sequence);
+    }
+    response.replaces = superseded.map(|previous| previous.sequence);
+    Some(response)
+}
 
-        let response = ServerResponse::speech_result(clean_text.to_string(), emotion);
-        if let Ok(json) = serde_json::to_string(&response) {
-            let _ = socket.send(Message::Text(json)).await;
+/// 短语句与上一句之间停顿足够短时返回合并后的音频，否则原样返回
+fn coalesce_with_previous(
+    state: &AppState,
+    previous: Option<&PreviousUtterance>,
+    utterance: Utterance,
+) -> Coalesced {
+    let config = state.config();
+    let (Some(window), Some(previous)) = (config.utterance_coalesce_window, previous) else {
+        return Coalesced::Separate(utterance);
+    };
+    let max_ms = config.utterance_coalesce_max.as_millis() as u64;
+    let gap_ms = previous.utterance.gap_to(&utterance);
+    if utterance.duration_ms() > max_ms || gap_ms > window.as_millis() as u64 {
+        return Coalesced::Separate(utterance);
+    }
+    match previous.utterance.merged_with(&utterance) {
+        Some(merged) => {
+            METRICS
+                .utterances_coalesced_total
+                .fetch_add(1, Ordering::Relaxed);
+            Coalesced::Merged(merged)
         }
-    } else {
-        info!("(忽略无效语音)");
+        None => Coalesced::Separate(utterance),
     }
 }
 
-/// 验证语音识别结果是否有效
-fn is_valid_speech(text: &str) -> bool {
-    !text.is_empty() && text != "你去找我吧"
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{State, WebSocketUpgrade};
+    use axum::routing::get;
+    use axum::Router;
+    use futures::{SinkExt, StreamExt};
+    use std::sync::RwLock;
+    use std::time::Duration;
+    use tokio::sync::{broadcast, watch};
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    use crate::audio::VadMetadata;
+    use crate::config::Config;
+    use crate::emotion::EmotionAnalyzer;
+    use crate::history::SqliteStore;
+    use crate::reanalyze::ReanalyzeJob;
+    use crate::speech::{RecognizeCallbacks, RecognizerRegistry, Transcript};
+    use crate::stream::StreamSessions;
+    use crate::vad::EndReason;
+
+    /// 一识别就 panic 的识别器
+    struct PanickingRecognizer;
+
+    impl Recognizer for PanickingRecognizer {
+        fn model_name(&self) -> &str {
+            "panicking"
+        }
+
+        fn language(&self) -> &str {
+            "zh"
+        }
+
+        fn recognize_with_callbacks(
+            &self,
+            _audio_data: &[f32],
+            _initial_prompt: Option<&str>,
+            _callbacks: RecognizeCallbacks<'_>,
+        ) -> Transcript {
+            panic!("识别器崩溃");
+        }
+    }
+
+    fn test_state(recognizer: Arc<dyn Recognizer>) -> AppState {
+        let mut config = Config::from_env();
+        config.wake_phrase = None;
+        config.strict_handshake = false;
+        config.persist_history = false;
+        let (_, shutdown) = watch::channel(false);
+        AppState {
+            recognizers: Arc::new(RecognizerRegistry::single(recognizer)),
+            silero_vad: None,
+            emotion_analyzer: Arc::new(EmotionAnalyzer::from_config(&config)),
+            sinks: Arc::new(Vec::new()),
+            history: Arc::new(SqliteStore::open(":memory:")),
+            stream_sessions: Arc::new(StreamSessions::default()),
+            reanalyze_job: Arc::new(ReanalyzeJob::default()),
+            monitor: broadcast::channel(16).0,
+            connection_limiter: None,
+            shutdown,
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// 在本地端口上启动只有 WebSocket 路由的服务器，返回地址
+    async fn serve(state: AppState) -> String {
+        async fn handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+            upgrade(ws, state)
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(handler)).with_state(state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("ws://{}", addr)
+    }
+
+    /// 带 PCM 编码标识的音频消息：1 秒 440Hz 正弦波后接 1 秒静音
+    fn speech_then_silence() -> Vec<u8> {
+        let mut message = vec![AudioCodec::Pcm16 as u8];
+        for i in 0..32000 {
+            let sample = if i < 16000 {
+                let t = i as f32 / 16000.0;
+                ((t * 440.0 * std::f32::consts::TAU).sin() * 16000.0) as i16
+            } else {
+                0
+            };
+            message.extend(sample.to_le_bytes());
+        }
+        message
+    }
+
+    /// 模型最近一次加载失败的识别器
+    struct UnreadyRecognizer;
+
+    impl Recognizer for UnreadyRecognizer {
+        fn model_name(&self) -> &str {
+            "unready"
+        }
+
+        fn language(&self) -> &str {
+            "zh"
+        }
+
+        fn recognize_with_callbacks(
+            &self,
+            _audio_data: &[f32],
+            _initial_prompt: Option<&str>,
+            _callbacks: RecognizeCallbacks<'_>,
+        ) -> Transcript {
+            unreachable!("未就绪的识别器不应被调用");
+        }
+
+        fn is_ready(&self) -> bool {
+            false
+        }
+    }
+
+    /// 读取服务器发来的消息直到连接关闭，返回收到的错误码和关闭码
+    async fn read_until_close(url: String, messages: Vec<ClientMessage>) -> (Vec<String>, u16) {
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        for message in messages {
+            client.send(message).await.unwrap();
+        }
+
+        let mut errors = Vec::new();
+        let close = tokio::time::timeout(Duration::from_secs(10), async {
+            while let Some(message) = client.next().await {
+                match message.unwrap() {
+                    ClientMessage::Text(text) => {
+                        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+                        if value["type"] == "error" {
+                            errors.push(value["code"].as_str().unwrap_or_default().to_string());
+                        }
+                    }
+                    ClientMessage::Close(frame) => return frame,
+                    _ => {}
+                }
+            }
+            None
+        })
+        .await
+        .expect("连接没有被关闭")
+        .expect("关闭帧缺少关闭码");
+        (errors, u16::from(close.code))
+    }
+
+    #[tokio::test]
+    async fn recognizer_panic_closes_connection_with_internal_error() {
+        let url = serve(test_state(Arc::new(PanickingRecognizer))).await;
+        let hello = r#"{"type": "hello", "version": "test", "codec_header": true}"#;
+        let (errors, code) = read_until_close(
+            url,
+            vec![
+                ClientMessage::text(hello),
+                ClientMessage::binary(speech_then_silence()),
+            ],
+        )
+        .await;
+
+        assert!(
+            errors.iter().any(|code| code == "internal"),
+            "没有收到 internal 错误"
+        );
+        assert_eq!(code, CloseReason::Internal.code());
+    }
+
+    #[tokio::test]
+    async fn unready_model_rejects_connection_on_upgrade() {
+        let state = test_state(Arc::new(UnreadyRecognizer));
+        let mut config = (*state.config()).clone();
+        config.require_model_ready = true;
+        state.replace_config(config);
+        let url = serve(state).await;
+
+        let (errors, code) = read_until_close(url, Vec::new()).await;
+
+        assert_eq!(errors, ["model_not_ready"]);
+        assert_eq!(code, CloseReason::ModelNotReady.code());
+    }
+
+    /// 持续推理直到被中止，最长 5 秒
+    struct AbortableRecognizer;
+
+    impl Recognizer for AbortableRecognizer {
+        fn model_name(&self) -> &str {
+            "abortable"
+        }
+
+        fn language(&self) -> &str {
+            "zh"
+        }
+
+        fn recognize_with_callbacks(
+            &self,
+            _audio_data: &[f32],
+            _initial_prompt: Option<&str>,
+            callbacks: RecognizeCallbacks<'_>,
+        ) -> Transcript {
+            let started = std::time::Instant::now();
+            while started.elapsed() < Duration::from_secs(5) {
+                if callbacks
+                    .abort
+                    .is_some_and(|abort| abort.load(Ordering::Relaxed))
+                {
+                    return Transcript::default();
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            Transcript {
+                text: "没有被中止".to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recognition_is_cancelled_with_one_worker_thread() {
+        let recognizer: Arc<dyn Recognizer> = Arc::new(AbortableRecognizer);
+        let state = test_state(recognizer.clone());
+        let cancel = Arc::new(AtomicBool::new(false));
+        let barge_in = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            barge_in.store(true, Ordering::Relaxed);
+        });
+        let utterance = Utterance {
+            samples: vec![0.1; 16000],
+            pre_emphasis: None,
+            vad: VadMetadata {
+                voiced_frames: 50,
+                silent_frames: 0,
+                max_energy: 3000.0,
+                end: EndReason::Silence,
+            },
+            finalized_at: std::time::Instant::now(),
+            offset_ms: 0,
+        };
+
+        let started = std::time::Instant::now();
+        let result = pipeline::process_utterance(
+            &state,
+            &recognizer,
+            &SessionSettings::new("session".to_string()),
+            &mut ConversationState::new(&state.config()),
+            &utterance,
+            UtteranceHooks {
+                cancel: Some(cancel),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        assert!(result.is_none());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
 }
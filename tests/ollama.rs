@@ -0,0 +1,167 @@
+//! 情绪分析器对接模拟 Ollama 服务的测试
+
+use std::time::Duration;
+
+use heart_mirror_brain::config::Config;
+use heart_mirror_brain::emotion::{BreakerState, EmotionAnalyzer, EmotionError, OllamaApi};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// 指向模拟服务的分析器：单次请求超时 200ms，连续失败 2 次熔断
+fn analyzer(server: &MockServer, retries: u32) -> EmotionAnalyzer {
+    let mut config = Config::from_env();
+    config.ollama_host = server.uri();
+    config.ollama_api = OllamaApi::Generate;
+    config.ollama_retries = retries;
+    config.ollama_timeout = Duration::from_millis(200);
+    config.breaker_threshold = 2;
+    config.breaker_cooldown = Duration::from_secs(60);
+    config.emotion_single_flight = false;
+    EmotionAnalyzer::from_config(&config)
+}
+
+fn generate() -> wiremock::MockBuilder {
+    Mock::given(method("POST")).and(path("/api/generate"))
+}
+
+fn reply(response: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "response": response }))
+}
+
+#[tokio::test]
+async fn returns_emotion_from_response() {
+    let server = MockServer::start().await;
+    generate()
+        .respond_with(reply(" Joy\n"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let analyzer = analyzer(&server, 1);
+    let emotion = analyzer.try_analyze("今天真开心", Some("zh"), &[]).await;
+
+    assert_eq!(emotion.unwrap(), "joy");
+    assert_eq!(analyzer.breaker_status().state, BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn unexpected_output_falls_back_to_neutral() {
+    let server = MockServer::start().await;
+    generate()
+        .respond_with(reply("I am not sure"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let emotion = analyzer(&server, 1).try_analyze("嗯", None, &[]).await;
+
+    assert_eq!(emotion.unwrap(), "neutral");
+}
+
+#[tokio::test]
+async fn malformed_json_is_not_retried_and_does_not_trip_breaker() {
+    let server = MockServer::start().await;
+    generate()
+        .respond_with(ResponseTemplate::new(200).set_body_string("{\"response\": "))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let analyzer = analyzer(&server, 2);
+    for _ in 0..3 {
+        let result = analyzer.try_analyze("你好", None, &[]).await;
+        assert!(
+            matches!(result, Err(EmotionError::Parse(_))),
+            "{:?}",
+            result
+        );
+    }
+    assert_eq!(analyzer.breaker_status().state, BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn server_error_is_retried() {
+    let server = MockServer::start().await;
+    generate()
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&server)
+        .await;
+    generate()
+        .respond_with(reply("sadness"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let emotion = analyzer(&server, 2).try_analyze("好难过", None, &[]).await;
+
+    assert_eq!(emotion.unwrap(), "sadness");
+}
+
+#[tokio::test]
+async fn server_error_fails_after_retries() {
+    let server = MockServer::start().await;
+    generate()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let result = analyzer(&server, 2).try_analyze("你好", None, &[]).await;
+
+    assert!(
+        matches!(result, Err(EmotionError::Status(status)) if status.as_u16() == 500),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn slow_response_times_out() {
+    let server = MockServer::start().await;
+    generate()
+        .respond_with(reply("joy").set_delay(Duration::from_secs(2)))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let result = analyzer(&server, 0).try_analyze("你好", None, &[]).await;
+
+    assert!(
+        matches!(result, Err(EmotionError::Timeout(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn breaker_opens_after_consecutive_failures() {
+    let server = MockServer::start().await;
+    // 熔断后的请求不应到达 Ollama
+    generate()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let analyzer = analyzer(&server, 0);
+    for _ in 0..2 {
+        let result = analyzer.try_analyze("你好", None, &[]).await;
+        assert!(
+            matches!(result, Err(EmotionError::Status(_))),
+            "{:?}",
+            result
+        );
+    }
+    assert_eq!(analyzer.breaker_status().state, BreakerState::Open);
+
+    let result = analyzer.try_analyze("你好", None, &[]).await;
+    assert!(
+        matches!(result, Err(EmotionError::CircuitOpen)),
+        "{:?}",
+        result
+    );
+    assert_eq!(analyzer.analyze("你好", None).await, "neutral");
+}